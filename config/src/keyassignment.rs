@@ -97,6 +97,12 @@ pub enum Pattern {
     CaseSensitiveString(String),
     CaseInSensitiveString(String),
     Regex(String),
+    /// A case sensitive string that only matches when it is bounded
+    /// by non-word characters (or the start/end of the line)
+    WholeWord(String),
+    /// A regex in which `^` and `$` match at the start and end of
+    /// each line, rather than only at the start and end of the text
+    MultiLineRegex(String),
 }
 
 impl std::ops::Deref for Pattern {
@@ -106,6 +112,8 @@ impl std::ops::Deref for Pattern {
             Pattern::CaseSensitiveString(s) => s,
             Pattern::CaseInSensitiveString(s) => s,
             Pattern::Regex(s) => s,
+            Pattern::WholeWord(s) => s,
+            Pattern::MultiLineRegex(s) => s,
         }
     }
 }
@@ -116,6 +124,8 @@ impl std::ops::DerefMut for Pattern {
             Pattern::CaseSensitiveString(s) => s,
             Pattern::CaseInSensitiveString(s) => s,
             Pattern::Regex(s) => s,
+            Pattern::WholeWord(s) => s,
+            Pattern::MultiLineRegex(s) => s,
         }
     }
 }
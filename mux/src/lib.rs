@@ -1,5 +1,5 @@
 use crate::client::{ClientId, ClientInfo};
-use crate::pane::{Pane, PaneId};
+use crate::pane::{close_pane_output, forward_pane_output, Pane, PaneId};
 use crate::tab::{SplitDirection, Tab, TabId};
 use crate::window::{Window, WindowId};
 use anyhow::{anyhow, Context, Error};
@@ -33,12 +33,14 @@ pub mod connui;
 pub mod domain;
 pub mod localpane;
 pub mod pane;
+pub mod pendingpane;
 pub mod renderable;
 pub mod ssh;
 pub mod tab;
 pub mod termwiztermtab;
 pub mod tmux;
 pub mod tmux_commands;
+pub mod tmux_layout;
 mod tmux_pty;
 pub mod window;
 
@@ -82,10 +84,16 @@ pub struct Mux {
 const BUFSIZE: usize = 1024 * 1024;
 
 /// This function bounces parsed actions over to the main thread to feed to
-/// the pty in the mux.
+/// the pty in the mux, along with the number of bytes that they were
+/// parsed from.
 /// It blocks until the mux has finished consuming the data, which provides
 /// some back-pressure so that eg: ctrl-c can remain responsive.
-fn send_actions_to_mux(pane_id: PaneId, dead: &Arc<AtomicBool>, actions: Vec<Action>) {
+fn send_actions_to_mux(
+    pane_id: PaneId,
+    dead: &Arc<AtomicBool>,
+    actions: Vec<Action>,
+    bytes: usize,
+) {
     let start = Instant::now();
     promise::spawn::block_on(promise::spawn::spawn_into_main_thread({
         let dead = Arc::clone(&dead);
@@ -93,6 +101,7 @@ fn send_actions_to_mux(pane_id: PaneId, dead: &Arc<AtomicBool>, actions: Vec<Act
             let mux = Mux::get().unwrap();
             if let Some(pane) = mux.get_pane(pane_id) {
                 let start = Instant::now();
+                pane.record_output(bytes);
                 pane.perform_actions(actions);
                 histogram!(
                     "send_actions_to_mux.perform_actions.latency",
@@ -116,6 +125,8 @@ fn parse_buffered_data(pane_id: PaneId, dead: &Arc<AtomicBool>, mut rx: FileDesc
     let mut parser = termwiz::escape::parser::Parser::new();
     let mut actions = vec![];
     let mut hold = false;
+    // The number of bytes read since actions were last sent to the mux
+    let mut bytes = 0;
 
     loop {
         match rx.read(&mut buf) {
@@ -128,6 +139,7 @@ fn parse_buffered_data(pane_id: PaneId, dead: &Arc<AtomicBool>, mut rx: FileDesc
                 break;
             }
             Ok(size) => {
+                bytes += size;
                 parser.parse(&buf[0..size], |action| {
                     let mut flush = false;
                     match &action {
@@ -138,7 +150,12 @@ fn parse_buffered_data(pane_id: PaneId, dead: &Arc<AtomicBool>, mut rx: FileDesc
 
                             // Flush prior actions
                             if !actions.is_empty() {
-                                send_actions_to_mux(pane_id, dead, std::mem::take(&mut actions));
+                                send_actions_to_mux(
+                                    pane_id,
+                                    dead,
+                                    std::mem::take(&mut actions),
+                                    std::mem::take(&mut bytes),
+                                );
                             }
                         }
                         Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(
@@ -156,11 +173,21 @@ fn parse_buffered_data(pane_id: PaneId, dead: &Arc<AtomicBool>, mut rx: FileDesc
                     actions.push(action);
 
                     if flush && !actions.is_empty() {
-                        send_actions_to_mux(pane_id, dead, std::mem::take(&mut actions));
+                        send_actions_to_mux(
+                            pane_id,
+                            dead,
+                            std::mem::take(&mut actions),
+                            std::mem::take(&mut bytes),
+                        );
                     }
                 });
                 if !actions.is_empty() && !hold {
-                    send_actions_to_mux(pane_id, dead, std::mem::take(&mut actions));
+                    send_actions_to_mux(
+                        pane_id,
+                        dead,
+                        std::mem::take(&mut actions),
+                        std::mem::take(&mut bytes),
+                    );
                 }
 
                 buf.resize(configuration().mux_output_parser_buffer_size, 0);
@@ -223,6 +250,7 @@ fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<d
             }
             Ok(size) => {
                 histogram!("read_from_pane_pty.bytes.rate", size as f64);
+                forward_pane_output(pane_id, &buf[..size]);
                 if let Err(err) = tx.write_all(&buf[..size]) {
                     error!(
                         "read_pty failed to write to parser: pane {} {:?}",
@@ -233,6 +261,7 @@ fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<d
             }
         }
     }
+    close_pane_output(pane_id);
 
     match configuration().exit_behavior {
         ExitBehavior::Hold | ExitBehavior::CloseOnCleanExit => {
@@ -570,6 +599,8 @@ impl Mux {
         if let Some(pane) = self.panes.borrow_mut().remove(&pane_id).clone() {
             log::debug!("killing pane {}", pane_id);
             pane.kill();
+            // Stop any trickle paste from trying to reach the pane
+            pane.cancel_paste();
             self.recompute_pane_count();
             self.notify(MuxNotification::PaneRemoved(pane_id));
         }
@@ -655,7 +686,13 @@ impl Mux {
                 .tabs
                 .borrow()
                 .iter()
-                .filter_map(|(&id, tab)| if tab.is_dead() { Some(id) } else { None })
+                .filter_map(|(&id, tab)| {
+                    if tab.should_auto_close() {
+                        Some(id)
+                    } else {
+                        None
+                    }
+                })
                 .collect();
         }
 
@@ -1,5 +1,9 @@
 use crate::domain::DomainId;
-use crate::pane::{CloseReason, Pane, PaneId, Pattern, SearchResult};
+use crate::pane::{
+    find_pattern_matches, fire_output_triggers, output_trigger_text, tee_pane_output, CloseReason,
+    InputEvent, InputRecorder, NonBlockingReader, OutputRate, OutputTrigger, OutputTriggerCallback,
+    Pane, PaneCapabilities, PaneId, Pattern, SearchResult, ViewportState,
+};
 use crate::renderable::*;
 use crate::tmux::{TmuxDomain, TmuxDomainState};
 use crate::{Domain, Mux, MuxNotification};
@@ -15,6 +19,7 @@ use std::cell::{RefCell, RefMut};
 use std::collections::{HashMap, HashSet};
 use std::io::Result as IoResult;
 use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use termwiz::escape::DeviceControlMode;
@@ -56,6 +61,16 @@ pub struct LocalPane {
     domain_id: DomainId,
     tmux_domain: RefCell<Option<Arc<TmuxDomainState>>>,
     proc_list: RefCell<Option<CachedProcInfo>>,
+    output_triggers: RefCell<Vec<OutputTrigger>>,
+    last_activity: RefCell<Instant>,
+    output_rate: RefCell<OutputRate>,
+    pasted_bytes: RefCell<u64>,
+    input_recorder: InputRecorder,
+    viewport: ViewportState,
+    read_only: RefCell<bool>,
+    /// The copy of the pty output that is read by try_read; created
+    /// on the first call to try_read
+    nonblocking_reader: RefCell<Option<NonBlockingReader>>,
 }
 
 #[async_trait(?Send)]
@@ -88,6 +103,10 @@ impl Pane for LocalPane {
         terminal_get_dirty_lines(&mut self.terminal.borrow_mut(), lines, seqno)
     }
 
+    fn is_dirty(&self, seqno: SequenceNo) -> bool {
+        self.get_current_seqno() > seqno
+    }
+
     fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
         let (first, mut lines) = terminal_get_lines(&mut self.terminal.borrow_mut(), lines);
 
@@ -113,6 +132,27 @@ impl Pane for LocalPane {
         terminal_get_dimensions(&mut self.terminal.borrow_mut())
     }
 
+    fn scroll_offset(&self) -> StableRowIndex {
+        self.viewport.top(&self.get_dimensions())
+    }
+
+    fn set_viewport_top(&self, top: StableRowIndex) {
+        self.viewport.set_top(top, &self.get_dimensions());
+    }
+
+    fn set_read_only(&self, read_only: bool) {
+        *self.read_only.borrow_mut() = read_only;
+    }
+
+    fn is_read_only(&self) -> bool {
+        *self.read_only.borrow()
+    }
+
+    fn scrollback_memory_estimate(&self) -> usize {
+        let dims = self.get_dimensions();
+        dims.scrollback_rows * dims.cols * std::mem::size_of::<termwiz::cell::Cell>()
+    }
+
     fn copy_user_vars(&self) -> HashMap<String, String> {
         self.terminal.borrow().user_vars().clone()
     }
@@ -235,16 +275,58 @@ impl Pane for LocalPane {
     }
 
     fn perform_actions(&self, actions: Vec<termwiz::escape::Action>) {
-        self.terminal.borrow_mut().perform_actions(actions)
+        let text = if self.output_triggers.borrow().is_empty() {
+            None
+        } else {
+            Some(output_trigger_text(&actions))
+        };
+
+        self.terminal.borrow_mut().perform_actions(actions);
+        *self.last_activity.borrow_mut() = Instant::now();
+
+        if let Some(text) = text {
+            fire_output_triggers(&self.output_triggers, self.pane_id, &text);
+        }
+    }
+
+    fn last_activity(&self) -> Instant {
+        *self.last_activity.borrow()
+    }
+
+    fn record_output(&self, bytes: usize) {
+        self.output_rate.borrow_mut().record(bytes);
+    }
+
+    fn output_rate(&self) -> f64 {
+        self.output_rate.borrow().rate()
+    }
+
+    fn set_output_trigger(
+        &self,
+        pattern: Pattern,
+        callback: OutputTriggerCallback,
+    ) -> anyhow::Result<()> {
+        let trigger = OutputTrigger::new(pattern, callback)?;
+        self.output_triggers.borrow_mut().push(trigger);
+        Ok(())
     }
 
     fn mouse_event(&self, event: MouseEvent) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Ok(());
+        }
         Mux::get().unwrap().record_input_for_current_identity();
+        self.input_recorder.record(InputEvent::Mouse(event));
         self.terminal.borrow_mut().mouse_event(event)
     }
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Ok(());
+        }
         Mux::get().unwrap().record_input_for_current_identity();
+        self.input_recorder
+            .record(InputEvent::KeyDown { key, mods });
         if self.tmux_domain.borrow().is_some() {
             log::error!("key: {:?}", key);
             if key == KeyCode::Char('q') {
@@ -257,10 +339,22 @@ impl Pane for LocalPane {
     }
 
     fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Ok(());
+        }
         Mux::get().unwrap().record_input_for_current_identity();
+        self.input_recorder.record(InputEvent::KeyUp { key, mods });
         self.terminal.borrow_mut().key_up(key, mods)
     }
 
+    fn start_input_recording(&self) {
+        self.input_recorder.start();
+    }
+
+    fn stop_input_recording(&self) -> Vec<InputEvent> {
+        self.input_recorder.stop()
+    }
+
     fn resize(&self, size: PtySize) -> Result<(), Error> {
         self.pty.borrow_mut().resize(size)?;
         self.terminal.borrow_mut().resize(
@@ -281,15 +375,39 @@ impl Pane for LocalPane {
         Ok(Some(self.pty.borrow_mut().try_clone_reader()?))
     }
 
+    fn try_read(&self, buf: &mut [u8]) -> IoResult<usize> {
+        // The mux reader thread owns the pty output, so read a copy of
+        // it rather than competing with that thread for the data
+        self.nonblocking_reader
+            .borrow_mut()
+            .get_or_insert_with(|| tee_pane_output(self.pane_id))
+            .try_read(buf)
+    }
+
     fn send_paste(&self, text: &str) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Ok(());
+        }
         Mux::get().unwrap().record_input_for_current_identity();
+        self.input_recorder
+            .record(InputEvent::Paste(text.to_string()));
         if self.tmux_domain.borrow().is_some() {
             Ok(())
         } else {
-            self.terminal.borrow_mut().send_paste(text)
+            self.terminal.borrow_mut().send_paste(text)?;
+            *self.pasted_bytes.borrow_mut() += text.len() as u64;
+            Ok(())
         }
     }
 
+    fn total_pasted_bytes(&self) -> u64 {
+        *self.pasted_bytes.borrow()
+    }
+
+    fn capabilities(&self) -> PaneCapabilities {
+        PaneCapabilities::all()
+    }
+
     fn get_title(&self) -> String {
         let title = self.terminal.borrow_mut().get_title().to_string();
         // If the title is the default pane title, then try to spice
@@ -447,7 +565,16 @@ impl Pane for LocalPane {
         term.get_semantic_zones()
     }
 
-    async fn search(&self, mut pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+    async fn search(&self, pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+        self.search_cancellable(pattern, Arc::new(AtomicBool::new(false)))
+            .await
+    }
+
+    async fn search_cancellable(
+        &self,
+        mut pattern: Pattern,
+        cancel: Arc<AtomicBool>,
+    ) -> anyhow::Result<Vec<SearchResult>> {
         let term = self.terminal.borrow();
         let screen = term.screen();
 
@@ -487,70 +614,35 @@ impl Pane for LocalPane {
             if haystack.is_empty() {
                 return;
             }
-            match pattern {
-                // Rust only provides a case sensitive match_indices function, so
-                // we have to pre-arrange to lowercase both the pattern and the
-                // haystack strings
-                Pattern::CaseInSensitiveString(s) | Pattern::CaseSensitiveString(s) => {
-                    for (idx, s) in haystack.match_indices(s) {
-                        let match_id = match uniq_matches.get(s).copied() {
-                            Some(id) => id,
-                            None => {
-                                let id = uniq_matches.len();
-                                uniq_matches.insert(s.to_owned(), id);
-                                id
-                            }
-                        };
-                        let (start_x, start_y) = haystack_idx_to_coord(idx, coords);
-                        let (end_x, end_y) = haystack_idx_to_coord(idx + s.len(), coords);
-                        results.push(SearchResult {
-                            start_x,
-                            start_y,
-                            end_x,
-                            end_y,
-                            match_id,
-                        });
+            // Rust only provides a case sensitive match_indices function, so
+            // for CaseInSensitiveString we have pre-arranged to lowercase both
+            // the pattern and the haystack strings
+            for range in find_pattern_matches(pattern, haystack) {
+                let s = &haystack[range.clone()];
+                let match_id = match uniq_matches.get(s).copied() {
+                    Some(id) => id,
+                    None => {
+                        let id = uniq_matches.len();
+                        uniq_matches.insert(s.to_owned(), id);
+                        id
                     }
-                }
-                Pattern::Regex(r) => {
-                    if let Ok(re) = regex::Regex::new(r) {
-                        // Allow for the regex to contain captures
-                        for c in re.captures_iter(haystack) {
-                            // Look for the captures in reverse order, as index==0 is
-                            // the whole matched string.  We can't just call
-                            // `c.iter().rev()` as the capture iterator isn't double-ended.
-                            for idx in (0..c.len()).rev() {
-                                if let Some(m) = c.get(idx) {
-                                    let s = m.as_str();
-                                    let match_id = match uniq_matches.get(s).copied() {
-                                        Some(id) => id,
-                                        None => {
-                                            let id = uniq_matches.len();
-                                            uniq_matches.insert(s.to_owned(), id);
-                                            id
-                                        }
-                                    };
-
-                                    let (start_x, start_y) =
-                                        haystack_idx_to_coord(m.start(), coords);
-                                    let (end_x, end_y) = haystack_idx_to_coord(m.end(), coords);
-                                    results.push(SearchResult {
-                                        start_x,
-                                        start_y,
-                                        end_x,
-                                        end_y,
-                                        match_id,
-                                    });
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
+                };
+                let (start_x, start_y) = haystack_idx_to_coord(range.start, coords);
+                let (end_x, end_y) = haystack_idx_to_coord(range.end, coords);
+                results.push(SearchResult {
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    match_id,
+                });
             }
         }
 
         for (idx, line) in screen.lines.iter().enumerate() {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(results);
+            }
             let stable_row = screen.phys_to_stable_row_index(idx);
 
             let mut wrapped = false;
@@ -572,7 +664,7 @@ impl Pane for LocalPane {
             }
 
             if !wrapped {
-                if let Pattern::Regex(_) = &pattern {
+                if let Pattern::Regex(_) | Pattern::MultiLineRegex(_) = &pattern {
                     if let Some(coord) = coords.last().copied() {
                         coords.push(Coord {
                             byte_idx: haystack.len(),
@@ -753,6 +845,14 @@ impl LocalPane {
             domain_id,
             tmux_domain: RefCell::new(None),
             proc_list: RefCell::new(None),
+            output_triggers: RefCell::new(vec![]),
+            last_activity: RefCell::new(Instant::now()),
+            output_rate: RefCell::new(OutputRate::default()),
+            pasted_bytes: RefCell::new(0),
+            input_recorder: InputRecorder::default(),
+            viewport: ViewportState::default(),
+            read_only: RefCell::new(false),
+            nonblocking_reader: RefCell::new(None),
         }
     }
 
@@ -844,3 +944,249 @@ impl Drop for LocalPane {
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::pane::alloc_pane_id;
+    use crate::tab::Tab;
+    use portable_pty::{native_pty_system, CommandBuilder, PtySystem};
+    use std::io::{Read, Write};
+    use std::rc::Rc;
+
+    fn test_size() -> PtySize {
+        PtySize {
+            rows: 10,
+            cols: 20,
+            pixel_width: 200,
+            pixel_height: 250,
+        }
+    }
+
+    /// Returns a pane running cat, that has been sent count numbered
+    /// lines of output
+    fn pane_with_output(size: PtySize, count: usize) -> LocalPane {
+        let pair = native_pty_system().openpty(size).unwrap();
+        let child = pair
+            .slave
+            .spawn_command(CommandBuilder::new("cat"))
+            .unwrap();
+        let writer = pair.master.try_clone_writer().unwrap();
+        let terminal = Terminal::new(
+            crate::pty_size_to_terminal_size(size),
+            Arc::new(config::TermConfig::new()),
+            "WezTerm",
+            config::wezterm_version(),
+            Box::new(writer),
+        );
+        let pane = LocalPane::new(alloc_pane_id(), terminal, child, pair.master, 0);
+        output_lines(&pane, count);
+        pane
+    }
+
+    fn output_lines(pane: &LocalPane, count: usize) {
+        let output: String = (0..count).map(|i| format!("line {}\r\n", i)).collect();
+        pane.terminal.borrow_mut().advance_bytes(output);
+    }
+
+    #[test]
+    fn scroll_viewport() {
+        // Queues, rather than runs, the notification sent when the
+        // child is killed as the pane is dropped
+        let _executor = promise::spawn::SimpleExecutor::new();
+
+        let pane = pane_with_output(test_size(), 50);
+        assert_eq!(pane.get_dimensions().physical_top, 41);
+        assert_eq!(pane.scroll_offset(), 41);
+
+        pane.scroll_by(-5);
+        assert_eq!(pane.scroll_offset(), 36);
+
+        // The view stays put as more output arrives
+        output_lines(&pane, 50);
+        assert_eq!(pane.get_dimensions().physical_top, 91);
+        assert_eq!(pane.scroll_offset(), 36);
+
+        pane.scroll_to(-100);
+        assert_eq!(pane.scroll_offset(), pane.get_dimensions().scrollback_top);
+
+        // Scrolling to the bottom resumes following the output
+        pane.scroll_to(1000);
+        assert_eq!(pane.scroll_offset(), 91);
+        output_lines(&pane, 50);
+        assert_eq!(pane.scroll_offset(), 141);
+    }
+
+    #[test]
+    fn restore_scroll_position() {
+        let _executor = promise::spawn::SimpleExecutor::new();
+
+        let pane: Rc<dyn Pane> = Rc::new(pane_with_output(test_size(), 50));
+        let tab = Tab::new(&test_size());
+        tab.assign_pane(&pane);
+
+        pane.scroll_by(-10);
+        let layout = tab.capture_layout();
+        assert_eq!(layout.scroll_offsets, vec![(pane.pane_id(), 31)]);
+
+        pane.scroll_to(1000);
+        assert_eq!(pane.scroll_offset(), 41);
+        tab.restore_layout(&layout);
+        assert_eq!(pane.scroll_offset(), 31);
+    }
+
+    #[test]
+    fn viewport_range() {
+        let _executor = promise::spawn::SimpleExecutor::new();
+
+        let pane: Rc<dyn Pane> = Rc::new(pane_with_output(test_size(), 50));
+        let tab = Tab::new(&test_size());
+        tab.assign_pane(&pane);
+        assert_eq!(tab.active_pane_viewport(), Some(41..51));
+
+        tab.scroll_active_pane(-5);
+        assert_eq!(tab.active_pane_viewport(), Some(36..46));
+        let (_, lines) = pane.get_lines(pane.viewport_range());
+        assert_eq!(lines[0].as_str().trim_end(), "line 36");
+    }
+
+    #[test]
+    fn resize_preserves_scroll_position() {
+        let _executor = promise::spawn::SimpleExecutor::new();
+
+        let pane: Rc<dyn Pane> = Rc::new(pane_with_output(test_size(), 50));
+        let tab = Tab::new(&test_size());
+        tab.assign_pane(&pane);
+        pane.scroll_by(-10);
+        assert_eq!(pane.scroll_offset(), 31);
+
+        // Growing the tab brings more of the scrollback on screen
+        tab.resize(PtySize {
+            rows: 15,
+            cols: 30,
+            pixel_width: 300,
+            pixel_height: 375,
+        });
+        assert_eq!(pane.get_dimensions().viewport_rows, 15);
+        assert_eq!(pane.get_dimensions().physical_top, 36);
+        assert_eq!(pane.scroll_offset(), 31);
+
+        tab.resize(test_size());
+        assert_eq!(pane.scroll_offset(), 31);
+    }
+
+    #[test]
+    fn try_read() {
+        let _executor = promise::spawn::SimpleExecutor::new();
+
+        let pane = pane_with_output(test_size(), 0);
+        let mut buf = [0u8; 64];
+        let err = pane.try_read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+        // Stand in for the mux reader thread, which is the only reader
+        // of the pty and passes a copy of the output to try_read
+        let pane_id = pane.pane_id();
+        let mut reader = pane.reader().unwrap().unwrap();
+        let reader_thread = std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while let Ok(size) = reader.read(&mut buf) {
+                if size == 0 {
+                    break;
+                }
+                crate::pane::forward_pane_output(pane_id, &buf[..size]);
+            }
+            crate::pane::close_pane_output(pane_id);
+        });
+
+        // Reads until the output satisfies done, or EOF
+        let read_until = |done: &dyn Fn(&[u8]) -> bool| -> (Vec<u8>, bool) {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let mut output = vec![];
+            let mut buf = [0u8; 64];
+            while !done(&output) {
+                assert!(Instant::now() < deadline, "timed out reading pty");
+                match pane.try_read(&mut buf) {
+                    Ok(0) => return (output, true),
+                    Ok(size) => output.extend_from_slice(&buf[..size]),
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(1))
+                    }
+                    Err(err) => panic!("{}", err),
+                }
+            }
+            (output, false)
+        };
+
+        pane.pty.borrow_mut().write_all(b"hello\n").unwrap();
+        let (output, eof) =
+            read_until(&|output: &[u8]| String::from_utf8_lossy(output).contains("hello"));
+        assert!(!eof);
+        assert!(String::from_utf8_lossy(&output).contains("hello"));
+
+        // Once cat exits, the reader thread stops and try_read reports EOF
+        pane.kill();
+        let (_, eof) = read_until(&|_: &[u8]| false);
+        assert!(eof);
+        reader_thread.join().unwrap();
+    }
+
+    #[test]
+    fn output_trigger() {
+        let _executor = promise::spawn::SimpleExecutor::new();
+
+        let pane = pane_with_output(test_size(), 0);
+        let fired = Rc::new(std::cell::Cell::new(0));
+        pane.set_output_trigger(
+            Pattern::CaseSensitiveString("build finished".to_string()),
+            Box::new({
+                let fired = Rc::clone(&fired);
+                let pane_id = pane.pane_id();
+                move |id| {
+                    assert_eq!(id, pane_id);
+                    fired.set(fired.get() + 1);
+                }
+            }),
+        )
+        .unwrap();
+
+        // Stand in for the mux, which parses the pty output and passes
+        // the resulting actions to the pane
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut reader = pane.reader().unwrap().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while let Ok(size) = reader.read(&mut buf) {
+                if size == 0 || tx.send(buf[..size].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut parser = termwiz::escape::parser::Parser::new();
+        let mut output = String::new();
+        // Writes bytes to cat, then processes the output until done
+        // returns true for it
+        let mut write_and_process = |bytes: &[u8], done: &dyn Fn(&str) -> bool| {
+            pane.pty.borrow_mut().write_all(bytes).unwrap();
+            while !done(&output) {
+                let data = rx
+                    .recv_timeout(Duration::from_secs(10))
+                    .expect("timed out reading pty");
+                output.push_str(&String::from_utf8_lossy(&data));
+                pane.perform_actions(parser.parse_as_vec(&data));
+            }
+        };
+        let count = |output: &str| output.matches("build finished").count();
+
+        // The terminal echoes the partial line straight away, but cat
+        // only prints the line once it is complete
+        write_and_process(b"build fin", &|output: &str| output.contains("build fin"));
+        assert_eq!(fired.get(), 0);
+        // The echo completes the pattern, which spans both reads
+        write_and_process(b"ished\n", &|output: &str| count(output) >= 2);
+        assert_eq!(fired.get(), 1);
+
+        write_and_process(b"build finished\n", &|output: &str| count(output) >= 4);
+        assert_eq!(fired.get(), 1);
+    }
+}
@@ -7,10 +7,15 @@ use downcast_rs::{impl_downcast, Downcast};
 use portable_pty::PtySize;
 use rangeset::RangeSet;
 use serde::{Deserialize, Serialize};
-use std::cell::RefMut;
+use std::cell::{RefCell, RefMut};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use termwiz::escape::{Action, ControlCode};
 use termwiz::hyperlink::Rule;
 use termwiz::input::KeyboardEncoding;
 use termwiz::surface::{Line, SequenceNo, SEQ_ZERO};
@@ -22,6 +27,17 @@ use wezterm_term::{
 };
 
 static PANE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+lazy_static::lazy_static! {
+    /// Reported as the last_activity of panes that don't track activity
+    static ref NO_ACTIVITY: Instant = Instant::now();
+    /// The cancellation flag shared by the in-flight trickle pastes
+    /// of each pane
+    static ref PASTE_CANCEL: Mutex<HashMap<PaneId, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+    /// The channels that receive a copy of the pty output of each pane;
+    /// see tee_pane_output
+    static ref OUTPUT_TEES: Mutex<HashMap<PaneId, OutputSender>> = Mutex::new(HashMap::new());
+}
+type OutputSender = flume::Sender<std::io::Result<Vec<u8>>>;
 pub type PaneId = usize;
 
 pub fn alloc_pane_id() -> PaneId {
@@ -41,8 +57,462 @@ pub struct SearchResult {
     pub match_id: usize,
 }
 
+/// A SearchResult together with its text, captured at the time of the
+/// search so that it remains accurate even if the scrollback moves
+/// before the result is displayed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResultWithContext {
+    pub result: SearchResult,
+    /// The text of the line on which the match starts, without
+    /// trailing whitespace
+    pub line_text: String,
+    /// The text of the matched cells.  The rows of a match that
+    /// spans several lines are separated by newlines.
+    pub matched_text: String,
+}
+
+impl SearchResultWithContext {
+    /// Extracts the text for result from lines, the first of which is
+    /// the stable row first.  Rows that are not present in lines
+    /// contribute no text.
+    pub fn from_lines(result: SearchResult, first: StableRowIndex, lines: &[Line]) -> Self {
+        let mut line_text = String::new();
+        let mut matched = vec![];
+        for (idx, line) in lines.iter().enumerate() {
+            let y = first + idx as StableRowIndex;
+            if y < result.start_y || y > result.end_y {
+                continue;
+            }
+            if y == result.start_y {
+                line_text = line.as_str().trim_end().to_string();
+            }
+            let start = if y == result.start_y {
+                result.start_x
+            } else {
+                0
+            };
+            let end = if y == result.end_y {
+                result.end_x
+            } else {
+                usize::MAX
+            };
+            matched.push(line.columns_as_str(start..end));
+        }
+        Self {
+            result,
+            line_text,
+            matched_text: matched.join("\n"),
+        }
+    }
+}
+
 pub use config::keyassignment::Pattern;
 
+/// Compiles the regex for the regex based patterns.
+/// Returns Ok(None) for the string based patterns.
+pub fn pattern_regex(pattern: &Pattern) -> anyhow::Result<Option<regex::Regex>> {
+    Ok(match pattern {
+        Pattern::Regex(r) => Some(regex::Regex::new(r)?),
+        // Let ^ and $ anchor at each line of the haystack
+        Pattern::MultiLineRegex(r) => Some(regex::Regex::new(&format!("(?m){}", r))?),
+        Pattern::CaseSensitiveString(_)
+        | Pattern::CaseInSensitiveString(_)
+        | Pattern::WholeWord(_) => None,
+    })
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Returns true if the text in range is bounded by non-word characters,
+/// or the start/end of haystack
+fn is_whole_word(haystack: &str, range: &Range<usize>) -> bool {
+    let before = haystack[..range.start].chars().next_back();
+    let after = haystack[range.end..].chars().next();
+    !before.map(is_word_char).unwrap_or(false) && !after.map(is_word_char).unwrap_or(false)
+}
+
+/// Returns the byte ranges of the matches for pattern in haystack.
+/// CaseInSensitiveString is matched literally; the caller is expected
+/// to have lowercased both the pattern and the haystack.
+/// For the regex patterns, the last capture group that participated in
+/// each match is reported in place of the whole match, so that the
+/// pattern can select a portion of the text that it matches.
+/// An invalid regex has no matches.
+pub fn find_pattern_matches(pattern: &Pattern, haystack: &str) -> Vec<Range<usize>> {
+    match pattern {
+        Pattern::CaseSensitiveString(s) | Pattern::CaseInSensitiveString(s) => haystack
+            .match_indices(s.as_str())
+            .map(|(idx, m)| idx..idx + m.len())
+            .collect(),
+        Pattern::WholeWord(s) => haystack
+            .match_indices(s.as_str())
+            .map(|(idx, m)| idx..idx + m.len())
+            .filter(|range| is_whole_word(haystack, range))
+            .collect(),
+        Pattern::Regex(_) | Pattern::MultiLineRegex(_) => match pattern_regex(pattern) {
+            Ok(Some(re)) => re
+                .captures_iter(haystack)
+                .filter_map(|c| {
+                    // Look for the captures in reverse order, as index==0 is
+                    // the whole matched string.  We can't just call
+                    // `c.iter().rev()` as the capture iterator isn't double-ended.
+                    (0..c.len())
+                        .rev()
+                        .find_map(|idx| c.get(idx))
+                        .map(|m| m.start()..m.end())
+                })
+                .collect(),
+            _ => vec![],
+        },
+    }
+}
+
+/// A run of cells on a single row, in coordinates that are relative to
+/// the top left of the visible area of a pane (or, once offset via
+/// Tab::changed_cells_in_tab, of the containing tab)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellRange {
+    pub y: usize,
+    pub x: Range<usize>,
+}
+
+/// Called with the id of the pane whose output matched an OutputTrigger
+pub type OutputTriggerCallback = Box<dyn FnOnce(PaneId)>;
+
+/// The number of bytes of recent output that an OutputTrigger keeps,
+/// so that patterns that span several reads from the pty are matched
+const OUTPUT_TRIGGER_TAIL: usize = 4096;
+
+/// A one-shot trigger that fires when its pattern is seen in the
+/// output processed by a pane; useful for eg: generating a notification
+/// when a long running build completes.
+pub struct OutputTrigger {
+    pattern: Pattern,
+    regex: Option<regex::Regex>,
+    callback: OutputTriggerCallback,
+    /// The recent output, since the trigger was created
+    tail: String,
+}
+
+impl OutputTrigger {
+    pub fn new(pattern: Pattern, callback: OutputTriggerCallback) -> anyhow::Result<Self> {
+        let regex = pattern_regex(&pattern)?;
+        Ok(Self {
+            pattern,
+            regex,
+            callback,
+            tail: String::new(),
+        })
+    }
+
+    /// Appends newly processed output to the tail, and returns true if
+    /// the pattern matches the tail
+    fn observe(&mut self, text: &str) -> bool {
+        self.tail.push_str(text);
+        if self.matches(&self.tail) {
+            return true;
+        }
+        if self.tail.len() > OUTPUT_TRIGGER_TAIL {
+            let mut start = self.tail.len() - OUTPUT_TRIGGER_TAIL;
+            while !self.tail.is_char_boundary(start) {
+                start += 1;
+            }
+            // Prefer to start on a line boundary, so that anchored
+            // patterns don't match a partial line
+            if let Some(newline) = self.tail[start..].find('\n') {
+                start += newline + 1;
+            }
+            self.tail.drain(..start);
+        }
+        false
+    }
+
+    /// Returns true if the pattern matches the supplied output text
+    pub fn matches(&self, text: &str) -> bool {
+        match &self.pattern {
+            Pattern::CaseSensitiveString(s) => text.contains(s.as_str()),
+            Pattern::CaseInSensitiveString(s) => text.to_lowercase().contains(&s.to_lowercase()),
+            Pattern::WholeWord(_) => !find_pattern_matches(&self.pattern, text).is_empty(),
+            Pattern::Regex(_) | Pattern::MultiLineRegex(_) => self
+                .regex
+                .as_ref()
+                .map(|re| re.is_match(text))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Returns the text printed by actions, for matching against output
+/// triggers.  Line feeds and tabs are kept, while other control codes
+/// and escape sequences are ignored.
+pub fn output_trigger_text(actions: &[Action]) -> String {
+    let mut text = String::new();
+    for action in actions {
+        match action {
+            Action::Print(c) => text.push(*c),
+            Action::Control(ControlCode::LineFeed) => text.push('\n'),
+            Action::Control(ControlCode::HorizontalTab) => text.push('\t'),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Removes the triggers that match the newly processed output `text`
+/// and invokes their callbacks.  Each trigger matches against the
+/// recent output that it has seen, including `text`, so a pattern is
+/// matched even when it is split across several calls.
+/// The callbacks are invoked after `triggers` has been released, so
+/// they are free to register new triggers.
+pub fn fire_output_triggers(triggers: &RefCell<Vec<OutputTrigger>>, pane_id: PaneId, text: &str) {
+    let fired = {
+        let mut triggers = triggers.borrow_mut();
+        let mut fired = vec![];
+        let mut idx = 0;
+        while idx < triggers.len() {
+            if triggers[idx].observe(text) {
+                fired.push(triggers.remove(idx));
+            } else {
+                idx += 1;
+            }
+        }
+        fired
+    };
+    for trigger in fired {
+        (trigger.callback)(pane_id);
+    }
+}
+
+/// The time over which the rate tracked by OutputRate halves once
+/// the output stops
+const OUTPUT_RATE_HALF_LIFE: Duration = Duration::from_secs(1);
+
+/// Tracks an exponentially weighted moving average of the rate at
+/// which a pane produces output, in bytes per second; for example,
+/// so that the GUI can show a busy indicator for the panes that are
+/// actively outputting.
+pub struct OutputRate {
+    /// The decaying total of the bytes seen, as of updated
+    level: f64,
+    updated: Instant,
+}
+
+impl Default for OutputRate {
+    fn default() -> Self {
+        Self {
+            level: 0.,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl OutputRate {
+    pub fn record(&mut self, bytes: usize) {
+        self.record_at(bytes, Instant::now());
+    }
+
+    pub fn record_at(&mut self, bytes: usize, now: Instant) {
+        self.level = self.level_at(now) + bytes as f64;
+        self.updated = self.updated.max(now);
+    }
+
+    /// Returns the rate in bytes per second
+    pub fn rate(&self) -> f64 {
+        self.rate_at(Instant::now())
+    }
+
+    pub fn rate_at(&self, now: Instant) -> f64 {
+        // For a steady rate r, the level settles at r * half_life / ln(2)
+        self.level_at(now) * std::f64::consts::LN_2 / OUTPUT_RATE_HALF_LIFE.as_secs_f64()
+    }
+
+    fn level_at(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.level * 0.5f64.powf(elapsed.as_secs_f64() / OUTPUT_RATE_HALF_LIFE.as_secs_f64())
+    }
+}
+
+/// Tracks the position of the viewport of a pane with scrollback, for
+/// use by panes that implement scroll_offset and set_viewport_top.
+/// Like the viewport in the GUI, it follows the bottom of the
+/// scrollback until it is scrolled back, and then stays on the same
+/// stable row as more output arrives or the pane is resized, until it
+/// is scrolled to the bottom again.
+#[derive(Debug, Default)]
+pub struct ViewportState {
+    /// None when tracking the bottom of the scrollback
+    top: RefCell<Option<StableRowIndex>>,
+}
+
+impl ViewportState {
+    /// Returns the top of the viewport, clamped to the scrollback
+    /// described by dims
+    pub fn top(&self, dims: &RenderableDimensions) -> StableRowIndex {
+        match *self.top.borrow() {
+            Some(top) => top.max(dims.scrollback_top).min(dims.physical_top),
+            None => dims.physical_top,
+        }
+    }
+
+    pub fn set_top(&self, top: StableRowIndex, dims: &RenderableDimensions) {
+        *self.top.borrow_mut() = if top >= dims.physical_top {
+            None
+        } else {
+            Some(top.max(dims.scrollback_top))
+        };
+    }
+}
+
+/// Returns a NonBlockingReader that receives a copy of the pty output
+/// of the specified pane, from now on, as it is read by the mux.
+/// This allows Pane::try_read to be implemented without opening a
+/// second reader that would compete with the mux for the output.
+/// Only one tee can exist per pane; a second call replaces the first.
+pub fn tee_pane_output(pane_id: PaneId) -> NonBlockingReader {
+    let (tx, rx) = flume::unbounded();
+    OUTPUT_TEES.lock().unwrap().insert(pane_id, tx);
+    NonBlockingReader::with_receiver(rx)
+}
+
+/// Passes data that was read from the pty of the specified pane to its
+/// tee, if any.  The tee is removed once its reader has been dropped.
+pub fn forward_pane_output(pane_id: PaneId, data: &[u8]) {
+    let mut tees = OUTPUT_TEES.lock().unwrap();
+    let disconnected = match tees.get(&pane_id) {
+        Some(tx) => tx.send(Ok(data.to_vec())).is_err(),
+        None => false,
+    };
+    if disconnected {
+        tees.remove(&pane_id);
+    }
+}
+
+/// Removes the tee of the specified pane, if any, so that its reader
+/// reports EOF once it has consumed the output that was passed to it
+pub fn close_pane_output(pane_id: PaneId) {
+    OUTPUT_TEES.lock().unwrap().remove(&pane_id);
+}
+
+/// Adapts a blocking reader, such as the one returned by Pane::reader,
+/// so that it can be polled without blocking.
+/// A helper thread performs the blocking reads and passes the data
+/// back over a channel.
+pub struct NonBlockingReader {
+    rx: flume::Receiver<std::io::Result<Vec<u8>>>,
+    pending: RefCell<Vec<u8>>,
+}
+
+impl NonBlockingReader {
+    pub fn new(mut reader: Box<dyn std::io::Read + Send>) -> Self {
+        let (tx, rx) = flume::unbounded();
+        std::thread::spawn(move || {
+            let mut buf = vec![0u8; 8192];
+            loop {
+                let res = reader.read(&mut buf).map(|size| buf[..size].to_vec());
+                let done = match &res {
+                    Ok(data) => data.is_empty(),
+                    Err(_) => true,
+                };
+                if tx.send(res).is_err() || done {
+                    break;
+                }
+            }
+        });
+        Self::with_receiver(rx)
+    }
+
+    fn with_receiver(rx: flume::Receiver<std::io::Result<Vec<u8>>>) -> Self {
+        Self {
+            rx,
+            pending: RefCell::new(vec![]),
+        }
+    }
+
+    /// Copies any available data into buf, returning the number of
+    /// bytes copied.  Returns a WouldBlock error if no data is available
+    /// yet, and 0 once the underlying reader has reached EOF.
+    pub fn try_read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut pending = self.pending.borrow_mut();
+        if pending.is_empty() {
+            match self.rx.try_recv() {
+                Ok(Ok(data)) => *pending = data,
+                Ok(Err(err)) => return Err(err),
+                Err(flume::TryRecvError::Empty) => {
+                    return Err(std::io::ErrorKind::WouldBlock.into())
+                }
+                Err(flume::TryRecvError::Disconnected) => return Ok(0),
+            }
+        }
+        let size = pending.len().min(buf.len());
+        buf[..size].copy_from_slice(&pending[..size]);
+        pending.drain(..size);
+        Ok(size)
+    }
+}
+
+/// An input event captured while a pane is recording its input
+/// (see Pane::start_input_recording)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputEvent {
+    KeyDown { key: KeyCode, mods: KeyModifiers },
+    KeyUp { key: KeyCode, mods: KeyModifiers },
+    Mouse(MouseEvent),
+    Paste(String),
+}
+
+/// Holds the input events of a pane while recording is active.
+/// Panes that support recording embed one of these and feed it
+/// from their input methods.
+#[derive(Default)]
+pub struct InputRecorder {
+    events: RefCell<Option<Vec<InputEvent>>>,
+}
+
+impl InputRecorder {
+    /// Starts recording, discarding any previously recorded events
+    pub fn start(&self) {
+        self.events.borrow_mut().replace(vec![]);
+    }
+
+    /// Stops recording and returns the recorded events
+    pub fn stop(&self) -> Vec<InputEvent> {
+        self.events.borrow_mut().take().unwrap_or_default()
+    }
+
+    /// Records event, if recording is active
+    pub fn record(&self, event: InputEvent) {
+        if let Some(events) = self.events.borrow_mut().as_mut() {
+            events.push(event);
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Declares which of the optional Pane features are implemented by a
+    /// pane, rather than being left as the default no-op, so that the
+    /// GUI can disable actions that the pane doesn't support.
+    #[derive(Default)]
+    pub struct PaneCapabilities: u16 {
+        /// search() returns results
+        const SEARCH = 1;
+        /// The pane retains scrollback that can be scrolled through
+        const SCROLLBACK = 2;
+        /// resize() changes the size of the pane content
+        const RESIZE = 4;
+        /// kill() terminates the pane
+        const KILL = 8;
+        /// get_current_working_dir() can return a directory
+        const CWD = 16;
+        /// get_semantic_zones() returns zones
+        const SEMANTIC_ZONES = 32;
+        /// scroll_offset() and set_viewport_top() track the viewport,
+        /// so that the mux can scroll the pane too
+        const VIEWPORT = 64;
+    }
+}
+
 /// Why a close request is being made
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CloseReason {
@@ -54,40 +524,141 @@ pub enum CloseReason {
     Pane,
 }
 
+/// What a paste appears to contain, as determined by classify_paste
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PasteKind {
+    /// A single URL, with nothing else other than surrounding whitespace
+    Url,
+    /// More than one line of text, which a shell would run as several
+    /// commands if it isn't using bracketed paste
+    MultilineCommand,
+    /// Anything else, such as a single line of text
+    PlainText,
+}
+
 const PASTE_CHUNK_SIZE: usize = 1024;
 
 struct Paste {
     pane_id: PaneId,
     text: String,
     offset: usize,
+    /// The maximum number of bytes to send at a time
+    chunk_size: usize,
+    /// If set, the chunks are spaced out so that the average rate
+    /// doesn't exceed this many bytes per second
+    bytes_per_sec: Option<usize>,
+    /// Set by Pane::cancel_paste to stop sending further chunks
+    cancelled: Arc<AtomicBool>,
 }
 
-fn paste_next_chunk(paste: &Arc<Mutex<Paste>>) {
-    let mut locked = paste.lock().unwrap();
-    let mux = Mux::get().unwrap();
-    let pane = mux.get_pane(locked.pane_id).unwrap();
+/// Returns the cancellation flag for new pastes into pane_id
+fn paste_cancel_flag(pane_id: PaneId) -> Arc<AtomicBool> {
+    Arc::clone(
+        PASTE_CANCEL
+            .lock()
+            .unwrap()
+            .entry(pane_id)
+            .or_insert_with(|| Arc::new(AtomicBool::new(false))),
+    )
+}
 
-    let remain = locked.text.len() - locked.offset;
-    let mut chunk = remain.min(PASTE_CHUNK_SIZE);
+/// Cancels any in-flight pastes into pane_id.  The flag is removed from
+/// the registry so that subsequent pastes start afresh.
+fn cancel_pastes_for_pane(pane_id: PaneId) {
+    if let Some(flag) = PASTE_CANCEL.lock().unwrap().remove(&pane_id) {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Called once a paste into pane_id has finished with its cancellation
+/// flag, to remove the flag from the registry if no other paste into
+/// that pane is still using it
+fn release_paste_cancel_flag(pane_id: PaneId, flag: &Arc<AtomicBool>) {
+    let mut flags = PASTE_CANCEL.lock().unwrap();
+    if let Some(registered) = flags.get(&pane_id) {
+        // The registry and the caller each hold a reference
+        if Arc::ptr_eq(registered, flag) && Arc::strong_count(flag) <= 2 {
+            flags.remove(&pane_id);
+        }
+    }
+}
+
+impl Paste {
+    /// Returns the maximum size of a chunk; when rate limited, this is
+    /// capped at one second's worth of data
+    fn chunk_size(&self) -> usize {
+        let chunk_size = self.chunk_size.max(1);
+        match self.bytes_per_sec {
+            Some(rate) => chunk_size.min(rate.max(1)),
+            None => chunk_size,
+        }
+    }
+
+    /// Returns the length of the next chunk to send
+    fn next_chunk_len(&self) -> usize {
+        let remain = self.text.len() - self.offset;
+        let mut chunk = remain.min(self.chunk_size());
+
+        // Make sure we chunk at a char boundary, otherwise the
+        // slice operation below will panic
+        while !self.text.is_char_boundary(self.offset + chunk) && chunk < remain {
+            chunk += 1;
+        }
+        chunk
+    }
+
+    /// Returns how long to wait after sending a chunk of len bytes
+    /// before sending the next one
+    fn delay_after(&self, len: usize) -> Duration {
+        match self.bytes_per_sec {
+            Some(rate) if rate > 0 => Duration::from_secs_f64(len as f64 / rate as f64),
+            _ => Duration::from_secs(0),
+        }
+    }
+}
 
-    // Make sure we chunk at a char boundary, otherwise the
-    // slice operation below will panic
-    while !locked.text.is_char_boundary(locked.offset + chunk) && chunk < remain {
-        chunk += 1;
+/// Sends the next chunk of paste to pane.  Returns the delay before
+/// the following chunk should be sent, or None if the paste is complete
+/// or has been cancelled.
+fn send_next_chunk(paste: &mut Paste, pane: &dyn Pane) -> Option<Duration> {
+    if paste.cancelled.load(Ordering::Relaxed) {
+        return None;
     }
-    let text_slice = &locked.text[locked.offset..locked.offset + chunk];
+
+    let remain = paste.text.len() - paste.offset;
+    let chunk = paste.next_chunk_len();
+    let text_slice = &paste.text[paste.offset..paste.offset + chunk];
     pane.send_paste(text_slice).unwrap();
 
     if chunk < remain {
         // There is more to send
-        locked.offset += chunk;
-        schedule_next_paste(paste);
+        paste.offset += chunk;
+        Some(paste.delay_after(chunk))
+    } else {
+        None
+    }
+}
+
+fn paste_next_chunk(paste: &Arc<Mutex<Paste>>) {
+    let mut locked = paste.lock().unwrap();
+    if locked.cancelled.load(Ordering::Relaxed) {
+        return;
+    }
+    let mux = Mux::get().unwrap();
+    let pane = mux.get_pane(locked.pane_id).unwrap();
+
+    match send_next_chunk(&mut locked, &*pane) {
+        Some(delay) => schedule_next_paste(paste, delay),
+        None => release_paste_cancel_flag(locked.pane_id, &locked.cancelled),
     }
 }
 
-fn schedule_next_paste(paste: &Arc<Mutex<Paste>>) {
+fn schedule_next_paste(paste: &Arc<Mutex<Paste>>, delay: Duration) {
     let paste = Arc::clone(paste);
     promise::spawn::spawn(async move {
+        if delay > Duration::from_secs(0) {
+            smol::Timer::after(delay).await;
+        }
         paste_next_chunk(&paste);
     })
     .detach();
@@ -182,6 +753,35 @@ pub trait Pane: Downcast {
         seqno: SequenceNo,
     ) -> RangeSet<StableRowIndex>;
 
+    /// Returns true if the pane may need to be repainted because
+    /// something has changed since seqno, which is typically the value
+    /// of get_current_seqno() when the pane was last rendered.  This
+    /// allows the renderer to skip idle panes without examining their
+    /// lines.  The default conservatively reports the pane as dirty.
+    fn is_dirty(&self, _seqno: SequenceNo) -> bool {
+        true
+    }
+
+    /// Returns the current generation along with the cells in the
+    /// viewport that have changed since since_generation, which is
+    /// typically the generation returned by a prior call.
+    /// The default uses the line based change tracking of
+    /// get_changed_since and so reports entire rows.
+    fn changed_cells(&self, since_generation: u64) -> (u64, Vec<CellRange>) {
+        let generation = self.get_current_seqno() as u64;
+        let viewport = self.viewport_range();
+        let cols = self.get_dimensions().cols;
+        let changed = self
+            .get_changed_since(viewport.clone(), since_generation as SequenceNo)
+            .iter_values()
+            .map(|row| CellRange {
+                y: (row - viewport.start) as usize,
+                x: 0..cols,
+            })
+            .collect();
+        (generation, changed)
+    }
+
     /// Returns a set of lines from the scrollback or visible portion of
     /// the display.  The lines are indexed using StableRowIndex, which
     /// can be invalidated if the scrollback is busy, or when switching
@@ -314,18 +914,147 @@ pub trait Pane: Downcast {
     /// Returns render related dimensions
     fn get_dimensions(&self) -> RenderableDimensions;
 
+    /// Returns the stable row index of the top of the viewport.
+    /// The default is to report that the viewport is tracking
+    /// the bottom of the scrollback.
+    fn scroll_offset(&self) -> StableRowIndex {
+        self.get_dimensions().physical_top
+    }
+
+    /// Called by scroll_to to move the top of the viewport to the
+    /// specified row, which has already been clamped to the scrollback.
+    /// Panes that track a viewport, typically using ViewportState,
+    /// should override this together with scroll_offset, and report
+    /// PaneCapabilities::VIEWPORT.
+    fn set_viewport_top(&self, _top: StableRowIndex) {}
+
+    /// Scrolls so that the top of the viewport is at the specified row,
+    /// clamped to the bounds of the scrollback.
+    fn scroll_to(&self, offset: StableRowIndex) {
+        let dims = self.get_dimensions();
+        self.set_viewport_top(offset.max(dims.scrollback_top).min(dims.physical_top));
+    }
+
+    /// Scrolls the viewport by delta rows; negative values scroll
+    /// back into the scrollback.
+    fn scroll_by(&self, delta: isize) {
+        self.scroll_to(self.scroll_offset().saturating_add(delta as StableRowIndex));
+    }
+
+    /// Returns the range of stable rows that are visible in the viewport,
+    /// starting from the row reported by scroll_offset
+    fn viewport_range(&self) -> Range<StableRowIndex> {
+        let top = self.scroll_offset();
+        top..top + self.get_dimensions().viewport_rows as StableRowIndex
+    }
+
+    /// Returns a hash of the text in the viewport, so that panes that
+    /// are showing the same content can be detected cheaply
+    fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let (_, lines) = self.get_lines(self.viewport_range());
+        for line in lines {
+            line.as_str().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Returns an estimate of the number of bytes used to hold the
+    /// scrollback of this pane, for reporting purposes
+    fn scrollback_memory_estimate(&self) -> usize {
+        0
+    }
+
+    /// Returns the set of optional features that this pane supports.
+    /// The default is to declare none of them.
+    fn capabilities(&self) -> PaneCapabilities {
+        PaneCapabilities::empty()
+    }
+
     fn get_title(&self) -> String;
     fn send_paste(&self, text: &str) -> anyhow::Result<()>;
+    /// Returns the total number of bytes that have been pasted into
+    /// this pane, including each chunk sent by trickle_paste.
+    /// Panes that don't track this report 0.
+    fn total_pasted_bytes(&self) -> u64 {
+        0
+    }
     fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>>;
     fn writer(&self) -> RefMut<dyn std::io::Write>;
+
+    /// Reads output from the pane without blocking, returning a
+    /// WouldBlock error when no data is available.  This is intended
+    /// for event loops that poll rather than dedicating a thread to
+    /// the blocking reader.  Panes can use NonBlockingReader to
+    /// implement this, or tee_pane_output when the mux is already
+    /// reading from the pane.
+    fn try_read(&self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::other(
+            "non-blocking reads are not supported by this pane",
+        ))
+    }
+
+    /// Sends text to the pane as though it were typed, encoded as UTF-8
+    fn write_str(&self, s: &str) -> anyhow::Result<()> {
+        let mut writer = self.writer();
+        writer.write_all(s.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
     fn resize(&self, size: PtySize) -> anyhow::Result<()>;
+
+    /// Returns the size that the content of the pane would like to
+    /// occupy, if it has expressed a preference; for example, a TUI
+    /// application that knows how much space it needs.
+    fn preferred_size(&self) -> Option<PtySize> {
+        None
+    }
+
     /// Called as a hint that the pane is being resized as part of
     /// a zoom-to-fill-all-the-tab-space operation.
     fn set_zoomed(&self, _zoomed: bool) {}
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()>;
     fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()>;
     fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()>;
+
+    /// Starts recording the key, mouse and paste input that is sent
+    /// to this pane, so that it can be attached to a bug report and
+    /// replayed.  Panes that don't support recording ignore this.
+    fn start_input_recording(&self) {}
+
+    /// Stops recording input and returns the events that were
+    /// captured since start_input_recording was called
+    fn stop_input_recording(&self) -> Vec<InputEvent> {
+        vec![]
+    }
     fn perform_actions(&self, _actions: Vec<termwiz::escape::Action>) {}
+
+    /// Returns the time at which the pane last processed output.
+    /// Panes that don't track this report a fixed time from around
+    /// the start of the process, so that they sort as the least
+    /// recently active.
+    fn last_activity(&self) -> Instant {
+        *NO_ACTIVITY
+    }
+
+    /// Records that bytes of raw output were read from the pty of
+    /// this pane, ahead of the corresponding call to perform_actions.
+    /// Panes that don't track their output rate ignore this.
+    fn record_output(&self, _bytes: usize) {}
+
+    /// Returns the recent rate at which the pane has been producing
+    /// output, in bytes per second; see OutputRate.
+    /// Panes that don't track this report 0.
+    fn output_rate(&self) -> f64 {
+        0.
+    }
+
+    /// Returns true if the pane is currently producing output faster
+    /// than threshold_bps bytes per second
+    fn is_output_active(&self, threshold_bps: f64) -> bool {
+        self.output_rate() > threshold_bps
+    }
     fn is_dead(&self) -> bool;
     fn kill(&self) {}
     fn palette(&self) -> ColorPalette;
@@ -359,6 +1088,41 @@ pub trait Pane: Downcast {
         Ok(vec![])
     }
 
+    /// Like search, but gives up once cancel is set, returning the
+    /// results that were found up to that point.  This allows a search
+    /// to be abandoned when the user changes the query.
+    /// The default implementation only checks cancel before starting
+    /// the search.
+    async fn search_cancellable(
+        &self,
+        pattern: Pattern,
+        cancel: Arc<AtomicBool>,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(vec![]);
+        }
+        self.search(pattern).await
+    }
+
+    /// Like search, but each result also carries the matched text and
+    /// the text of the line on which it starts, so that callers don't
+    /// need to re-read the lines, which may have scrolled in the
+    /// meantime.  The default implementation reads the lines from
+    /// get_lines immediately after searching.
+    async fn search_with_context(
+        &self,
+        pattern: Pattern,
+    ) -> anyhow::Result<Vec<SearchResultWithContext>> {
+        let results = self.search(pattern).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                let (first, lines) = self.get_lines(result.start_y..result.end_y + 1);
+                SearchResultWithContext::from_lines(result, first, &lines)
+            })
+            .collect())
+    }
+
     /// Retrieve the set of semantic zones
     fn get_semantic_zones(&self) -> anyhow::Result<Vec<SemanticZone>> {
         Ok(vec![])
@@ -379,12 +1143,57 @@ pub trait Pane: Downcast {
     }
 
     fn get_current_working_dir(&self) -> Option<Url>;
+
+    /// Like get_current_working_dir, but allows implementations for
+    /// which the working directory requires a round trip, such as
+    /// remote panes, to fetch it without blocking the GUI thread.
+    /// The default returns the result of get_current_working_dir.
+    async fn get_current_working_dir_async(&self) -> anyhow::Result<Option<Url>> {
+        Ok(self.get_current_working_dir())
+    }
+
+    /// Arranges for callback to be called, once, the next time that
+    /// pattern is seen in the output processed by this pane.
+    fn set_output_trigger(
+        &self,
+        _pattern: Pattern,
+        _callback: OutputTriggerCallback,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("output triggers are not supported by this pane");
+    }
+
     fn get_foreground_process_name(&self) -> Option<String> {
         None
     }
 
+    /// Returns the maximum number of bytes that trickle_paste sends
+    /// to the pane at a time.  Slow links benefit from smaller chunks.
+    /// A value of 0 is treated as 1.
+    fn paste_chunk_size(&self) -> usize {
+        PASTE_CHUNK_SIZE
+    }
+
+    /// Classifies text that is about to be pasted, so that the GUI can
+    /// intercept it before calling trickle_paste; for example, to open
+    /// a URL rather than typing it.  This only examines the text.
+    fn classify_paste(&self, text: &str) -> PasteKind {
+        let text = text.trim();
+        if text.contains(['\n', '\r']) {
+            return PasteKind::MultilineCommand;
+        }
+        if !text.contains(char::is_whitespace) {
+            if let Ok(url) = Url::parse(text) {
+                if url.has_host() {
+                    return PasteKind::Url;
+                }
+            }
+        }
+        PasteKind::PlainText
+    }
+
     fn trickle_paste(&self, text: String) -> anyhow::Result<()> {
-        if text.len() <= PASTE_CHUNK_SIZE {
+        let chunk_size = self.paste_chunk_size().max(1);
+        if text.len() <= chunk_size {
             // Send it all now
             self.send_paste(&text)?;
         } else {
@@ -393,11 +1202,55 @@ pub trait Pane: Downcast {
                 pane_id: self.pane_id(),
                 text,
                 offset: 0,
+                chunk_size,
+                bytes_per_sec: None,
+                cancelled: paste_cancel_flag(self.pane_id()),
             }));
             paste_next_chunk(&paste);
         }
         Ok(())
     }
+
+    /// Like trickle_paste, but spaces out the chunks so that the average
+    /// rate doesn't exceed bytes_per_sec, for flow controlled links
+    fn trickle_paste_rate_limited(&self, text: String, bytes_per_sec: usize) -> anyhow::Result<()> {
+        if bytes_per_sec == 0 {
+            anyhow::bail!("paste rate must be greater than 0 bytes per second");
+        }
+        let mut paste = Paste {
+            pane_id: self.pane_id(),
+            text,
+            offset: 0,
+            chunk_size: self.paste_chunk_size(),
+            bytes_per_sec: Some(bytes_per_sec),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        if paste.text.len() <= paste.chunk_size() {
+            // Send it all now
+            self.send_paste(&paste.text)?;
+        } else {
+            paste.cancelled = paste_cancel_flag(self.pane_id());
+            paste_next_chunk(&Arc::new(Mutex::new(paste)));
+        }
+        Ok(())
+    }
+
+    /// Stops any trickle paste that is still in progress for this pane;
+    /// chunks that have already been sent are unaffected
+    fn cancel_paste(&self) {
+        cancel_pastes_for_pane(self.pane_id());
+    }
+
+    /// Places the pane into, or takes it out of, read-only mode.
+    /// While read-only, implementations ignore key_down, key_up,
+    /// send_paste and mouse_event rather than passing the input on
+    /// to the application; for example, for a view-only log tail.
+    /// Panes that don't support read-only mode ignore this.
+    fn set_read_only(&self, _read_only: bool) {}
+
+    fn is_read_only(&self) -> bool {
+        false
+    }
 }
 impl_downcast!(Pane);
 
@@ -408,8 +1261,12 @@ mod test {
 
     struct FakePane {
         lines: Vec<Line>,
+        written: RefCell<Vec<u8>>,
+        output: Option<NonBlockingReader>,
+        recorder: InputRecorder,
     }
 
+    #[async_trait(?Send)]
     impl Pane for FakePane {
         fn pane_id(&self) -> PaneId {
             unimplemented!()
@@ -448,21 +1305,59 @@ mod test {
         fn get_title(&self) -> String {
             unimplemented!()
         }
-        fn send_paste(&self, _: &str) -> anyhow::Result<()> {
-            unimplemented!()
+        fn send_paste(&self, text: &str) -> anyhow::Result<()> {
+            self.recorder.record(InputEvent::Paste(text.to_string()));
+            Ok(())
         }
         fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>> {
             Ok(None)
         }
         fn writer(&self) -> RefMut<dyn std::io::Write> {
-            unimplemented!()
+            self.written.borrow_mut()
+        }
+        fn capabilities(&self) -> PaneCapabilities {
+            PaneCapabilities::SEARCH | PaneCapabilities::SCROLLBACK
+        }
+        async fn search(&self, pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+            let needle = match pattern {
+                Pattern::CaseSensitiveString(s) => s,
+                _ => unimplemented!(),
+            };
+            Ok(self
+                .lines
+                .iter()
+                .enumerate()
+                .filter_map(|(y, line)| {
+                    let x = line.as_str().find(&needle)?;
+                    Some(SearchResult {
+                        start_y: y as StableRowIndex,
+                        start_x: x,
+                        end_y: y as StableRowIndex,
+                        end_x: x + needle.len(),
+                        match_id: 0,
+                    })
+                })
+                .collect())
+        }
+        fn try_read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match &self.output {
+                Some(output) => output.try_read(buf),
+                None => Ok(0),
+            }
         }
         fn resize(&self, _: PtySize) -> anyhow::Result<()> {
             unimplemented!()
         }
 
-        fn mouse_event(&self, _: MouseEvent) -> anyhow::Result<()> {
-            unimplemented!()
+        fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()> {
+            self.recorder.record(InputEvent::Mouse(event));
+            Ok(())
+        }
+        fn start_input_recording(&self) {
+            self.recorder.start();
+        }
+        fn stop_input_recording(&self) -> Vec<InputEvent> {
+            self.recorder.stop()
         }
         fn is_dead(&self) -> bool {
             unimplemented!()
@@ -483,11 +1378,13 @@ mod test {
         fn get_current_working_dir(&self) -> Option<Url> {
             None
         }
-        fn key_down(&self, _: KeyCode, _: KeyModifiers) -> anyhow::Result<()> {
-            unimplemented!()
+        fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+            self.recorder.record(InputEvent::KeyDown { key, mods });
+            Ok(())
         }
-        fn key_up(&self, _: KeyCode, _: KeyModifiers) -> anyhow::Result<()> {
-            unimplemented!()
+        fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+            self.recorder.record(InputEvent::KeyUp { key, mods });
+            Ok(())
         }
     }
 
@@ -537,6 +1434,9 @@ mod test {
 
         let pane = FakePane {
             lines: physical_lines,
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
         };
 
         fn summarize_logical_lines(lines: &[LogicalLine]) -> Vec<(StableRowIndex, String)> {
@@ -904,4 +1804,503 @@ mod test {
             _ => unreachable!(),
         }
     }
+
+    #[test]
+    fn output_trigger_fires_once() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let fired = Rc::new(Cell::new(0));
+        let triggers = RefCell::new(vec![]);
+        triggers.borrow_mut().push(
+            OutputTrigger::new(
+                Pattern::Regex("build (finished|failed)".to_string()),
+                Box::new({
+                    let fired = Rc::clone(&fired);
+                    move |pane_id| {
+                        assert_eq!(pane_id, 42);
+                        fired.set(fired.get() + 1);
+                    }
+                }),
+            )
+            .unwrap(),
+        );
+
+        fire_output_triggers(&triggers, 42, "compiling foo\n");
+        assert_eq!(fired.get(), 0);
+        // The pattern is matched when it spans several batches of output
+        fire_output_triggers(&triggers, 42, "build fin");
+        assert_eq!(fired.get(), 0);
+        fire_output_triggers(&triggers, 42, "ished in 2s\n");
+        assert_eq!(fired.get(), 1);
+        assert!(triggers.borrow().is_empty());
+        fire_output_triggers(&triggers, 42, "build finished in 3s\n");
+        assert_eq!(fired.get(), 1);
+    }
+
+    #[test]
+    fn write_str() {
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+        pane.write_str("echo hello\n").unwrap();
+        pane.write_str("héllo").unwrap();
+        assert_eq!(
+            pane.written.borrow().as_slice(),
+            "echo hello\nhéllo".as_bytes()
+        );
+    }
+
+    #[test]
+    fn try_read() {
+        struct ChannelReader(std::sync::mpsc::Receiver<Vec<u8>>);
+        impl std::io::Read for ChannelReader {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let data = self.0.recv().unwrap_or_default();
+                buf[..data.len()].copy_from_slice(&data);
+                Ok(data.len())
+            }
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: Some(NonBlockingReader::new(Box::new(ChannelReader(rx)))),
+            recorder: InputRecorder::default(),
+        };
+
+        let mut buf = [0u8; 4];
+        let err = pane.try_read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+        tx.send(b"hello".to_vec()).unwrap();
+        let mut output = vec![];
+        while output.len() < 5 {
+            match pane.try_read(&mut buf) {
+                Ok(size) => output.extend_from_slice(&buf[..size]),
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(std::time::Duration::from_millis(1))
+                }
+                Err(err) => panic!("{}", err),
+            }
+        }
+        assert_eq!(output, b"hello");
+
+        drop(tx);
+        loop {
+            match pane.try_read(&mut buf) {
+                Ok(size) => {
+                    assert_eq!(size, 0);
+                    break;
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(std::time::Duration::from_millis(1))
+                }
+                Err(err) => panic!("{}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+        let caps = pane.capabilities();
+        assert!(caps.contains(PaneCapabilities::SEARCH));
+        assert!(caps.contains(PaneCapabilities::SCROLLBACK));
+        assert!(!caps.contains(PaneCapabilities::KILL));
+        assert!(!caps.intersects(PaneCapabilities::RESIZE | PaneCapabilities::CWD));
+
+        let pending = crate::pendingpane::PendingPane::new(
+            0,
+            PtySize {
+                rows: 24,
+                cols: 80,
+                pixel_width: 800,
+                pixel_height: 600,
+            },
+        );
+        assert_eq!(pending.capabilities(), PaneCapabilities::RESIZE);
+    }
+
+    #[test]
+    fn search_cancellable() {
+        let pane = FakePane {
+            lines: vec![Line::from_text(
+                "hello there",
+                &Default::default(),
+                SEQ_ZERO,
+            )],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+        let pattern = Pattern::CaseSensitiveString("there".to_string());
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let results =
+            smol::block_on(pane.search_cancellable(pattern.clone(), Arc::clone(&cancel))).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].start_x, 6);
+
+        cancel.store(true, Ordering::Relaxed);
+        let results = smol::block_on(pane.search_cancellable(pattern, cancel)).unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn input_recording() {
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+
+        // Input is ignored until recording starts
+        pane.key_down(KeyCode::Char('x'), KeyModifiers::NONE)
+            .unwrap();
+        pane.start_input_recording();
+        pane.key_down(KeyCode::Char('a'), KeyModifiers::CTRL)
+            .unwrap();
+        pane.key_up(KeyCode::Char('a'), KeyModifiers::CTRL).unwrap();
+        pane.send_paste("hello").unwrap();
+
+        let events = pane.stop_input_recording();
+        assert_eq!(
+            events,
+            vec![
+                InputEvent::KeyDown {
+                    key: KeyCode::Char('a'),
+                    mods: KeyModifiers::CTRL
+                },
+                InputEvent::KeyUp {
+                    key: KeyCode::Char('a'),
+                    mods: KeyModifiers::CTRL
+                },
+                InputEvent::Paste("hello".to_string()),
+            ]
+        );
+
+        pane.key_down(KeyCode::Char('y'), KeyModifiers::NONE)
+            .unwrap();
+        assert!(pane.stop_input_recording().is_empty());
+    }
+
+    #[test]
+    fn rate_limited_paste_schedule() {
+        let len = 10_000;
+        let rate = 2_000;
+        let mut paste = Paste {
+            pane_id: 0,
+            text: "x".repeat(len),
+            offset: 0,
+            chunk_size: PASTE_CHUNK_SIZE,
+            bytes_per_sec: Some(rate),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+
+        // Step through the chunks as paste_next_chunk would, advancing
+        // a simulated clock by the delay scheduled after each one
+        let mut clock = Duration::from_secs(0);
+        let mut chunks = vec![];
+        loop {
+            let chunk = paste.next_chunk_len();
+            chunks.push(chunk);
+            if paste.offset + chunk >= len {
+                break;
+            }
+            paste.offset += chunk;
+            clock += paste.delay_after(chunk);
+        }
+
+        assert!(chunks.iter().all(|&chunk| chunk <= rate));
+        assert_eq!(chunks.iter().sum::<usize>(), len);
+        // Everything but the final chunk must have been paced
+        let last = *chunks.last().unwrap();
+        let minimum = Duration::from_secs_f64((len - last) as f64 / rate as f64);
+        assert!(clock >= minimum, "{:?} < {:?}", clock, minimum);
+
+        // Without a rate limit, there is no delay between chunks
+        paste.bytes_per_sec = None;
+        assert_eq!(paste.delay_after(1024), Duration::from_secs(0));
+
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+        assert!(pane.trickle_paste_rate_limited("x".into(), 0).is_err());
+    }
+
+    #[test]
+    fn paste_chunk_size() {
+        fn chunks(len: usize, chunk_size: usize) -> Vec<usize> {
+            let mut paste = Paste {
+                pane_id: 0,
+                text: "x".repeat(len),
+                offset: 0,
+                chunk_size,
+                bytes_per_sec: None,
+                cancelled: Arc::new(AtomicBool::new(false)),
+            };
+            let mut chunks = vec![];
+            while paste.offset < len {
+                let chunk = paste.next_chunk_len();
+                chunks.push(chunk);
+                paste.offset += chunk;
+            }
+            chunks
+        }
+
+        assert_eq!(chunks(3000, 500), vec![500; 6]);
+        assert_eq!(chunks(3001, 500).len(), 7);
+        // A chunk size of 0 is clamped rather than looping forever
+        assert_eq!(chunks(3, 0), vec![1, 1, 1]);
+
+        // Pastes that fit in a chunk are sent in one shot
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+        assert_eq!(pane.paste_chunk_size(), PASTE_CHUNK_SIZE);
+        pane.start_input_recording();
+        pane.trickle_paste("hello".to_string()).unwrap();
+        assert_eq!(
+            pane.stop_input_recording(),
+            vec![InputEvent::Paste("hello".to_string())]
+        );
+    }
+
+    #[test]
+    fn cancel_paste() {
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+        let pane_id = 0x7fff_0000;
+        let mut paste = Paste {
+            pane_id,
+            text: "x".repeat(3000),
+            offset: 0,
+            chunk_size: 1000,
+            bytes_per_sec: None,
+            cancelled: paste_cancel_flag(pane_id),
+        };
+
+        pane.start_input_recording();
+        assert!(send_next_chunk(&mut paste, &pane).is_some());
+        cancel_pastes_for_pane(pane_id);
+        assert!(send_next_chunk(&mut paste, &pane).is_none());
+        assert_eq!(
+            pane.stop_input_recording(),
+            vec![InputEvent::Paste("x".repeat(1000))]
+        );
+        assert_eq!(paste.offset, 1000);
+
+        // A subsequent paste is not affected by the earlier cancellation
+        assert!(!paste_cancel_flag(pane_id).load(Ordering::Relaxed));
+        cancel_pastes_for_pane(pane_id);
+    }
+
+    #[test]
+    fn release_paste_cancel_flag() {
+        let pane_id = 0x7fff_0001;
+        let first = paste_cancel_flag(pane_id);
+        let second = paste_cancel_flag(pane_id);
+
+        // The flag is kept while another paste is still using it
+        super::release_paste_cancel_flag(pane_id, &first);
+        drop(first);
+        assert!(PASTE_CANCEL.lock().unwrap().contains_key(&pane_id));
+
+        super::release_paste_cancel_flag(pane_id, &second);
+        assert!(!PASTE_CANCEL.lock().unwrap().contains_key(&pane_id));
+    }
+
+    #[test]
+    fn whole_word_pattern() {
+        let haystack = "foo foobar barfoo foo_bar (foo) foo";
+        let ranges = find_pattern_matches(&Pattern::WholeWord("foo".into()), haystack);
+        assert_eq!(ranges, vec![0..3, 27..30, 32..35]);
+        // The plain string pattern finds every occurrence
+        assert_eq!(
+            find_pattern_matches(&Pattern::CaseSensitiveString("foo".into()), haystack).len(),
+            6
+        );
+        // Whole word matching is case sensitive
+        assert!(find_pattern_matches(&Pattern::WholeWord("FOO".into()), haystack).is_empty());
+        // Non-ascii word characters are respected
+        assert!(find_pattern_matches(&Pattern::WholeWord("foo".into()), "éfoo").is_empty());
+    }
+
+    #[test]
+    fn multiline_regex_pattern() {
+        let haystack = "INFO starting\nERROR disk full\nINFO retrying\nERROR gave up\n";
+        let multi = find_pattern_matches(&Pattern::MultiLineRegex("^ERROR (.*)$".into()), haystack);
+        let matched: Vec<&str> = multi.iter().map(|r| &haystack[r.clone()]).collect();
+        assert_eq!(matched, vec!["disk full", "gave up"]);
+
+        // Without multiline, ^ only anchors at the start of the text
+        assert!(find_pattern_matches(&Pattern::Regex("^ERROR".into()), haystack).is_empty());
+        assert_eq!(
+            find_pattern_matches(&Pattern::Regex("^INFO".into()), haystack),
+            vec![0..4]
+        );
+
+        // An invalid regex has no matches rather than failing
+        assert!(find_pattern_matches(&Pattern::MultiLineRegex("(".into()), haystack).is_empty());
+    }
+
+    #[test]
+    fn output_trigger_tail_is_bounded() {
+        let mut trigger =
+            OutputTrigger::new(Pattern::MultiLineRegex("^done$".into()), Box::new(|_| {})).unwrap();
+        let line = format!("{}\n", "x".repeat(99));
+        for _ in 0..100 {
+            assert!(!trigger.observe(&line));
+        }
+        assert!(trigger.tail.len() <= OUTPUT_TRIGGER_TAIL);
+        assert!(trigger.tail.starts_with('x'));
+        assert!(!trigger.observe("do"));
+        assert!(trigger.observe("ne\n"));
+    }
+
+    #[test]
+    fn output_trigger_pattern_modes() {
+        let trigger = |pattern| OutputTrigger::new(pattern, Box::new(|_| {})).unwrap();
+        assert!(trigger(Pattern::WholeWord("done".into())).matches("build done!"));
+        assert!(!trigger(Pattern::WholeWord("done".into())).matches("undone"));
+        assert!(trigger(Pattern::MultiLineRegex("^ok$".into())).matches("a\nok\nb"));
+        assert!(!trigger(Pattern::Regex("^ok$".into())).matches("a\nok\nb"));
+    }
+
+    #[test]
+    fn search_with_context() {
+        let attr = Default::default();
+        let pane = FakePane {
+            lines: vec![
+                Line::from_text("first line", &attr, SEQ_ZERO),
+                Line::from_text("the needle is here   ", &attr, SEQ_ZERO),
+            ],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+        let results = smol::block_on(
+            pane.search_with_context(Pattern::CaseSensitiveString("needle".to_string())),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![SearchResultWithContext {
+                result: SearchResult {
+                    start_y: 1,
+                    start_x: 4,
+                    end_y: 1,
+                    end_x: 10,
+                    match_id: 0,
+                },
+                line_text: "the needle is here".to_string(),
+                matched_text: "needle".to_string(),
+            }]
+        );
+
+        // A match that spans rows includes each row's portion
+        let result = SearchResult {
+            start_y: 0,
+            start_x: 6,
+            end_y: 1,
+            end_x: 3,
+            match_id: 0,
+        };
+        let context = SearchResultWithContext::from_lines(result, 0, &pane.lines);
+        assert_eq!(context.line_text, "first line");
+        assert_eq!(context.matched_text, "line\nthe");
+    }
+
+    #[test]
+    fn classify_paste() {
+        let pane = FakePane {
+            lines: vec![],
+            written: RefCell::new(vec![]),
+            output: None,
+            recorder: InputRecorder::default(),
+        };
+
+        assert_eq!(
+            pane.classify_paste("https://wezfurlong.org/wezterm/"),
+            PasteKind::Url
+        );
+        assert_eq!(
+            pane.classify_paste("  http://localhost:8080/?q=1\n"),
+            PasteKind::Url
+        );
+        assert_eq!(
+            pane.classify_paste("cd /tmp\nrm -rf build\n"),
+            PasteKind::MultilineCommand
+        );
+        assert_eq!(
+            pane.classify_paste("echo one\r\necho two"),
+            PasteKind::MultilineCommand
+        );
+        assert_eq!(pane.classify_paste("ls -l"), PasteKind::PlainText);
+        assert_eq!(
+            pane.classify_paste("see https://wezfurlong.org/"),
+            PasteKind::PlainText
+        );
+        // Parses as a URL, but is more likely to be text
+        assert_eq!(pane.classify_paste("note:remember"), PasteKind::PlainText);
+        assert_eq!(pane.classify_paste(""), PasteKind::PlainText);
+        // Classifying has no side effects
+        assert!(pane.written.borrow().is_empty());
+    }
+
+    #[test]
+    fn viewport_state() {
+        let mut dims = RenderableDimensions {
+            cols: 80,
+            viewport_rows: 24,
+            scrollback_rows: 124,
+            physical_top: 100,
+            scrollback_top: 0,
+        };
+        let viewport = ViewportState::default();
+        assert_eq!(viewport.top(&dims), 100);
+
+        viewport.set_top(70, &dims);
+        assert_eq!(viewport.top(&dims), 70);
+
+        // Stays on the same row as output scrolls the screen
+        dims.physical_top = 110;
+        dims.scrollback_top = 10;
+        assert_eq!(viewport.top(&dims), 70);
+
+        // Clamped once the row falls out of the scrollback
+        dims.physical_top = 180;
+        dims.scrollback_top = 80;
+        assert_eq!(viewport.top(&dims), 80);
+
+        viewport.set_top(-5, &dims);
+        assert_eq!(viewport.top(&dims), 80);
+
+        // Scrolling to the bottom resumes following the output
+        viewport.set_top(500, &dims);
+        assert_eq!(viewport.top(&dims), 180);
+        dims.physical_top = 190;
+        assert_eq!(viewport.top(&dims), 190);
+    }
 }
@@ -0,0 +1,159 @@
+//! A placeholder pane that occupies a slot in the tab while the real
+//! pane is still being spawned, for example by a remote domain.
+//! Once the real pane is available, `Tab::replace_pane` swaps it in
+//! without disturbing the layout.
+
+use crate::domain::DomainId;
+use crate::pane::{alloc_pane_id, CloseReason, Pane, PaneCapabilities, PaneId};
+use crate::renderable::*;
+use async_trait::async_trait;
+use portable_pty::PtySize;
+use rangeset::RangeSet;
+use std::cell::{RefCell, RefMut};
+use std::ops::Range;
+use termwiz::cell::CellAttributes;
+use termwiz::surface::{Line, SequenceNo, SEQ_ZERO};
+use url::Url;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::{KeyCode, KeyModifiers, MouseEvent, StableRowIndex};
+
+pub struct PendingPane {
+    pane_id: PaneId,
+    domain_id: DomainId,
+    size: RefCell<PtySize>,
+    message: String,
+    writer: RefCell<std::io::Sink>,
+}
+
+impl PendingPane {
+    pub fn new(domain_id: DomainId, size: PtySize) -> Self {
+        Self::with_message(domain_id, size, "Connecting...")
+    }
+
+    pub fn with_message(domain_id: DomainId, size: PtySize, message: &str) -> Self {
+        Self {
+            pane_id: alloc_pane_id(),
+            domain_id,
+            size: RefCell::new(size),
+            message: message.to_string(),
+            writer: RefCell::new(std::io::sink()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Pane for PendingPane {
+    fn pane_id(&self) -> PaneId {
+        self.pane_id
+    }
+
+    fn get_cursor_position(&self) -> StableCursorPosition {
+        StableCursorPosition::default()
+    }
+
+    fn get_current_seqno(&self) -> SequenceNo {
+        SEQ_ZERO
+    }
+
+    fn get_changed_since(
+        &self,
+        _lines: Range<StableRowIndex>,
+        _seqno: SequenceNo,
+    ) -> RangeSet<StableRowIndex> {
+        RangeSet::new()
+    }
+
+    fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+        let size = *self.size.borrow();
+        let first = lines.start.max(0);
+        let last = lines.end.min(size.rows as StableRowIndex).max(first);
+        let result = (first..last)
+            .map(|row| {
+                let mut line = if row == 0 {
+                    Line::from_text(&self.message, &CellAttributes::default(), SEQ_ZERO)
+                } else {
+                    Line::with_width(0, SEQ_ZERO)
+                };
+                line.resize(size.cols as usize, SEQ_ZERO);
+                line
+            })
+            .collect();
+        (first, result)
+    }
+
+    fn get_dimensions(&self) -> RenderableDimensions {
+        let size = self.size.borrow();
+        RenderableDimensions {
+            cols: size.cols as usize,
+            viewport_rows: size.rows as usize,
+            scrollback_rows: size.rows as usize,
+            physical_top: 0,
+            scrollback_top: 0,
+        }
+    }
+
+    fn capabilities(&self) -> PaneCapabilities {
+        PaneCapabilities::RESIZE
+    }
+
+    fn get_title(&self) -> String {
+        self.message.clone()
+    }
+
+    fn can_close_without_prompting(&self, _reason: CloseReason) -> bool {
+        true
+    }
+
+    fn send_paste(&self, _text: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>> {
+        Ok(None)
+    }
+
+    fn writer(&self) -> RefMut<dyn std::io::Write> {
+        self.writer.borrow_mut()
+    }
+
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        *self.size.borrow_mut() = size;
+        Ok(())
+    }
+
+    fn key_down(&self, _key: KeyCode, _mods: KeyModifiers) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn key_up(&self, _key: KeyCode, _mods: KeyModifiers) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn mouse_event(&self, _event: MouseEvent) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn palette(&self) -> ColorPalette {
+        ColorPalette::default()
+    }
+
+    fn domain_id(&self) -> DomainId {
+        self.domain_id
+    }
+
+    fn is_mouse_grabbed(&self) -> bool {
+        false
+    }
+
+    fn is_alt_screen_active(&self) -> bool {
+        false
+    }
+
+    fn get_current_working_dir(&self) -> Option<Url> {
+        None
+    }
+}
@@ -1,5 +1,6 @@
 use crate::domain::DomainId;
 use crate::pane::*;
+use crate::tmux_layout::TmuxLayout;
 use crate::{Mux, WindowId};
 use bintree::PathBranch;
 use config::configuration;
@@ -8,16 +9,50 @@ use portable_pty::PtySize;
 use rangeset::range_intersection;
 use serde::{Deserialize, Serialize};
 use std::cell::{RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
+use std::ops::Range;
 use std::rc::Rc;
+use std::time::Instant;
+use termwiz::color::RgbColor;
 use url::Url;
+use wezterm_term::StableRowIndex;
 
 pub type Tree = bintree::Tree<Rc<dyn Pane>, SplitDirectionAndSize>;
 pub type Cursor = bintree::Cursor<Rc<dyn Pane>, SplitDirectionAndSize>;
 
 static TAB_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+static ACTIVE_SUB_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 pub type TabId = usize;
 
+/// The default upper bound on the number of panes in a tab; this is
+/// intended to stop a runaway script from splitting indefinitely.
+pub const DEFAULT_MAX_PANES: usize = 1024;
+
+/// The fraction of a split given to the first child by split_golden,
+/// and the step between the hues of pane_accent_color_for_id
+pub const GOLDEN_RATIO: f32 = 0.618_034;
+
+/// The number of previously active panes remembered for
+/// Tab::activate_last_pane
+const ACTIVE_HISTORY_LEN: usize = 16;
+
+/// The default factor by which a peeked pane is enlarged
+pub const DEFAULT_PEEK_FACTOR: f32 = 1.5;
+
+/// The output rate, in bytes per second, above which any_pane_busy
+/// considers a pane to be busy
+pub const BUSY_OUTPUT_RATE: f64 = 1024.;
+
+/// The ids of the panes in the first and second children of a split
+type SplitSides = (Vec<PaneId>, Vec<PaneId>);
+
+/// A subscription id and its callback; see Tab::subscribe_active_change
+type ActiveSubscriber = (usize, Rc<dyn Fn(usize)>);
+
+/// A pane id and the (left, top, width, height) of that pane
+type PaneRect = (PaneId, (usize, usize, usize, usize));
+
 /// A Tab is a container of Panes
 pub struct Tab {
     id: TabId,
@@ -25,6 +60,41 @@ pub struct Tab {
     size: RefCell<PtySize>,
     active: RefCell<usize>,
     zoomed: RefCell<Option<Rc<dyn Pane>>>,
+    focus_locked: RefCell<bool>,
+    reserved_bottom_rows: RefCell<u16>,
+    active_pane_border: RefCell<usize>,
+    pane_header_rows: RefCell<u16>,
+    pane_gutter: RefCell<u16>,
+    min_pane_size: RefCell<(usize, usize)>,
+    max_panes: RefCell<usize>,
+    layout_preset: RefCell<Option<LayoutPreset>>,
+    percentage_layout: RefCell<Option<Vec<f32>>>,
+    split_styles: RefCell<HashMap<usize, SplitStyle>>,
+    close_on_last_pane_death: RefCell<bool>,
+    unfocusable_panes: RefCell<HashSet<PaneId>>,
+    peeked: RefCell<Option<usize>>,
+    peek_factor: RefCell<f32>,
+    /// Maps a split name to the ids of the panes on either side of it
+    split_names: RefCell<HashMap<String, SplitSides>>,
+    overlays: RefCell<Vec<DisplacedLayout>>,
+    /// The ids of the previously active panes, most recent last
+    active_history: RefCell<Vec<PaneId>>,
+    active_subscribers: RefCell<Vec<ActiveSubscriber>>,
+    /// The id and position of the active pane as of the last time that
+    /// it changed; see repair_active
+    active_rect: RefCell<Option<PaneRect>>,
+    /// The ids of the panes whose output triggers have fired, in the
+    /// order that they fired; see set_output_trigger
+    triggered_panes: Rc<RefCell<Vec<PaneId>>>,
+}
+
+/// The layout that was displaced by Tab::push_overlay_pane
+struct DisplacedLayout {
+    tree: Tree,
+    active: usize,
+    zoomed: bool,
+    /// The usable size of the tab at the time of the push
+    size: PtySize,
 }
 
 #[derive(Clone)]
@@ -35,6 +105,10 @@ pub struct PositionedPane {
     pub is_active: bool,
     /// true if this pane is zoomed
     pub is_zoomed: bool,
+    /// true if this pane is being peeked; its position has been
+    /// enlarged to overlap its neighbors, which are not resized.
+    /// Only set by iter_panes_for_render.
+    pub is_peeked: bool,
     /// The offset from the top left corner of the containing tab to the top
     /// left corner of this pane, in cells.
     pub left: usize,
@@ -126,6 +200,83 @@ impl SplitDirectionAndSize {
     }
 }
 
+/// Captures the split structure of a tab so that it can be persisted,
+/// for example to restore a session, and later rebuilt with
+/// Tab::rebuild_from_layout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SerializedTab {
+    Empty,
+    Pane(PaneId),
+    Split {
+        /// The direction of the split and the sizes of its children
+        split: SplitDirectionAndSize,
+        first: Box<SerializedTab>,
+        second: Box<SerializedTab>,
+    },
+}
+
+/// Captures the view state of a tab; which pane is active and how far
+/// each pane has been scrolled, so that it can be restored later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TabLayout {
+    pub active_pane: Option<PaneId>,
+    pub scroll_offsets: Vec<(PaneId, StableRowIndex)>,
+}
+
+/// Options that influence how split_and_insert_with_options
+/// splits a pane
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SplitOptions {
+    /// After inserting the new pane, equalize the size of all of
+    /// the siblings that are arranged along the same axis, so that
+    /// repeated splits produce even rows/columns.
+    /// The pane tree is binary, so a row of n panes is held as a
+    /// chain of splits in the same direction; layout consumers such
+    /// as tmux_layout present that chain as one split with n children.
+    /// Re-splitting a pane along its parent's axis adds an evenly sized
+    /// sibling to that chain, and the chain is kept flat, with a single
+    /// item on the left/top of each of its splits, rather than nesting
+    /// a half-sized pair.
+    pub balance_siblings: bool,
+    /// Insert the new pane as the left/top child of the split, rather
+    /// than the right/bottom child, moving the existing pane into the
+    /// second slot
+    pub insert_before: bool,
+    /// The fraction of the space, excluding the divider, to give to
+    /// the first child of the split, in the range (0.0, 1.0).
+    /// The result is clamped so that each child keeps at least 1 cell.
+    /// When None, the space is halved.
+    pub ratio: Option<f32>,
+}
+
+/// The preset arrangements that cycle_layout steps through,
+/// modelled after the tmux layouts of the same names
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LayoutPreset {
+    /// All panes side by side, with equal widths
+    EvenHorizontal,
+    /// All panes stacked top to bottom, with equal heights
+    EvenVertical,
+    /// The first pane on the left, the others stacked on the right
+    MainVertical,
+    /// The first pane at the top, the others side by side below it
+    MainHorizontal,
+    /// The panes arranged in a grid of (nearly) equal sized cells
+    Tiled,
+}
+
+impl LayoutPreset {
+    pub fn next(self) -> Self {
+        match self {
+            Self::EvenHorizontal => Self::EvenVertical,
+            Self::EvenVertical => Self::MainVertical,
+            Self::MainVertical => Self::MainHorizontal,
+            Self::MainHorizontal => Self::Tiled,
+            Self::Tiled => Self::EvenHorizontal,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct PositionedSplit {
     /// The topological node index that can be used to reference this split
@@ -140,6 +291,121 @@ pub struct PositionedSplit {
     /// For Horizontal splits, how tall the split should be, for Vertical
     /// splits how wide it should be
     pub size: usize,
+    /// The style assigned via Tab::set_split_style, if any
+    pub style: Option<SplitStyle>,
+}
+
+/// A hint to the renderer about how to draw the divider of a split;
+/// for example, to color code the domain of the panes it separates
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SplitStyle {
+    pub color: RgbColor,
+}
+
+/// A scaled down rendering of the content of a tab, as produced
+/// by Tab::thumbnail, suitable for an overview of several tabs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThumbnailGrid {
+    pub cols: usize,
+    pub rows: usize,
+    /// The sampled characters, indexed by row and then column
+    pub cells: Vec<Vec<char>>,
+}
+
+impl ThumbnailGrid {
+    /// Returns the rows of the thumbnail as strings
+    pub fn lines(&self) -> Vec<String> {
+        self.cells.iter().map(|row| row.iter().collect()).collect()
+    }
+}
+
+/// A (left, top, width, height) rectangle, in cells, relative to
+/// the top left corner of the containing tab
+pub type CellRect = (usize, usize, usize, usize);
+
+/// Describes the layout of the panes in a tab, mirroring the
+/// structure of its split tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutNode {
+    Empty,
+    Leaf {
+        /// The topological pane index
+        index: usize,
+        pane_id: PaneId,
+        rect: CellRect,
+    },
+    Split {
+        /// The topological split index, as used by iter_splits
+        index: usize,
+        direction: SplitDirection,
+        /// The area occupied by the split and both of its children
+        rect: CellRect,
+        /// The area occupied by the divider between the children
+        divider: CellRect,
+        first: Box<LayoutNode>,
+        second: Box<LayoutNode>,
+    },
+}
+
+fn layout_node(
+    tree: &Tree,
+    left: usize,
+    top: usize,
+    size: &PtySize,
+    pane_index: &mut usize,
+    split_index: &mut usize,
+) -> LayoutNode {
+    match tree {
+        Tree::Empty | Tree::Node { data: None, .. } => LayoutNode::Empty,
+        Tree::Node {
+            left: first,
+            right: second,
+            data: Some(data),
+        } => {
+            let index = *split_index;
+            *split_index += 1;
+            let divider = match data.direction {
+                SplitDirection::Horizontal => (
+                    left + data.first.cols as usize,
+                    top,
+                    1,
+                    data.height() as usize,
+                ),
+                SplitDirection::Vertical => (
+                    left,
+                    top + data.first.rows as usize,
+                    data.width() as usize,
+                    1,
+                ),
+            };
+            let first = layout_node(&*first, left, top, &data.first, pane_index, split_index);
+            let second = layout_node(
+                &*second,
+                left + data.left_of_second(),
+                top + data.top_of_second(),
+                &data.second,
+                pane_index,
+                split_index,
+            );
+            LayoutNode::Split {
+                index,
+                direction: data.direction,
+                rect: (left, top, data.width() as usize, data.height() as usize),
+                divider,
+                first: Box::new(first),
+                second: Box::new(second),
+            }
+        }
+        Tree::Leaf(pane) => {
+            let index = *pane_index;
+            *pane_index += 1;
+            LayoutNode::Leaf {
+                index,
+                pane_id: pane.pane_id(),
+                rect: (left, top, size.cols as usize, size.rows as usize),
+            }
+        }
+    }
 }
 
 fn is_pane(pane: &Rc<dyn Pane>, other: &Option<&Rc<dyn Pane>>) -> bool {
@@ -225,28 +491,33 @@ where
 }
 
 /// Computes the minimum (x, y) size based on the panes in this portion
-/// of the tree.
-fn compute_min_size(tree: &mut Tree) -> (usize, usize) {
+/// of the tree, where min_pane is the minimum (x, y) size of a pane.
+fn compute_min_size(tree: &mut Tree, min_pane: (usize, usize)) -> (usize, usize) {
     match tree {
-        Tree::Node { data: None, .. } | Tree::Empty => (1, 1),
+        Tree::Node { data: None, .. } | Tree::Empty => min_pane,
         Tree::Node {
             left,
             right,
             data: Some(data),
         } => {
-            let (left_x, left_y) = compute_min_size(&mut *left);
-            let (right_x, right_y) = compute_min_size(&mut *right);
+            let (left_x, left_y) = compute_min_size(&mut *left, min_pane);
+            let (right_x, right_y) = compute_min_size(&mut *right, min_pane);
             match data.direction {
                 SplitDirection::Vertical => (left_x.max(right_x), left_y + right_y + 1),
                 SplitDirection::Horizontal => (left_x + right_x + 1, left_y.max(right_y)),
             }
         }
-        Tree::Leaf(_) => (1, 1),
+        Tree::Leaf(_) => min_pane,
     }
 }
 
-fn adjust_x_size(tree: &mut Tree, mut x_adjust: isize, cell_dimensions: &PtySize) {
-    let (min_x, _) = compute_min_size(tree);
+fn adjust_x_size(
+    tree: &mut Tree,
+    mut x_adjust: isize,
+    cell_dimensions: &PtySize,
+    min_pane: (usize, usize),
+) {
+    let (min_x, _) = compute_min_size(tree, min_pane);
     while x_adjust != 0 {
         match tree {
             Tree::Empty | Tree::Leaf(_) => return,
@@ -263,26 +534,26 @@ fn adjust_x_size(tree: &mut Tree, mut x_adjust: isize, cell_dimensions: &PtySize
                     x_adjust = new_cols.saturating_sub(data.first.cols as isize);
 
                     if x_adjust != 0 {
-                        adjust_x_size(&mut *left, x_adjust, cell_dimensions);
+                        adjust_x_size(&mut *left, x_adjust, cell_dimensions, min_pane);
                         data.first.cols = new_cols.try_into().unwrap();
                         data.first.pixel_width =
                             data.first.cols.saturating_mul(cell_dimensions.pixel_width);
 
-                        adjust_x_size(&mut *right, x_adjust, cell_dimensions);
+                        adjust_x_size(&mut *right, x_adjust, cell_dimensions, min_pane);
                         data.second.cols = data.first.cols;
                         data.second.pixel_width = data.first.pixel_width;
                     }
                     return;
                 }
                 SplitDirection::Horizontal if x_adjust > 0 => {
-                    adjust_x_size(&mut *left, 1, cell_dimensions);
+                    adjust_x_size(&mut *left, 1, cell_dimensions, min_pane);
                     data.first.cols += 1;
                     data.first.pixel_width =
                         data.first.cols.saturating_mul(cell_dimensions.pixel_width);
                     x_adjust -= 1;
 
                     if x_adjust > 0 {
-                        adjust_x_size(&mut *right, 1, cell_dimensions);
+                        adjust_x_size(&mut *right, 1, cell_dimensions, min_pane);
                         data.second.cols += 1;
                         data.second.pixel_width =
                             data.second.cols.saturating_mul(cell_dimensions.pixel_width);
@@ -290,29 +561,42 @@ fn adjust_x_size(tree: &mut Tree, mut x_adjust: isize, cell_dimensions: &PtySize
                     }
                 }
                 SplitDirection::Horizontal => {
-                    // x_adjust is negative
-                    if data.first.cols > 1 {
-                        adjust_x_size(&mut *left, -1, cell_dimensions);
+                    // x_adjust is negative; take a cell from each side in
+                    // turn, without going below the minimum size of either
+                    let (left_min, _) = compute_min_size(&mut *left, min_pane);
+                    let (right_min, _) = compute_min_size(&mut *right, min_pane);
+                    let prior_adjust = x_adjust;
+                    if data.first.cols as usize > left_min {
+                        adjust_x_size(&mut *left, -1, cell_dimensions, min_pane);
                         data.first.cols -= 1;
                         data.first.pixel_width =
                             data.first.cols.saturating_mul(cell_dimensions.pixel_width);
                         x_adjust += 1;
                     }
-                    if x_adjust < 0 && data.second.cols > 1 {
-                        adjust_x_size(&mut *right, -1, cell_dimensions);
+                    if x_adjust < 0 && data.second.cols as usize > right_min {
+                        adjust_x_size(&mut *right, -1, cell_dimensions, min_pane);
                         data.second.cols -= 1;
                         data.second.pixel_width =
                             data.second.cols.saturating_mul(cell_dimensions.pixel_width);
                         x_adjust += 1;
                     }
+                    if x_adjust == prior_adjust {
+                        // Neither side can shrink any further
+                        return;
+                    }
                 }
             },
         }
     }
 }
 
-fn adjust_y_size(tree: &mut Tree, mut y_adjust: isize, cell_dimensions: &PtySize) {
-    let (_, min_y) = compute_min_size(tree);
+fn adjust_y_size(
+    tree: &mut Tree,
+    mut y_adjust: isize,
+    cell_dimensions: &PtySize,
+    min_pane: (usize, usize),
+) {
+    let (_, min_y) = compute_min_size(tree, min_pane);
     while y_adjust != 0 {
         match tree {
             Tree::Empty | Tree::Leaf(_) => return,
@@ -329,25 +613,25 @@ fn adjust_y_size(tree: &mut Tree, mut y_adjust: isize, cell_dimensions: &PtySize
                     y_adjust = new_rows.saturating_sub(data.first.rows as isize);
 
                     if y_adjust != 0 {
-                        adjust_y_size(&mut *left, y_adjust, cell_dimensions);
+                        adjust_y_size(&mut *left, y_adjust, cell_dimensions, min_pane);
                         data.first.rows = new_rows.try_into().unwrap();
                         data.first.pixel_height =
                             data.first.rows.saturating_mul(cell_dimensions.pixel_height);
 
-                        adjust_y_size(&mut *right, y_adjust, cell_dimensions);
+                        adjust_y_size(&mut *right, y_adjust, cell_dimensions, min_pane);
                         data.second.rows = data.first.rows;
                         data.second.pixel_height = data.first.pixel_height;
                     }
                     return;
                 }
                 SplitDirection::Vertical if y_adjust > 0 => {
-                    adjust_y_size(&mut *left, 1, cell_dimensions);
+                    adjust_y_size(&mut *left, 1, cell_dimensions, min_pane);
                     data.first.rows += 1;
                     data.first.pixel_height =
                         data.first.rows.saturating_mul(cell_dimensions.pixel_height);
                     y_adjust -= 1;
                     if y_adjust > 0 {
-                        adjust_y_size(&mut *right, 1, cell_dimensions);
+                        adjust_y_size(&mut *right, 1, cell_dimensions, min_pane);
                         data.second.rows += 1;
                         data.second.pixel_height = data
                             .second
@@ -357,16 +641,20 @@ fn adjust_y_size(tree: &mut Tree, mut y_adjust: isize, cell_dimensions: &PtySize
                     }
                 }
                 SplitDirection::Vertical => {
-                    // y_adjust is negative
-                    if data.first.rows > 1 {
-                        adjust_y_size(&mut *left, -1, cell_dimensions);
+                    // y_adjust is negative; take a cell from each side in
+                    // turn, without going below the minimum size of either
+                    let (_, left_min) = compute_min_size(&mut *left, min_pane);
+                    let (_, right_min) = compute_min_size(&mut *right, min_pane);
+                    let prior_adjust = y_adjust;
+                    if data.first.rows as usize > left_min {
+                        adjust_y_size(&mut *left, -1, cell_dimensions, min_pane);
                         data.first.rows -= 1;
                         data.first.pixel_height =
                             data.first.rows.saturating_mul(cell_dimensions.pixel_height);
                         y_adjust += 1;
                     }
-                    if y_adjust < 0 && data.second.rows > 1 {
-                        adjust_y_size(&mut *right, -1, cell_dimensions);
+                    if y_adjust < 0 && data.second.rows as usize > right_min {
+                        adjust_y_size(&mut *right, -1, cell_dimensions, min_pane);
                         data.second.rows -= 1;
                         data.second.pixel_height = data
                             .second
@@ -374,1487 +662,8329 @@ fn adjust_y_size(tree: &mut Tree, mut y_adjust: isize, cell_dimensions: &PtySize
                             .saturating_mul(cell_dimensions.pixel_height);
                         y_adjust += 1;
                     }
+                    if y_adjust == prior_adjust {
+                        // Neither side can shrink any further
+                        return;
+                    }
                 }
             },
         }
     }
 }
 
-fn apply_sizes_from_splits(tree: &Tree, size: &PtySize) {
+/// Returns the number of items that are arranged side by side in
+/// the given direction by the chain of same-direction splits at the
+/// top of tree.
+fn count_items_along(tree: &Tree, direction: SplitDirection) -> usize {
     match tree {
-        Tree::Empty => return,
-        Tree::Node { data: None, .. } => return,
         Tree::Node {
             left,
             right,
             data: Some(data),
-        } => {
-            apply_sizes_from_splits(&*left, &data.first);
-            apply_sizes_from_splits(&*right, &data.second);
-        }
-        Tree::Leaf(pane) => {
-            pane.resize(*size).ok();
+        } if data.direction == direction => {
+            count_items_along(&*left, direction) + count_items_along(&*right, direction)
         }
+        _ => 1,
     }
 }
 
-fn cell_dimensions(size: &PtySize) -> PtySize {
-    PtySize {
-        rows: 1,
-        cols: 1,
-        pixel_width: size.pixel_width / size.cols,
-        pixel_height: size.pixel_height / size.rows,
+/// Returns the number of panes and the number of splits in tree
+fn count_leaves_and_splits(tree: &Tree) -> (usize, usize) {
+    match tree {
+        Tree::Empty => (0, 0),
+        Tree::Leaf(_) => (1, 0),
+        Tree::Node { left, right, data } => {
+            let (left_panes, left_splits) = count_leaves_and_splits(&*left);
+            let (right_panes, right_splits) = count_leaves_and_splits(&*right);
+            let this_split = if data.is_some() { 1 } else { 0 };
+            (
+                left_panes + right_panes,
+                left_splits + right_splits + this_split,
+            )
+        }
     }
 }
 
-impl Tab {
-    pub fn new(size: &PtySize) -> Self {
-        Self {
-            id: TAB_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed),
-            pane: RefCell::new(Some(Tree::new())),
-            size: RefCell::new(*size),
-            active: RefCell::new(0),
-            zoomed: RefCell::new(None),
+/// Divides total cells between count items, leaving room for
+/// the dividers between them.  The items are as close to equal in
+/// size as possible; any remainder is given to the left/top items.
+fn even_item_sizes(total: u16, count: usize) -> Vec<u16> {
+    let count = count.max(1);
+    let available = (total as usize).saturating_sub(count - 1);
+    let base = available / count;
+    let remainder = available % count;
+    (0..count)
+        .map(|idx| (base + if idx < remainder { 1 } else { 0 }).max(1) as u16)
+        .collect()
+}
+
+/// Divides total cells between the items in proportion to fractions,
+/// leaving room for the dividers between them.  The boundaries between
+/// items are rounded to the nearest cell so that the sizes add up, and
+/// every item keeps at least one cell.
+fn fractional_item_sizes(total: u16, fractions: &[f32]) -> Vec<u16> {
+    let count = fractions.len().max(1);
+    let available = (total as usize).saturating_sub(count - 1);
+    let sum: f32 = fractions.iter().sum();
+    let mut cumulative = 0.0;
+    let mut prior = 0;
+    let mut sizes: Vec<usize> = fractions
+        .iter()
+        .map(|fraction| {
+            cumulative += fraction;
+            let boundary = ((available as f32 * cumulative / sum).round() as usize).min(available);
+            let size = boundary - prior;
+            prior = boundary;
+            size
+        })
+        .collect();
+
+    // Give each empty item a cell, taken from the largest item
+    for idx in 0..sizes.len() {
+        if sizes[idx] == 0 {
+            sizes[idx] = 1;
+            if let Some(largest) = sizes.iter_mut().max() {
+                if *largest > 1 {
+                    *largest -= 1;
+                }
+            }
         }
     }
+    sizes.into_iter().map(|size| size as u16).collect()
+}
 
-    /// Called by the multiplexer client when building a local tab to
-    /// mirror a remote tab.  The supplied `root` is the information
-    /// about our counterpart in the the remote server.
-    /// This method builds a local tree based on the remote tree which
-    /// then replaces the local tree structure.
-    ///
-    /// The `make_pane` function is provided by the caller, and its purpose
-    /// is to lookup an existing Pane that corresponds to the provided
-    /// PaneEntry, or to create a new Pane from that entry.
-    /// make_pane is expected to add the pane to the mux if it creates
-    /// a new pane, otherwise the pane won't poll/update in the GUI.
-    pub fn sync_with_pane_tree<F>(&self, size: PtySize, root: PaneNode, mut make_pane: F)
-    where
-        F: FnMut(PaneEntry) -> Rc<dyn Pane>,
-    {
-        let mut active = None;
-        let mut zoomed = None;
-
-        log::debug!("sync_with_pane_tree with size {:?}", size);
-
-        let t = build_from_pane_tree(root.into_tree(), &mut active, &mut zoomed, &mut make_pane);
-        let mut cursor = t.cursor();
-
-        *self.active.borrow_mut() = 0;
-        if let Some(active) = active {
-            // Resolve the active pane to its index
-            let mut index = 0;
-            loop {
-                if let Some(pane) = cursor.leaf_mut() {
-                    if active.pane_id() == pane.pane_id() {
-                        // Found it
-                        *self.active.borrow_mut() = index;
-                        break;
-                    }
-                    index += 1;
+/// Assigns sizes (along the axis of direction) to the items that are
+/// arranged by the chain of same-direction splits at the top of tree,
+/// where current is the size that tree currently occupies along that
+/// axis.  The nested perpendicular splits are adjusted to fit, but
+/// the panes are not resized; use apply_sizes_from_splits for that.
+/// Returns the revised size of tree along the axis.
+fn apply_item_sizes(
+    tree: &mut Tree,
+    direction: SplitDirection,
+    sizes: &[u16],
+    current: u16,
+    cell_dimensions: &PtySize,
+    min_pane: (usize, usize),
+) -> u16 {
+    match tree {
+        Tree::Node {
+            left,
+            right,
+            data: Some(data),
+        } if data.direction == direction => {
+            let num_left = count_items_along(&*left, direction);
+            let (sizes_left, sizes_right) = sizes.split_at(num_left);
+            match direction {
+                SplitDirection::Horizontal => {
+                    let first = apply_item_sizes(
+                        &mut *left,
+                        direction,
+                        sizes_left,
+                        data.first.cols,
+                        cell_dimensions,
+                        min_pane,
+                    );
+                    let second = apply_item_sizes(
+                        &mut *right,
+                        direction,
+                        sizes_right,
+                        data.second.cols,
+                        cell_dimensions,
+                        min_pane,
+                    );
+                    data.first.cols = first;
+                    data.first.pixel_width = first.saturating_mul(cell_dimensions.pixel_width);
+                    data.second.cols = second;
+                    data.second.pixel_width = second.saturating_mul(cell_dimensions.pixel_width);
+                    first + second + 1
                 }
-                match cursor.preorder_next() {
-                    Ok(c) => cursor = c,
-                    Err(c) => {
-                        // Didn't find it
-                        cursor = c;
-                        break;
-                    }
+                SplitDirection::Vertical => {
+                    let first = apply_item_sizes(
+                        &mut *left,
+                        direction,
+                        sizes_left,
+                        data.first.rows,
+                        cell_dimensions,
+                        min_pane,
+                    );
+                    let second = apply_item_sizes(
+                        &mut *right,
+                        direction,
+                        sizes_right,
+                        data.second.rows,
+                        cell_dimensions,
+                        min_pane,
+                    );
+                    data.first.rows = first;
+                    data.first.pixel_height = first.saturating_mul(cell_dimensions.pixel_height);
+                    data.second.rows = second;
+                    data.second.pixel_height = second.saturating_mul(cell_dimensions.pixel_height);
+                    first + second + 1
                 }
             }
         }
-        self.pane.borrow_mut().replace(cursor.tree());
-        *self.zoomed.borrow_mut() = zoomed;
-        *self.size.borrow_mut() = size;
-
-        self.resize(size);
+        _ => {
+            let delta = sizes[0] as isize - current as isize;
+            match direction {
+                SplitDirection::Horizontal => adjust_x_size(tree, delta, cell_dimensions, min_pane),
+                SplitDirection::Vertical => adjust_y_size(tree, delta, cell_dimensions, min_pane),
+            }
+            sizes[0]
+        }
+    }
+}
 
-        log::debug!(
-            "sync tab: {:#?} zoomed: {} {:#?}",
-            size,
-            self.zoomed.borrow().is_some(),
-            self.iter_panes()
-        );
-        assert!(self.pane.borrow().is_some());
+/// Equalizes the items of every chain of splits of the given direction
+/// within tree, from the outermost chain inwards.  Splits of the other
+/// direction are adjusted to fit but keep their proportions.
+/// within_chain is true if tree is part of a chain that has already
+/// been balanced by the caller.
+fn balance_chains_along(
+    tree: &mut Tree,
+    direction: SplitDirection,
+    within_chain: bool,
+    cell_dimensions: &PtySize,
+    min_pane: (usize, usize),
+) {
+    let extent = match tree {
+        Tree::Node {
+            data: Some(data), ..
+        } if data.direction == direction => Some(match direction {
+            SplitDirection::Horizontal => data.size().cols,
+            SplitDirection::Vertical => data.size().rows,
+        }),
+        _ => None,
+    };
+    if let Some(current) = extent {
+        if !within_chain {
+            let count = count_items_along(tree, direction);
+            let sizes = even_item_sizes(current, count);
+            apply_item_sizes(tree, direction, &sizes, current, cell_dimensions, min_pane);
+        }
+    }
+    if let Tree::Node { left, right, .. } = tree {
+        let is_chain = extent.is_some();
+        balance_chains_along(&mut *left, direction, is_chain, cell_dimensions, min_pane);
+        balance_chains_along(&mut *right, direction, is_chain, cell_dimensions, min_pane);
     }
+}
 
-    pub fn codec_pane_tree(&self) -> PaneNode {
-        let mux = Mux::get().unwrap();
-        let tab_id = self.id;
-        let window_id = match mux.window_containing_tab(tab_id) {
-            Some(w) => w,
-            None => {
-                log::error!("no window contains tab {}", tab_id);
-                return PaneNode::Empty;
-            }
+/// Rearranges the chain of splits of the given direction at the top of
+/// tree so that the left/top child of each split in the chain is a
+/// single item, leaving the chain as a flat list of siblings rather
+/// than a nest.  The order and sizes of the items are unchanged.
+fn flatten_chain(tree: &mut Tree, direction: SplitDirection) {
+    loop {
+        let nested = match tree {
+            Tree::Node {
+                left,
+                data: Some(data),
+                ..
+            } if data.direction == direction => match &**left {
+                Tree::Node {
+                    data: Some(inner), ..
+                } => inner.direction == direction,
+                _ => false,
+            },
+            _ => false,
         };
+        if !nested {
+            break;
+        }
 
-        let workspace = match mux
-            .get_window(window_id)
-            .map(|w| w.get_workspace().to_string())
+        // ((x | y) | z) => (x | (y | z))
+        if let Tree::Node {
+            left,
+            right: z,
+            data: Some(outer),
+        } = std::mem::replace(tree, Tree::Empty)
         {
-            Some(ws) => ws,
-            None => {
-                log::error!("window id {} doesn't have a window!?", window_id);
-                return PaneNode::Empty;
+            if let Tree::Node {
+                left: x,
+                right: y,
+                data: Some(inner),
+            } = *left
+            {
+                let inner_data = SplitDirectionAndSize {
+                    direction,
+                    first: inner.second,
+                    second: outer.second,
+                };
+                *tree = Tree::Node {
+                    left: x,
+                    right: Box::new(Tree::Node {
+                        left: y,
+                        right: z,
+                        data: Some(inner_data),
+                    }),
+                    data: Some(SplitDirectionAndSize {
+                        direction,
+                        first: inner.first,
+                        second: inner_data.size(),
+                    }),
+                };
             }
-        };
-
-        let zoomed = self.zoomed.borrow();
-        let active = self.get_active_pane();
-        if let Some(root) = self.pane.borrow().as_ref() {
-            pane_tree(
-                root,
-                tab_id,
-                window_id,
-                active.as_ref(),
-                zoomed.as_ref(),
-                &workspace,
-            )
-        } else {
-            PaneNode::Empty
         }
     }
 
-    /// Returns a count of how many panes are in this tab
-    pub fn count_panes(&self) -> usize {
-        let mut count = 0;
-        let mut root = self.pane.borrow_mut();
-        let mut cursor = root.take().unwrap().cursor();
-
-        loop {
-            if cursor.is_leaf() {
-                count += 1;
-            }
-            match cursor.preorder_next() {
-                Ok(c) => cursor = c,
-                Err(c) => {
-                    root.replace(c.tree());
-                    return count;
-                }
-            }
+    if let Tree::Node {
+        right,
+        data: Some(data),
+        ..
+    } = tree
+    {
+        if data.direction == direction {
+            flatten_chain(&mut *right, direction);
         }
     }
+}
 
-    pub fn set_zoomed(&self, zoomed: bool) {
-        if self.zoomed.borrow().is_some() == zoomed {
-            // Current zoom state matches intended zoom state,
-            // so we have nothing to do.
-            return;
+/// Makes a copy of the structure and split sizes of tree, sharing
+/// the pane instances, so that layout changes can be modelled
+/// without affecting the real panes.
+fn clone_tree(tree: &Tree) -> Tree {
+    match tree {
+        Tree::Empty => Tree::Empty,
+        Tree::Node { left, right, data } => Tree::Node {
+            left: Box::new(clone_tree(&*left)),
+            right: Box::new(clone_tree(&*right)),
+            data: *data,
+        },
+        Tree::Leaf(pane) => Tree::Leaf(Rc::clone(pane)),
+    }
+}
+
+/// Collects the width of each pane in tree, in topological order
+fn collect_pane_widths(tree: &Tree, size: &PtySize, widths: &mut Vec<u16>) {
+    match tree {
+        Tree::Empty | Tree::Node { data: None, .. } => {}
+        Tree::Node {
+            left,
+            right,
+            data: Some(data),
+        } => {
+            collect_pane_widths(&*left, &data.first, widths);
+            collect_pane_widths(&*right, &data.second, widths);
         }
-        self.toggle_zoom();
+        Tree::Leaf(_) => widths.push(size.cols),
     }
+}
 
-    pub fn toggle_zoom(&self) {
-        let size = *self.size.borrow();
-        if self.zoomed.borrow_mut().take().is_some() {
-            // We were zoomed, but now we are not.
-            // Re-apply the size to the panes
-            if let Some(pane) = self.get_active_pane() {
-                pane.set_zoomed(false);
+fn apply_sizes_from_splits(tree: &Tree, size: &PtySize, gutter: u16, header: u16) {
+    fn apply(tree: &Tree, size: &PtySize, insets: (u16, u16), after_x: bool, after_y: bool) {
+        match tree {
+            Tree::Empty | Tree::Node { data: None, .. } => {}
+            Tree::Node {
+                left,
+                right,
+                data: Some(data),
+            } => {
+                apply(&*left, &data.first, insets, after_x, after_y);
+                let (after_x, after_y) = match data.direction {
+                    SplitDirection::Horizontal => (true, after_y),
+                    SplitDirection::Vertical => (after_x, true),
+                };
+                apply(&*right, &data.second, insets, after_x, after_y);
             }
-
-            let mut root = self.pane.borrow_mut();
-            apply_sizes_from_splits(root.as_mut().unwrap(), &size);
-        } else {
-            // We weren't zoomed, but now we want to zoom.
-            // Locate the active pane
-            if let Some(pane) = self.get_active_pane() {
-                pane.set_zoomed(true);
-                pane.resize(size).ok();
-                self.zoomed.borrow_mut().replace(pane);
+            Tree::Leaf(pane) => {
+                let (gutter, header) = insets;
+                let size = inset_for_gutter(*size, gutter, after_x, after_y);
+                pane.resize(inset_for_header(size, header)).ok();
             }
         }
     }
+    apply(tree, size, (gutter, header), false, false)
+}
 
-    pub fn contains_pane(&self, pane: PaneId) -> bool {
-        fn contains(tree: &Tree, pane: PaneId) -> bool {
-            match tree {
-                Tree::Empty => false,
-                Tree::Node { left, right, .. } => contains(left, pane) || contains(right, pane),
-                Tree::Leaf(p) => p.pane_id() == pane,
-            }
+/// Returns true if every pane in tree is dead
+fn all_panes_dead(tree: &Tree) -> bool {
+    match tree {
+        Tree::Empty => true,
+        Tree::Node { left, right, .. } => all_panes_dead(left) && all_panes_dead(right),
+        Tree::Leaf(pane) => pane.is_dead(),
+    }
+}
+
+/// Arranges for the specified panes to be removed from the mux
+fn remove_from_mux(pane_ids: Vec<PaneId>) {
+    promise::spawn::spawn_into_main_thread(async move {
+        let mux = Mux::get().unwrap();
+        for pane_id in pane_ids.into_iter() {
+            mux.remove_pane(pane_id);
         }
-        match &*self.pane.borrow() {
-            Some(root) => contains(root, pane),
-            None => false,
+    })
+    .detach();
+}
+
+/// Shrinks the slot size of a pane that follows a divider along x
+/// and/or y by the pane gutter, leaving at least one cell
+fn inset_for_gutter(size: PtySize, gutter: u16, after_x: bool, after_y: bool) -> PtySize {
+    let shrink = |cells: u16, pixels: u16, after: bool| {
+        if !after || gutter == 0 || cells == 0 {
+            return (cells, pixels);
         }
+        let inset = cells.saturating_sub(gutter).max(1);
+        (inset, pixels / cells * inset)
+    };
+    let (cols, pixel_width) = shrink(size.cols, size.pixel_width, after_x);
+    let (rows, pixel_height) = shrink(size.rows, size.pixel_height, after_y);
+    PtySize {
+        rows,
+        cols,
+        pixel_width,
+        pixel_height,
     }
+}
 
-    /// Walks the pane tree to produce the topologically ordered flattened
-    /// list of PositionedPane instances along with their positioning information.
-    pub fn iter_panes(&self) -> Vec<PositionedPane> {
-        self.iter_panes_impl(true)
+/// Shrinks the slot size of a pane by the rows reserved for its
+/// header, leaving at least one row
+fn inset_for_header(size: PtySize, header: u16) -> PtySize {
+    if header == 0 || size.rows == 0 {
+        return size;
     }
-
-    /// Like iter_panes, except that it will include all panes, regardless of
-    /// whether one of them is currently zoomed.
-    pub fn iter_panes_ignoring_zoom(&self) -> Vec<PositionedPane> {
-        self.iter_panes_impl(false)
+    let rows = size.rows.saturating_sub(header).max(1);
+    PtySize {
+        rows,
+        pixel_height: size.pixel_height / size.rows * rows,
+        ..size
     }
+}
 
-    fn iter_panes_impl(&self, respect_zoom_state: bool) -> Vec<PositionedPane> {
-        let mut panes = vec![];
-
-        if respect_zoom_state {
-            if let Some(zoomed) = self.zoomed.borrow().as_ref() {
-                let size = *self.size.borrow();
-                panes.push(PositionedPane {
-                    index: 0,
-                    is_active: true,
-                    is_zoomed: true,
-                    left: 0,
-                    top: 0,
-                    width: size.cols.into(),
-                    pixel_width: size.pixel_width.into(),
-                    height: size.rows.into(),
-                    pixel_height: size.pixel_height.into(),
-                    pane: Rc::clone(zoomed),
-                });
-                return panes;
-            }
-        }
+fn serialize_tree(tree: &Tree) -> SerializedTab {
+    match tree {
+        Tree::Empty | Tree::Node { data: None, .. } => SerializedTab::Empty,
+        Tree::Leaf(pane) => SerializedTab::Pane(pane.pane_id()),
+        Tree::Node {
+            left,
+            right,
+            data: Some(split),
+        } => SerializedTab::Split {
+            split: *split,
+            first: Box::new(serialize_tree(&*left)),
+            second: Box::new(serialize_tree(&*right)),
+        },
+    }
+}
 
-        let active_idx = *self.active.borrow();
-        let mut root = self.pane.borrow_mut();
-        let mut cursor = root.take().unwrap().cursor();
+fn tree_from_serialized<F>(layout: &SerializedTab, resolve: &F) -> Tree
+where
+    F: Fn(PaneId) -> Rc<dyn Pane>,
+{
+    match layout {
+        SerializedTab::Empty => Tree::Empty,
+        SerializedTab::Pane(pane_id) => Tree::Leaf(resolve(*pane_id)),
+        SerializedTab::Split {
+            split,
+            first,
+            second,
+        } => Tree::Node {
+            left: Box::new(tree_from_serialized(first, resolve)),
+            right: Box::new(tree_from_serialized(second, resolve)),
+            data: Some(*split),
+        },
+    }
+}
 
-        loop {
-            if cursor.is_leaf() {
-                let index = panes.len();
-                let mut left = 0usize;
-                let mut top = 0usize;
-                let mut parent_size = None;
-                for (branch, node) in cursor.path_to_root() {
-                    if let Some(node) = node {
-                        if parent_size.is_none() {
-                            parent_size.replace(if branch == PathBranch::IsRight {
-                                node.second
-                            } else {
-                                node.first
-                            });
-                        }
-                        if branch == PathBranch::IsRight {
-                            top += node.top_of_second();
-                            left += node.left_of_second();
-                        }
-                    }
+/// Returns a copy of layout in which the split sizes have been scaled
+/// to fit size, keeping the proportions of each split.  Each child
+/// keeps at least 1 cell.
+fn scale_serialized(layout: &SerializedTab, size: &PtySize, cell_dims: &PtySize) -> SerializedTab {
+    match layout {
+        SerializedTab::Split {
+            split,
+            first,
+            second,
+        } => {
+            let divide = |total: u16, first: u16, second: u16| {
+                let avail = total.saturating_sub(1);
+                let weight = (first + second).max(1) as f32;
+                let first = ((avail as f32 * first as f32 / weight).round() as u16)
+                    .max(1)
+                    .min(avail.saturating_sub(1));
+                (first, avail.saturating_sub(first))
+            };
+            let sized = |cols: u16, rows: u16| PtySize {
+                rows,
+                cols,
+                pixel_width: cols * cell_dims.pixel_width,
+                pixel_height: rows * cell_dims.pixel_height,
+            };
+            let (first_size, second_size) = match split.direction {
+                SplitDirection::Horizontal => {
+                    let (a, b) = divide(size.cols, split.first.cols, split.second.cols);
+                    (sized(a, size.rows), sized(b, size.rows))
                 }
-
-                let pane = Rc::clone(cursor.leaf_mut().unwrap());
-                let dims = parent_size.unwrap_or_else(|| *self.size.borrow());
-
-                panes.push(PositionedPane {
-                    index,
-                    is_active: index == active_idx,
-                    is_zoomed: false,
-                    left,
-                    top,
-                    width: dims.cols as _,
-                    height: dims.rows as _,
-                    pixel_width: dims.pixel_width as _,
-                    pixel_height: dims.pixel_height as _,
-                    pane,
-                });
-            }
-
-            match cursor.preorder_next() {
-                Ok(c) => cursor = c,
-                Err(c) => {
-                    root.replace(c.tree());
-                    break;
+                SplitDirection::Vertical => {
+                    let (a, b) = divide(size.rows, split.first.rows, split.second.rows);
+                    (sized(size.cols, a), sized(size.cols, b))
                 }
+            };
+            SerializedTab::Split {
+                split: SplitDirectionAndSize {
+                    direction: split.direction,
+                    first: first_size,
+                    second: second_size,
+                },
+                first: Box::new(scale_serialized(first, &first_size, cell_dims)),
+                second: Box::new(scale_serialized(second, &second_size, cell_dims)),
             }
         }
+        layout => layout.clone(),
+    }
+}
 
-        panes
+/// Arranges the items side by side along the axis of direction, by
+/// building a chain of splits.  Each item is a tree together with
+/// the size that it occupies; the items are expected to have the same
+/// size along the perpendicular axis.
+/// Returns the tree and its overall size.
+fn chain_items(mut items: Vec<(Tree, PtySize)>, direction: SplitDirection) -> (Tree, PtySize) {
+    let (first_tree, first) = items.remove(0);
+    if items.is_empty() {
+        return (first_tree, first);
     }
+    let (second_tree, second) = chain_items(items, direction);
+    let data = SplitDirectionAndSize {
+        direction,
+        first,
+        second,
+    };
+    (
+        Tree::Node {
+            left: Box::new(first_tree),
+            right: Box::new(second_tree),
+            data: Some(data),
+        },
+        data.size(),
+    )
+}
 
-    pub fn iter_splits(&self) -> Vec<PositionedSplit> {
-        let mut dividers = vec![];
-        if self.zoomed.borrow().is_some() {
-            return dividers;
+/// Returns a copy of size with its extent along the axis of direction
+/// replaced by cells
+fn size_along(
+    size: &PtySize,
+    direction: SplitDirection,
+    cells: u16,
+    cell_dimensions: &PtySize,
+) -> PtySize {
+    let mut size = *size;
+    match direction {
+        SplitDirection::Horizontal => {
+            size.cols = cells;
+            size.pixel_width = cells.saturating_mul(cell_dimensions.pixel_width);
         }
+        SplitDirection::Vertical => {
+            size.rows = cells;
+            size.pixel_height = cells.saturating_mul(cell_dimensions.pixel_height);
+        }
+    }
+    size
+}
 
-        let mut root = self.pane.borrow_mut();
-        let mut cursor = root.take().unwrap().cursor();
-        let mut index = 0;
-
-        loop {
-            if !cursor.is_leaf() {
-                let mut left = 0usize;
-                let mut top = 0usize;
-                for (branch, p) in cursor.path_to_root() {
-                    if let Some(p) = p {
-                        if branch == PathBranch::IsRight {
-                            left += p.left_of_second();
-                            top += p.top_of_second();
-                        }
-                    }
-                }
-                if let Ok(Some(node)) = cursor.node_mut() {
-                    match node.direction {
-                        SplitDirection::Horizontal => left += node.first.cols as usize,
-                        SplitDirection::Vertical => top += node.first.rows as usize,
-                    }
+/// Arranges panes side by side along the axis of direction, with
+/// (nearly) equal sizes, to fill an area of the specified size
+fn even_chain(
+    panes: Vec<Rc<dyn Pane>>,
+    direction: SplitDirection,
+    size: &PtySize,
+    cell_dimensions: &PtySize,
+) -> (Tree, PtySize) {
+    let total = match direction {
+        SplitDirection::Horizontal => size.cols,
+        SplitDirection::Vertical => size.rows,
+    };
+    let sizes = even_item_sizes(total, panes.len());
+    let items = panes
+        .into_iter()
+        .zip(sizes)
+        .map(|(pane, cells)| {
+            (
+                Tree::Leaf(pane),
+                size_along(size, direction, cells, cell_dimensions),
+            )
+        })
+        .collect();
+    chain_items(items, direction)
+}
 
-                    dividers.push(PositionedSplit {
-                        index,
-                        direction: node.direction,
-                        left,
-                        top,
-                        size: if node.direction == SplitDirection::Horizontal {
-                            node.height() as usize
-                        } else {
-                            node.width() as usize
-                        },
-                    })
-                }
-                index += 1;
+/// Builds a tree that arranges panes according to preset, to fill
+/// an area of the specified size.  panes must not be empty.
+fn preset_tree(
+    preset: LayoutPreset,
+    mut panes: Vec<Rc<dyn Pane>>,
+    size: &PtySize,
+    cell_dimensions: &PtySize,
+) -> Tree {
+    match preset {
+        LayoutPreset::EvenHorizontal => {
+            even_chain(panes, SplitDirection::Horizontal, size, cell_dimensions).0
+        }
+        LayoutPreset::EvenVertical => {
+            even_chain(panes, SplitDirection::Vertical, size, cell_dimensions).0
+        }
+        LayoutPreset::MainVertical | LayoutPreset::MainHorizontal => {
+            let (main_direction, rest_direction) = if preset == LayoutPreset::MainVertical {
+                (SplitDirection::Horizontal, SplitDirection::Vertical)
+            } else {
+                (SplitDirection::Vertical, SplitDirection::Horizontal)
+            };
+            if panes.len() < 2 {
+                return even_chain(panes, main_direction, size, cell_dimensions).0;
             }
-
-            match cursor.preorder_next() {
-                Ok(c) => cursor = c,
-                Err(c) => {
-                    root.replace(c.tree());
-                    break;
-                }
+            let total = match main_direction {
+                SplitDirection::Horizontal => size.cols,
+                SplitDirection::Vertical => size.rows,
+            };
+            let sizes = even_item_sizes(total, 2);
+            let main = panes.remove(0);
+            let main_size = size_along(size, main_direction, sizes[0], cell_dimensions);
+            let rest_size = size_along(size, main_direction, sizes[1], cell_dimensions);
+            let rest = even_chain(panes, rest_direction, &rest_size, cell_dimensions);
+            chain_items(vec![(Tree::Leaf(main), main_size), rest], main_direction).0
+        }
+        LayoutPreset::Tiled => {
+            let mut num_cols = 1;
+            while num_cols * num_cols < panes.len() {
+                num_cols += 1;
             }
+            let num_rows = panes.len().div_ceil(num_cols);
+            let mut panes = panes.into_iter();
+            let rows = even_item_sizes(size.rows, num_rows)
+                .into_iter()
+                .map(|rows| {
+                    let row_size =
+                        size_along(size, SplitDirection::Vertical, rows, cell_dimensions);
+                    let row_panes = panes.by_ref().take(num_cols).collect();
+                    even_chain(
+                        row_panes,
+                        SplitDirection::Horizontal,
+                        &row_size,
+                        cell_dimensions,
+                    )
+                })
+                .collect();
+            chain_items(rows, SplitDirection::Vertical).0
         }
-
-        dividers
     }
+}
 
-    pub fn tab_id(&self) -> TabId {
-        self.id
+/// Collects, for each pane in tree, the topological indices of the
+/// splits on the path from the root down to that pane
+fn collect_split_paths(
+    tree: &Tree,
+    split_index: &mut usize,
+    path: &mut Vec<usize>,
+    paths: &mut Vec<Vec<usize>>,
+) {
+    match tree {
+        Tree::Empty => {}
+        Tree::Node { left, right, .. } => {
+            path.push(*split_index);
+            *split_index += 1;
+            collect_split_paths(&*left, split_index, path, paths);
+            collect_split_paths(&*right, split_index, path, paths);
+            path.pop();
+        }
+        Tree::Leaf(_) => paths.push(path.clone()),
     }
+}
 
-    pub fn get_size(&self) -> PtySize {
-        *self.size.borrow()
+/// Collects the ids of the panes in tree, in topological order
+fn collect_pane_ids(tree: &Tree, ids: &mut Vec<PaneId>) {
+    match tree {
+        Tree::Empty => {}
+        Tree::Node { left, right, .. } => {
+            collect_pane_ids(&*left, ids);
+            collect_pane_ids(&*right, ids);
+        }
+        Tree::Leaf(pane) => ids.push(pane.pane_id()),
     }
+}
 
-    /// Apply the new size of the tab to the panes contained within.
-    /// The delta between the current and the new size is computed,
-    /// and is distributed between the splits.  For small resizes
-    /// this algorithm biases towards adjusting the left/top nodes
-    /// first.  For large resizes this tends to proportionally adjust
-    /// the relative sizes of the elements in a split.
-    pub fn resize(&self, size: PtySize) {
-        if size.rows == 0 || size.cols == 0 {
-            // Ignore "impossible" resize requests
-            return;
+/// Returns the ids of the panes on the first and second sides of the
+/// split with the specified topological index, counting from
+/// *split_index
+fn split_sides(tree: &Tree, target: usize, split_index: &mut usize) -> Option<SplitSides> {
+    match tree {
+        Tree::Node { left, right, .. } => {
+            if *split_index == target {
+                let mut first = vec![];
+                let mut second = vec![];
+                collect_pane_ids(&*left, &mut first);
+                collect_pane_ids(&*right, &mut second);
+                return Some((first, second));
+            }
+            *split_index += 1;
+            split_sides(&*left, target, split_index)
+                .or_else(|| split_sides(&*right, target, split_index))
         }
+        _ => None,
+    }
+}
 
-        // Un-zoom first, so that the layout can be reasoned about
-        // more easily.
-        let was_zoomed = self.zoomed.borrow().is_some();
-        self.set_zoomed(false);
-
-        {
-            let mut root = self.pane.borrow_mut();
-            let dims = cell_dimensions(&size);
-            let (min_x, min_y) = compute_min_size(root.as_mut().unwrap());
-            let current_size = *self.size.borrow();
-
-            // Constrain the new size to the minimum possible dimensions
-            let cols = size.cols.max(min_x as u16);
-            let rows = size.rows.max(min_y as u16);
-            let size = PtySize {
-                rows,
-                cols,
-                pixel_width: cols * dims.pixel_width,
-                pixel_height: rows * dims.pixel_height,
-            };
-
-            if size != current_size {
-                // Update the split nodes with adjusted sizes
-                adjust_x_size(
-                    root.as_mut().unwrap(),
-                    cols as isize - current_size.cols as isize,
-                    &dims,
-                );
-                adjust_y_size(
-                    root.as_mut().unwrap(),
-                    rows as isize - current_size.rows as isize,
-                    &dims,
-                );
+/// Builds the tmux layout cell for tree, which occupies an area of
+/// the specified size at (x, y)
+fn tmux_layout_cell(tree: &Tree, x: usize, y: usize, size: &PtySize) -> Option<TmuxLayout> {
+    match tree {
+        Tree::Empty | Tree::Node { data: None, .. } => None,
+        Tree::Leaf(pane) => Some(TmuxLayout::Pane {
+            width: size.cols as usize,
+            height: size.rows as usize,
+            x,
+            y,
+            pane_id: pane.pane_id(),
+        }),
+        Tree::Node {
+            data: Some(data), ..
+        } => {
+            let mut children = vec![];
+            tmux_layout_children(tree, data.direction, x, y, size, &mut children);
+            Some(TmuxLayout::Split {
+                width: size.cols as usize,
+                height: size.rows as usize,
+                x,
+                y,
+                direction: data.direction,
+                children,
+            })
+        }
+    }
+}
 
-                *self.size.borrow_mut() = size;
+/// tmux arranges any number of cells in a row or column, so flatten
+/// the chain of same-direction splits at the top of tree into a list
+fn tmux_layout_children(
+    tree: &Tree,
+    direction: SplitDirection,
+    x: usize,
+    y: usize,
+    size: &PtySize,
+    children: &mut Vec<TmuxLayout>,
+) {
+    match tree {
+        Tree::Node {
+            left,
+            right,
+            data: Some(data),
+        } if data.direction == direction => {
+            tmux_layout_children(&*left, direction, x, y, &data.first, children);
+            tmux_layout_children(
+                &*right,
+                direction,
+                x + data.left_of_second(),
+                y + data.top_of_second(),
+                &data.second,
+                children,
+            );
+        }
+        _ => children.extend(tmux_layout_cell(tree, x, y, size)),
+    }
+}
 
-                // And then resize the individual panes to match
-                apply_sizes_from_splits(root.as_mut().unwrap(), &size);
+/// Move to the nth (preorder) split node from the current position;
+/// the split counterpart to Cursor::go_to_nth_leaf
+fn go_to_nth_split(mut cursor: Cursor, n: usize) -> Result<Cursor, Cursor> {
+    let mut next = 0;
+    loop {
+        if !cursor.is_leaf() {
+            if next == n {
+                return Ok(cursor);
             }
+            next += 1;
         }
-
-        // And finally restore the zoom, if appropriate
-        self.set_zoomed(was_zoomed);
+        cursor = cursor.preorder_next()?;
     }
+}
 
-    fn apply_pane_size(&self, pane_size: PtySize, cursor: &mut Cursor) {
-        let cell_width = pane_size.pixel_width / pane_size.cols;
-        let cell_height = pane_size.pixel_height / pane_size.rows;
-        if let Ok(Some(node)) = cursor.node_mut() {
-            // Adjust the size of the node; we preserve the size of the first
-            // child and adjust the second, so if we are split down the middle
-            // and the window is made wider, the right column will grow in
-            // size, leaving the left at its current width.
-            if node.direction == SplitDirection::Horizontal {
-                node.first.rows = pane_size.rows;
-                node.second.rows = pane_size.rows;
+/// Derives a stable accent color from a pane id.  The hue advances by
+/// the golden ratio for each id so that panes with nearby ids, which
+/// are the ones most likely to share a tab, are far apart on the wheel.
+pub fn pane_accent_color_for_id(pane_id: PaneId) -> RgbColor {
+    let hue = (pane_id as f64 * GOLDEN_RATIO as f64).fract() * 6.0;
+    let (saturation, value) = (0.6, 0.9);
+
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue % 2.0) - 1.0).abs());
+    let (red, green, blue) = match hue as usize {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let min = value - chroma;
+    RgbColor::new_f32(
+        (red + min) as f32,
+        (green + min) as f32,
+        (blue + min) as f32,
+    )
+}
 
-                node.second.cols = pane_size.cols.saturating_sub(1 + node.first.cols);
-            } else {
-                node.first.cols = pane_size.cols;
-                node.second.cols = pane_size.cols;
-
-                node.second.rows = pane_size.rows.saturating_sub(1 + node.first.rows);
-            }
-            node.first.pixel_width = node.first.cols * cell_width;
-            node.first.pixel_height = node.first.rows * cell_height;
+fn cell_dimensions(size: &PtySize) -> PtySize {
+    PtySize {
+        rows: 1,
+        cols: 1,
+        pixel_width: size.pixel_width / size.cols,
+        pixel_height: size.pixel_height / size.rows,
+    }
+}
 
-            node.second.pixel_width = node.second.cols * cell_width;
-            node.second.pixel_height = node.second.rows * cell_height;
+impl Tab {
+    pub fn new(size: &PtySize) -> Self {
+        Self {
+            id: TAB_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed),
+            pane: RefCell::new(Some(Tree::new())),
+            size: RefCell::new(*size),
+            active: RefCell::new(0),
+            zoomed: RefCell::new(None),
+            focus_locked: RefCell::new(false),
+            reserved_bottom_rows: RefCell::new(0),
+            active_pane_border: RefCell::new(0),
+            pane_header_rows: RefCell::new(0),
+            pane_gutter: RefCell::new(0),
+            min_pane_size: RefCell::new((1, 1)),
+            max_panes: RefCell::new(DEFAULT_MAX_PANES),
+            layout_preset: RefCell::new(None),
+            percentage_layout: RefCell::new(None),
+            split_styles: RefCell::new(HashMap::new()),
+            close_on_last_pane_death: RefCell::new(true),
+            unfocusable_panes: RefCell::new(HashSet::new()),
+            peeked: RefCell::new(None),
+            peek_factor: RefCell::new(DEFAULT_PEEK_FACTOR),
+            split_names: RefCell::new(HashMap::new()),
+            overlays: RefCell::new(vec![]),
+            active_history: RefCell::new(vec![]),
+            active_subscribers: RefCell::new(vec![]),
+            active_rect: RefCell::new(None),
+            triggered_panes: Rc::new(RefCell::new(vec![])),
         }
     }
 
-    /// Called when running in the mux server after an individual pane
-    /// has been resized.
-    /// Because the split manipulation happened on the GUI we "lost"
-    /// the information that would have allowed us to call resize_split_by()
-    /// and instead need to back-infer the split size information.
-    /// We rely on the client to have resized (or be in the process
-    /// of resizing) affected panes consistently with its own Tab
-    /// tree model.
-    /// This method does a simple tree walk to the leaves to back-propagate
-    /// the size of the panes up to their containing node split data.
-    /// Without this step, disconnecting and reconnecting would cause
-    /// the GUI to use stale size information for the window it spawns
-    /// to attach this tab.
-    pub fn rebuild_splits_sizes_from_contained_panes(&self) {
-        if self.zoomed.borrow().is_some() {
-            return;
-        }
+    /// Called by the multiplexer client when building a local tab to
+    /// mirror a remote tab.  The supplied `root` is the information
+    /// about our counterpart in the the remote server.
+    /// This method builds a local tree based on the remote tree which
+    /// then replaces the local tree structure.
+    ///
+    /// The `make_pane` function is provided by the caller, and its purpose
+    /// is to lookup an existing Pane that corresponds to the provided
+    /// PaneEntry, or to create a new Pane from that entry.
+    /// make_pane is expected to add the pane to the mux if it creates
+    /// a new pane, otherwise the pane won't poll/update in the GUI.
+    pub fn sync_with_pane_tree<F>(&self, size: PtySize, root: PaneNode, mut make_pane: F)
+    where
+        F: FnMut(PaneEntry) -> Rc<dyn Pane>,
+    {
+        let mut active = None;
+        let mut zoomed = None;
+        let prior_active = self.get_active_idx();
 
-        fn compute_size(node: &mut Tree) -> Option<PtySize> {
-            match node {
-                Tree::Empty => None,
-                Tree::Leaf(pane) => {
-                    let dims = pane.get_dimensions();
-                    let size = PtySize {
-                        cols: dims.cols as u16,
-                        rows: dims.viewport_rows as u16,
-                        pixel_height: 0,
-                        pixel_width: 0,
-                    };
-                    Some(size)
+        log::debug!("sync_with_pane_tree with size {:?}", size);
+
+        let t = build_from_pane_tree(root.into_tree(), &mut active, &mut zoomed, &mut make_pane);
+        let mut cursor = t.cursor();
+
+        *self.active.borrow_mut() = 0;
+        if let Some(active) = active {
+            // Resolve the active pane to its index
+            let mut index = 0;
+            loop {
+                if let Some(pane) = cursor.leaf_mut() {
+                    if active.pane_id() == pane.pane_id() {
+                        // Found it
+                        *self.active.borrow_mut() = index;
+                        break;
+                    }
+                    index += 1;
                 }
-                Tree::Node { left, right, data } => {
-                    if let Some(data) = data {
-                        if let Some(first) = compute_size(left) {
-                            data.first = first;
-                        }
-                        if let Some(second) = compute_size(right) {
-                            data.second = second;
-                        }
-                        Some(data.size())
-                    } else {
-                        None
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(c) => {
+                        // Didn't find it
+                        cursor = c;
+                        break;
                     }
                 }
             }
         }
+        self.pane.borrow_mut().replace(cursor.tree());
+        *self.zoomed.borrow_mut() = zoomed;
+        *self.size.borrow_mut() = size;
 
-        let mut root = self.pane.borrow_mut();
-        if let Some(root) = root.as_mut() {
-            if let Some(size) = compute_size(root) {
-                *self.size.borrow_mut() = size;
-            }
-        }
+        self.resize(size);
+        self.fire_active_change(prior_active);
+
+        log::debug!(
+            "sync tab: {:#?} zoomed: {} {:#?}",
+            size,
+            self.zoomed.borrow().is_some(),
+            self.iter_panes()
+        );
+        assert!(self.pane.borrow().is_some());
     }
 
-    /// Given split_index, the topological index of a split returned by
-    /// iter_splits() as PositionedSplit::index, revised the split position
-    /// by the provided delta; positive values move the split to the right/bottom,
-    /// and negative values to the left/top.
-    /// The adjusted size is propogated downwards to contained children and
-    /// their panes are resized accordingly.
-    pub fn resize_split_by(&self, split_index: usize, delta: isize) {
-        if self.zoomed.borrow().is_some() {
-            return;
+    pub fn codec_pane_tree(&self) -> PaneNode {
+        let mux = Mux::get().unwrap();
+        let tab_id = self.id;
+        let window_id = match mux.window_containing_tab(tab_id) {
+            Some(w) => w,
+            None => {
+                log::error!("no window contains tab {}", tab_id);
+                return PaneNode::Empty;
+            }
+        };
+
+        let workspace = match mux
+            .get_window(window_id)
+            .map(|w| w.get_workspace().to_string())
+        {
+            Some(ws) => ws,
+            None => {
+                log::error!("window id {} doesn't have a window!?", window_id);
+                return PaneNode::Empty;
+            }
+        };
+
+        let zoomed = self.zoomed.borrow();
+        let active = self.get_active_pane();
+        if let Some(root) = self.pane.borrow().as_ref() {
+            pane_tree(
+                root,
+                tab_id,
+                window_id,
+                active.as_ref(),
+                zoomed.as_ref(),
+                &workspace,
+            )
+        } else {
+            PaneNode::Empty
         }
+    }
 
-        let mut root = self.pane.borrow_mut();
-        let mut cursor = root.take().unwrap().cursor();
-        let mut index = 0;
+    /// Returns true if the tab holds exactly one pane.
+    /// This is cheaper than comparing count_panes() with 1 as it only
+    /// needs to look at the root of the tree.
+    pub fn is_single_pane(&self) -> bool {
+        matches!(self.pane.borrow().as_ref(), Some(Tree::Leaf(_)))
+    }
 
-        // Position cursor on the specified split
-        loop {
-            if !cursor.is_leaf() {
-                if index == split_index {
-                    // Found it
-                    break;
-                }
-                index += 1;
-            }
-            match cursor.preorder_next() {
-                Ok(c) => cursor = c,
-                Err(c) => {
-                    // Didn't find it
-                    root.replace(c.tree());
-                    return;
+    /// Invokes f with the topological index of each pane in the tab,
+    /// along with the pane itself.  The zoom state is not taken into
+    /// account.  The panes are collected before f is called, so the
+    /// traversal is complete before any pane is visited; f must not
+    /// alter the structure of the tab (eg: by splitting or removing
+    /// panes), as those changes would not be reflected in the panes
+    /// that remain to be visited.
+    pub fn with_each_pane<F: FnMut(usize, &Rc<dyn Pane>)>(&self, mut f: F) {
+        fn collect(tree: &Tree, panes: &mut Vec<Rc<dyn Pane>>) {
+            match tree {
+                Tree::Empty => {}
+                Tree::Node { left, right, .. } => {
+                    collect(&*left, panes);
+                    collect(&*right, panes);
                 }
+                Tree::Leaf(pane) => panes.push(Rc::clone(pane)),
             }
         }
 
-        // Now cursor is looking at the split
-        self.adjust_node_at_cursor(&mut cursor, delta);
-        self.cascade_size_from_cursor(root, cursor);
+        let mut panes = vec![];
+        if let Some(root) = self.pane.borrow().as_ref() {
+            collect(root, &mut panes);
+        }
+        for (index, pane) in panes.iter().enumerate() {
+            f(index, pane);
+        }
     }
 
-    fn adjust_node_at_cursor(&self, cursor: &mut Cursor, delta: isize) {
-        let cell_dimensions = self.cell_dimensions();
-        if let Ok(Some(node)) = cursor.node_mut() {
-            match node.direction {
-                SplitDirection::Horizontal => {
-                    let width = node.width();
+    /// Returns the topological index and title of each pane in the tab.
+    /// The zoom state is not taken into account.
+    pub fn pane_titles(&self) -> Vec<(usize, String)> {
+        let mut titles = vec![];
+        self.with_each_pane(|index, pane| titles.push((index, pane.get_title())));
+        titles
+    }
 
-                    let mut cols = node.first.cols as isize;
-                    cols = cols
-                        .saturating_add(delta)
-                        .max(1)
-                        .min((width as isize).saturating_sub(2));
-                    node.first.cols = cols as u16;
-                    node.first.pixel_width =
-                        node.first.cols.saturating_mul(cell_dimensions.pixel_width);
+    /// Returns a count of how many panes are in this tab, without
+    /// building the PositionedPane list.  The zoom state is not taken
+    /// into account, so this always matches iter_panes_ignoring_zoom(),
+    /// and matches iter_panes() unless a pane is zoomed.
+    pub fn count_panes(&self) -> usize {
+        self.pane
+            .borrow()
+            .as_ref()
+            .map(|root| count_leaves_and_splits(root).0)
+            .unwrap_or(0)
+    }
 
-                    node.second.cols = width.saturating_sub(node.first.cols.saturating_add(1));
-                    node.second.pixel_width =
-                        node.second.cols.saturating_mul(cell_dimensions.pixel_width);
-                }
-                SplitDirection::Vertical => {
-                    let height = node.height();
+    /// Returns a count of how many splits iter_splits() would return,
+    /// without building the PositionedSplit list
+    pub fn count_splits(&self) -> usize {
+        if self.zoomed.borrow().is_some() {
+            return 0;
+        }
+        self.pane
+            .borrow()
+            .as_ref()
+            .map(|root| count_leaves_and_splits(root).1)
+            .unwrap_or(0)
+    }
 
-                    let mut rows = node.first.rows as isize;
-                    rows = rows
-                        .saturating_add(delta)
-                        .max(1)
-                        .min((height as isize).saturating_sub(2));
-                    node.first.rows = rows as u16;
-                    node.first.pixel_height =
-                        node.first.rows.saturating_mul(cell_dimensions.pixel_height);
+    /// Returns true if a pane is zoomed to fill the tab
+    pub fn is_zoomed(&self) -> bool {
+        self.zoomed.borrow().is_some()
+    }
 
-                    node.second.rows = height.saturating_sub(node.first.rows.saturating_add(1));
-                    node.second.pixel_height = node
-                        .second
-                        .rows
-                        .saturating_mul(cell_dimensions.pixel_height);
-                }
-            }
+    pub fn set_zoomed(&self, zoomed: bool) {
+        if self.zoomed.borrow().is_some() == zoomed {
+            // Current zoom state matches intended zoom state,
+            // so we have nothing to do.
+            return;
         }
+        self.toggle_zoom();
     }
 
-    fn cascade_size_from_cursor(&self, mut root: RefMut<Option<Tree>>, mut cursor: Cursor) {
-        // Now we need to cascade this down to children
-        match cursor.preorder_next() {
-            Ok(c) => cursor = c,
-            Err(c) => {
-                root.replace(c.tree());
-                return;
+    pub fn toggle_zoom(&self) {
+        let size = self.usable_size();
+        if self.zoomed.borrow_mut().take().is_some() {
+            // We were zoomed, but now we are not.
+            // Re-apply the size to the panes
+            if let Some(pane) = self.get_active_pane() {
+                pane.set_zoomed(false);
             }
-        }
-        let root_size = *self.size.borrow();
 
-        loop {
-            // Figure out the available size by looking at our immediate parent node.
-            // If we are the root, look at the provided new size
-            let pane_size = if let Some((branch, Some(parent))) = cursor.path_to_root().next() {
-                if branch == PathBranch::IsRight {
-                    parent.second
-                } else {
-                    parent.first
-                }
-            } else {
-                root_size
-            };
-
-            if cursor.is_leaf() {
-                // Apply our size to the tty
-                cursor.leaf_mut().map(|pane| pane.resize(pane_size));
-            } else {
-                self.apply_pane_size(pane_size, &mut cursor);
+            let mut root = self.pane.borrow_mut();
+            apply_sizes_from_splits(
+                root.as_mut().unwrap(),
+                &size,
+                self.get_pane_gutter(),
+                self.get_pane_header_rows(),
+            );
+        } else {
+            // We weren't zoomed, but now we want to zoom.
+            // Locate the active pane
+            if let Some(pane) = self.get_active_pane() {
+                pane.set_zoomed(true);
+                pane.resize(inset_for_header(size, self.get_pane_header_rows()))
+                    .ok();
+                self.zoomed.borrow_mut().replace(pane);
             }
-            match cursor.preorder_next() {
-                Ok(c) => cursor = c,
-                Err(c) => {
-                    root.replace(c.tree());
-                    break;
-                }
+        }
+    }
+
+    /// Makes the pane at pane_index the active pane and zooms it to
+    /// fill the tab.  If a different pane is already zoomed, it is
+    /// unzoomed first.
+    pub fn zoom_pane(&self, pane_index: usize) -> anyhow::Result<()> {
+        if pane_index >= self.count_panes() {
+            anyhow::bail!("invalid pane_index {}; cannot zoom!", pane_index);
+        }
+        if self.zoomed.borrow().is_some() && self.get_active_idx() != pane_index {
+            self.set_zoomed(false);
+        }
+        self.set_active_idx(pane_index);
+        self.set_zoomed(true);
+        Ok(())
+    }
+
+    /// Restores the layout that was in effect prior to zooming
+    pub fn unzoom(&self) {
+        self.set_zoomed(false);
+    }
+
+    pub fn contains_pane(&self, pane: PaneId) -> bool {
+        fn contains(tree: &Tree, pane: PaneId) -> bool {
+            match tree {
+                Tree::Empty => false,
+                Tree::Node { left, right, .. } => contains(left, pane) || contains(right, pane),
+                Tree::Leaf(p) => p.pane_id() == pane,
             }
         }
+        match &*self.pane.borrow() {
+            Some(root) => contains(root, pane),
+            None => false,
+        }
     }
 
-    /// Adjusts the size of the active pane in the specified direction
-    /// by the specified amount.
-    pub fn adjust_pane_size(&self, direction: PaneDirection, amount: usize) {
-        if self.zoomed.borrow().is_some() {
-            return;
+    /// Walks the pane tree to produce the topologically ordered flattened
+    /// list of PositionedPane instances along with their positioning information.
+    pub fn iter_panes(&self) -> Vec<PositionedPane> {
+        let mut panes = vec![];
+        self.iter_panes_impl(true, &mut panes);
+        panes
+    }
+
+    /// Like iter_panes, except that the results are written into the
+    /// caller provided `panes` buffer, which is cleared first.
+    /// This allows per-frame callers to amortize the allocation of the
+    /// vector across calls.
+    pub fn iter_panes_into(&self, panes: &mut Vec<PositionedPane>) {
+        panes.clear();
+        self.iter_panes_impl(true, panes);
+    }
+
+    /// Like iter_panes, except that it will include all panes, regardless of
+    /// whether one of them is currently zoomed.
+    pub fn iter_panes_ignoring_zoom(&self) -> Vec<PositionedPane> {
+        let mut panes = vec![];
+        self.iter_panes_impl(false, &mut panes);
+        panes
+    }
+
+    fn iter_panes_impl(&self, respect_zoom_state: bool, panes: &mut Vec<PositionedPane>) {
+        if respect_zoom_state {
+            if let Some(zoomed) = self.zoomed.borrow().as_ref() {
+                let size = self.usable_size();
+                panes.push(PositionedPane {
+                    index: 0,
+                    is_active: true,
+                    is_zoomed: true,
+                    is_peeked: false,
+                    left: 0,
+                    top: 0,
+                    width: size.cols.into(),
+                    pixel_width: size.pixel_width.into(),
+                    height: size.rows.into(),
+                    pixel_height: size.pixel_height.into(),
+                    pane: Rc::clone(zoomed),
+                });
+                return;
+            }
         }
-        let active_index = *self.active.borrow();
+
+        let active_idx = *self.active.borrow();
         let mut root = self.pane.borrow_mut();
         let mut cursor = root.take().unwrap().cursor();
-        let mut index = 0;
 
-        // Position cursor on the active leaf
         loop {
             if cursor.is_leaf() {
-                if index == active_index {
-                    // Found it
-                    break;
-                }
-                index += 1;
+                let index = panes.len();
+                let (left, top, dims) = self.leaf_position(&cursor);
+                let pane = Rc::clone(cursor.leaf_mut().unwrap());
+
+                panes.push(PositionedPane {
+                    index,
+                    is_active: index == active_idx,
+                    is_zoomed: false,
+                    is_peeked: false,
+                    left,
+                    top,
+                    width: dims.cols as _,
+                    height: dims.rows as _,
+                    pixel_width: dims.pixel_width as _,
+                    pixel_height: dims.pixel_height as _,
+                    pane,
+                });
             }
+
             match cursor.preorder_next() {
                 Ok(c) => cursor = c,
                 Err(c) => {
-                    // Didn't find it
                     root.replace(c.tree());
-                    return;
+                    break;
                 }
             }
         }
+    }
 
-        // We are on the active leaf.
-        // Now we go up until we find the parent node that is
-        // aligned with the desired direction.
-        let split_direction = match direction {
-            PaneDirection::Left | PaneDirection::Right => SplitDirection::Horizontal,
-            PaneDirection::Up | PaneDirection::Down => SplitDirection::Vertical,
-            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
+    /// Like iter_panes, except that the peeked pane, if any, is reported
+    /// with is_peeked set and its area enlarged by the peek factor, so
+    /// that the renderer can draw it over its neighbors.  The positions
+    /// returned by iter_panes, which are used for layout, are unaffected.
+    pub fn iter_panes_for_render(&self) -> Vec<PositionedPane> {
+        let mut panes = self.iter_panes();
+        if self.zoomed.borrow().is_none() {
+            self.apply_peek(&mut panes);
+        }
+        panes
+    }
+
+    /// Enlarges the position of the peeked pane, if any, by the peek
+    /// factor about its center, keeping it within the tab
+    fn apply_peek(&self, panes: &mut [PositionedPane]) {
+        let peeked = match *self.peeked.borrow() {
+            Some(index) => index,
+            None => return,
         };
-        let delta = match direction {
-            PaneDirection::Down | PaneDirection::Right => amount as isize,
-            PaneDirection::Up | PaneDirection::Left => -(amount as isize),
-            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
+        let pos = match panes.get_mut(peeked) {
+            Some(pos) => pos,
+            None => return,
         };
-        loop {
-            match cursor.go_up() {
-                Ok(mut c) => {
-                    if let Ok(Some(node)) = c.node_mut() {
-                        if node.direction == split_direction {
-                            self.adjust_node_at_cursor(&mut c, delta);
-                            self.cascade_size_from_cursor(root, c);
-                            return;
-                        }
-                    }
-
-                    cursor = c;
-                }
+        let factor = *self.peek_factor.borrow();
+        let size = self.usable_size();
+        let cell_dims = self.cell_dimensions();
 
-                Err(c) => {
-                    root.replace(c.tree());
-                    return;
-                }
-            }
+        fn grow(start: usize, len: usize, factor: f32, total: usize) -> (usize, usize) {
+            let new_len = ((len as f32 * factor).round() as usize).max(len).min(total);
+            let center = start + len / 2;
+            let start = center
+                .saturating_sub(new_len / 2)
+                .min(total.saturating_sub(new_len));
+            (start, new_len)
         }
+
+        let (left, width) = grow(pos.left, pos.width, factor, size.cols as usize);
+        let (top, height) = grow(pos.top, pos.height, factor, size.rows as usize);
+        pos.left = left;
+        pos.width = width;
+        pos.pixel_width = width * cell_dims.pixel_width as usize;
+        pos.top = top;
+        pos.height = height;
+        pos.pixel_height = height * cell_dims.pixel_height as usize;
+        pos.is_peeked = true;
     }
 
-    /// Activate an adjacent pane in the specified direction.
-    /// In cases where there are multiple adjacent panes in the
-    /// intended direction, we take the pane that has the largest
-    /// edge intersection.
-    pub fn activate_pane_direction(&self, direction: PaneDirection) {
+    /// Marks the pane at pane_index as being peeked, for example while
+    /// the mouse hovers over it, or clears the peek if None.
+    /// iter_panes_for_render reports the peeked pane with is_peeked set
+    /// and its area enlarged by the peek factor, so that it can be drawn
+    /// over its neighbors.  Unlike zooming, no pane is actually resized;
+    /// the peek is purely a hint for the renderer, and has no effect
+    /// while the tab is zoomed.  The peek is cleared when splitting or
+    /// removing panes changes the pane indices.
+    pub fn set_peeked_pane(&self, pane_index: Option<usize>) {
+        *self.peeked.borrow_mut() = pane_index;
+    }
+
+    pub fn get_peeked_pane(&self) -> Option<usize> {
+        *self.peeked.borrow()
+    }
+
+    /// Sets the factor by which the peeked pane is enlarged along each
+    /// axis; values less than 1.0 are treated as 1.0
+    pub fn set_peek_factor(&self, factor: f32) {
+        *self.peek_factor.borrow_mut() = factor.max(1.0);
+    }
+
+    /// Like iter_panes, but each pane is accompanied by its depth in the
+    /// tree; the number of splits that contain it.
+    pub fn iter_panes_with_depth(&self) -> Vec<(PositionedPane, usize)> {
         if self.zoomed.borrow().is_some() {
-            if !configuration().unzoom_on_switch_pane {
-                return;
-            }
-            self.toggle_zoom();
+            return self.iter_panes().into_iter().map(|pos| (pos, 0)).collect();
         }
-        let panes = self.iter_panes();
-
-        let active = match panes.iter().find(|pane| pane.is_active) {
-            Some(p) => p,
-            None => {
-                // No active pane somehow...
-                self.set_active_idx(0);
-                return;
-            }
-        };
 
-        if matches!(direction, PaneDirection::Next | PaneDirection::Prev) {
-            let max_pane_id = panes.iter().map(|p| p.index).max().unwrap_or(active.index);
+        let mut depths = vec![];
+        {
+            let mut root = self.pane.borrow_mut();
+            let mut cursor = root.take().unwrap().cursor();
 
-            if direction == PaneDirection::Next {
-                self.set_active_idx(if active.index == max_pane_id {
-                    0
-                } else {
-                    active.index + 1
-                });
-            } else {
-                self.set_active_idx(if active.index == 0 {
-                    max_pane_id
-                } else {
-                    active.index - 1
-                });
+            loop {
+                if cursor.is_leaf() {
+                    depths.push(cursor.path_to_root().count());
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(c) => {
+                        root.replace(c.tree());
+                        break;
+                    }
+                }
             }
-            return;
         }
 
-        let mut best = None;
+        self.iter_panes().into_iter().zip(depths).collect()
+    }
 
-        /// Compute the edge intersection size between two touching panes
-        fn compute_score(
-            active_start: usize,
-            active_size: usize,
-            current_start: usize,
-            current_size: usize,
-        ) -> usize {
-            range_intersection(
-                &(active_start..active_start + active_size),
-                &(current_start..current_start + current_size),
-            )
-            .unwrap_or(0..0)
-            .count()
+    /// Returns the layout of the panes as a tree of rectangles that
+    /// mirrors the split tree; this is intended to help visualize and
+    /// debug layouts.  The zoom state is not taken into account.
+    pub fn layout_tree_rects(&self) -> LayoutNode {
+        let size = self.usable_size();
+        match self.pane.borrow().as_ref() {
+            Some(root) => layout_node(root, 0, 0, &size, &mut 0, &mut 0),
+            None => LayoutNode::Empty,
         }
+    }
 
-        for pane in &panes {
-            let score = match direction {
-                PaneDirection::Right => {
-                    if pane.left == active.left + active.width + 1 {
-                        compute_score(active.top, active.height, pane.top, pane.height)
+    /// Computes the (left, top) offset and size of the leaf at the
+    /// cursor position by walking its path to the root.
+    fn leaf_position(&self, cursor: &Cursor) -> (usize, usize, PtySize) {
+        let mut left = 0usize;
+        let mut top = 0usize;
+        let mut parent_size = None;
+        for (branch, node) in cursor.path_to_root() {
+            if let Some(node) = node {
+                if parent_size.is_none() {
+                    parent_size.replace(if branch == PathBranch::IsRight {
+                        node.second
                     } else {
-                        0
-                    }
-                }
-                PaneDirection::Left => {
-                    if pane.left + pane.width + 1 == active.left {
-                        compute_score(active.top, active.height, pane.top, pane.height)
-                    } else {
-                        0
-                    }
+                        node.first
+                    });
                 }
-                PaneDirection::Up => {
-                    if pane.top + pane.height + 1 == active.top {
-                        compute_score(active.left, active.width, pane.left, pane.width)
-                    } else {
-                        0
-                    }
-                }
-                PaneDirection::Down => {
-                    if active.top + active.height + 1 == pane.top {
-                        compute_score(active.left, active.width, pane.left, pane.width)
-                    } else {
-                        0
-                    }
+                if branch == PathBranch::IsRight {
+                    top += node.top_of_second();
+                    left += node.left_of_second();
                 }
-                PaneDirection::Next | PaneDirection::Prev => unreachable!(),
-            };
-
-            if score > 0 {
-                let target = match best.take() {
-                    Some((best_score, best_pane)) if best_score > score => (best_score, best_pane),
-                    _ => (score, pane),
-                };
-                best.replace(target);
             }
         }
 
-        if let Some((_, target)) = best.take() {
-            self.set_active_idx(target.index);
-        }
+        let dims = parent_size.unwrap_or_else(|| self.usable_size());
+        let inset = inset_for_gutter(dims, self.get_pane_gutter(), left > 0, top > 0);
+        left += (dims.cols - inset.cols) as usize;
+        top += (dims.rows - inset.rows) as usize;
+        (left, top, inset)
     }
 
-    pub fn prune_dead_panes(&self) -> bool {
-        self.remove_pane_if(|_, pane| pane.is_dead())
+    /// Returns the size of the slot in the layout that holds the pane
+    /// with the specified topological index; unlike the size of the
+    /// pane itself, this includes the pane gutter.
+    /// The zoom state is not taken into account.
+    fn slot_size(&self, pane_index: usize) -> Option<PtySize> {
+        let mut root = self.pane.borrow_mut();
+        let cursor = root.take()?.cursor();
+
+        match cursor.go_to_nth_leaf(pane_index) {
+            Ok(cursor) => {
+                let size = match cursor.path_to_root().next() {
+                    Some((PathBranch::IsRight, Some(parent))) => parent.second,
+                    Some((_, Some(parent))) => parent.first,
+                    _ => self.usable_size(),
+                };
+                root.replace(cursor.tree());
+                Some(size)
+            }
+            Err(cursor) => {
+                root.replace(cursor.tree());
+                None
+            }
+        }
     }
 
-    pub fn kill_pane(&self, pane_id: PaneId) -> bool {
-        self.remove_pane_if(|_, pane| pane.pane_id() == pane_id)
-    }
+    /// Returns the (left, top, width, height) of the pane with the
+    /// specified topological index, in cells, consistent with the
+    /// PositionedPane that iter_panes() would return for it.
+    /// Returns None if pane_index is invalid.
+    pub fn pane_rect(&self, pane_index: usize) -> Option<(usize, usize, usize, usize)> {
+        if self.zoomed.borrow().is_some() {
+            if pane_index != 0 {
+                return None;
+            }
+            let size = self.usable_size();
+            return Some((0, 0, size.cols as usize, size.rows as usize));
+        }
 
-    pub fn kill_panes_in_domain(&self, domain: DomainId) -> bool {
-        self.remove_pane_if(|_, pane| pane.domain_id() == domain)
+        let mut root = self.pane.borrow_mut();
+        let cursor = root.take().unwrap().cursor();
+
+        match cursor.go_to_nth_leaf(pane_index) {
+            Ok(cursor) => {
+                let (left, top, dims) = self.leaf_position(&cursor);
+                root.replace(cursor.tree());
+                Some((left, top, dims.cols as usize, dims.rows as usize))
+            }
+            Err(cursor) => {
+                root.replace(cursor.tree());
+                None
+            }
+        }
     }
 
-    fn remove_pane_if<F>(&self, f: F) -> bool
-    where
-        F: Fn(usize, &Rc<dyn Pane>) -> bool,
-    {
-        let mut dead_panes = vec![];
-        let zoomed_pane = self.zoomed.borrow().as_ref().map(|p| p.pane_id());
+    /// Returns the dividers between the panes.  When a pane gutter is
+    /// set, each divider is the first cell of the gap between the panes
+    /// on either side of it, and the gutter follows it.
+    pub fn iter_splits(&self) -> Vec<PositionedSplit> {
+        let mut dividers = vec![];
+        if self.zoomed.borrow().is_some() {
+            return dividers;
+        }
 
-        {
-            let root_size = *self.size.borrow();
-            let mut active_idx = *self.active.borrow();
-            let mut root = self.pane.borrow_mut();
-            let mut cursor = root.take().unwrap().cursor();
-            let mut pane_index = 0;
-            let cell_dims = self.cell_dimensions();
+        let styles = self.split_styles.borrow();
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = root.take().unwrap().cursor();
+        let mut index = 0;
 
-            loop {
-                // Figure out the available size by looking at our immediate parent node.
-                // If we are the root, look at the tab size
-                let pane_size = if let Some((branch, Some(parent))) = cursor.path_to_root().next() {
-                    if branch == PathBranch::IsRight {
-                        parent.second
-                    } else {
-                        parent.first
+        loop {
+            if !cursor.is_leaf() {
+                let mut left = 0usize;
+                let mut top = 0usize;
+                for (branch, p) in cursor.path_to_root() {
+                    if let Some(p) = p {
+                        if branch == PathBranch::IsRight {
+                            left += p.left_of_second();
+                            top += p.top_of_second();
+                        }
                     }
-                } else {
-                    root_size
+                }
+                if let Ok(Some(node)) = cursor.node_mut() {
+                    match node.direction {
+                        SplitDirection::Horizontal => left += node.first.cols as usize,
+                        SplitDirection::Vertical => top += node.first.rows as usize,
+                    }
+
+                    dividers.push(PositionedSplit {
+                        index,
+                        direction: node.direction,
+                        left,
+                        top,
+                        size: if node.direction == SplitDirection::Horizontal {
+                            node.height() as usize
+                        } else {
+                            node.width() as usize
+                        },
+                        style: styles.get(&index).copied(),
+                    })
+                }
+                index += 1;
+            }
+
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    break;
+                }
+            }
+        }
+
+        dividers
+    }
+
+    /// Assigns a style to the split with the specified topological
+    /// index, as used by PositionedSplit::index, or clears it when style
+    /// is None.  The style is reported by iter_splits.
+    /// The style is associated with the index rather than the split,
+    /// so it should be reassigned when the splits are changed.
+    pub fn set_split_style(&self, split_index: usize, style: Option<SplitStyle>) {
+        let mut styles = self.split_styles.borrow_mut();
+        match style {
+            Some(style) => {
+                styles.insert(split_index, style);
+            }
+            None => {
+                styles.remove(&split_index);
+            }
+        }
+    }
+
+    /// Returns the accent color for the pane with the specified
+    /// topological index, for use by colored pane borders and indicators.
+    /// The color is derived from the pane id, so it follows the pane as
+    /// the layout changes.  Returns None if pane_index is invalid.
+    pub fn pane_accent_color(&self, pane_index: usize) -> Option<RgbColor> {
+        let pane = self
+            .iter_panes_ignoring_zoom()
+            .into_iter()
+            .nth(pane_index)?;
+        Some(pane_accent_color_for_id(pane.pane.pane_id()))
+    }
+
+    /// Returns the topological index of the lowest split that contains
+    /// both of the panes with the specified indices, as used by
+    /// PositionedSplit::index.  Returns None if either pane index is
+    /// invalid.  The zoom state is not taken into account.
+    pub fn common_split(&self, a: usize, b: usize) -> Option<usize> {
+        let mut paths = vec![];
+        if let Some(root) = self.pane.borrow().as_ref() {
+            collect_split_paths(root, &mut 0, &mut vec![], &mut paths);
+        }
+        let (path_a, path_b) = (paths.get(a)?, paths.get(b)?);
+        path_a
+            .iter()
+            .zip(path_b.iter())
+            .take_while(|(a, b)| a == b)
+            .last()
+            .map(|(split, _)| *split)
+    }
+
+    /// Associates name with the split that has the specified topological
+    /// index, as used by PositionedSplit::index, replacing any split
+    /// that previously had that name.
+    /// Unlike split styles, the name follows the split rather than the
+    /// index: the split is remembered by the panes on either side of it,
+    /// so the name continues to resolve as other panes are added and
+    /// removed, for as long as each side retains one of those panes.
+    pub fn set_split_name(&self, split_index: usize, name: &str) -> anyhow::Result<()> {
+        let sides = self
+            .pane
+            .borrow()
+            .as_ref()
+            .and_then(|root| split_sides(root, split_index, &mut 0));
+        match sides {
+            Some(sides) => {
+                self.split_names
+                    .borrow_mut()
+                    .insert(name.to_string(), sides);
+                Ok(())
+            }
+            None => anyhow::bail!("invalid split_index {}", split_index),
+        }
+    }
+
+    /// Returns the current topological index of the split with the
+    /// specified name, or None if there is no such name, or the split
+    /// no longer exists.  The zoom state is not taken into account.
+    pub fn split_by_name(&self, name: &str) -> Option<usize> {
+        let (first, second) = self.split_names.borrow().get(name)?.clone();
+        let ids: Vec<PaneId> = self
+            .iter_panes_ignoring_zoom()
+            .iter()
+            .map(|pos| pos.pane.pane_id())
+            .collect();
+        let index_of = |side: &[PaneId]| {
+            side.iter()
+                .find_map(|id| ids.iter().position(|candidate| candidate == id))
+        };
+        self.common_split(index_of(&first)?, index_of(&second)?)
+    }
+
+    /// Returns the fraction of each split that is occupied by its first
+    /// child, keyed by split index as used by PositionedSplit::index.
+    /// The divider is excluded, so a 40/39 column split reports 40/79.
+    /// The zoom state is not taken into account.
+    pub fn split_ratios(&self) -> Vec<(usize, f32)> {
+        fn collect_ratios(tree: &Tree, ratios: &mut Vec<(usize, f32)>) {
+            if let Tree::Node { left, right, data } = tree {
+                if let Some(data) = data {
+                    let (first, second) = match data.direction {
+                        SplitDirection::Horizontal => (data.first.cols, data.second.cols),
+                        SplitDirection::Vertical => (data.first.rows, data.second.rows),
+                    };
+                    let total = (first + second).max(1) as f32;
+                    ratios.push((ratios.len(), first as f32 / total));
+                }
+                collect_ratios(&*left, ratios);
+                collect_ratios(&*right, ratios);
+            }
+        }
+
+        let mut ratios = vec![];
+        if let Some(root) = self.pane.borrow().as_ref() {
+            collect_ratios(root, &mut ratios);
+        }
+        ratios
+    }
+
+    /// Returns the layout of the tab in the form used by tmux, for
+    /// example `b25d,80x24,0,0,0`, using the wezterm pane ids.
+    /// Returns an empty string if the tab has no panes.
+    /// The zoom state is not taken into account.
+    pub fn tmux_layout(&self) -> String {
+        let size = self.usable_size();
+        self.pane
+            .borrow()
+            .as_ref()
+            .and_then(|root| tmux_layout_cell(root, 0, 0, &size))
+            .map(|layout| layout.to_layout_string())
+            .unwrap_or_default()
+    }
+
+    /// Returns the direction shared by every split in the tab, if the
+    /// layout is a simple row or column of panes.  Returns None if the
+    /// tab has only a single pane or if the splits are mixed.
+    /// The zoom state is not taken into account.
+    pub fn stack_axis(&self) -> Option<SplitDirection> {
+        fn check_axis(tree: &Tree, axis: &mut Option<SplitDirection>) -> bool {
+            match tree {
+                Tree::Node {
+                    left,
+                    right,
+                    data: Some(data),
+                } => {
+                    *axis.get_or_insert(data.direction) == data.direction
+                        && check_axis(&*left, axis)
+                        && check_axis(&*right, axis)
+                }
+                _ => true,
+            }
+        }
+
+        let mut axis = None;
+        match self.pane.borrow().as_ref() {
+            Some(root) if check_axis(root, &mut axis) => axis,
+            _ => None,
+        }
+    }
+
+    /// When the layout is a simple stack (see stack_axis), moves the
+    /// pane at pane_index by offset positions along the stack; negative
+    /// values move it towards the left/top.  The slots keep their sizes,
+    /// and the panes are resized to fit the slots they land in.
+    /// Returns the new index of the pane.
+    pub fn move_pane_in_stack(&self, pane_index: usize, offset: isize) -> anyhow::Result<usize> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot move a pane while zoomed");
+        }
+        if self.stack_axis().is_none() {
+            anyhow::bail!("cannot move a pane: the layout is not a simple stack");
+        }
+
+        let mut panes: Vec<Rc<dyn Pane>> = self.iter_panes().into_iter().map(|p| p.pane).collect();
+        if pane_index >= panes.len() {
+            anyhow::bail!("invalid pane_index {}; cannot move!", pane_index);
+        }
+        let new_index = (pane_index as isize)
+            .saturating_add(offset)
+            .max(0)
+            .min(panes.len() as isize - 1) as usize;
+        if new_index == pane_index {
+            return Ok(pane_index);
+        }
+
+        let active = self.get_active_pane();
+        let pane = panes.remove(pane_index);
+        panes.insert(new_index, pane);
+        self.reassign_panes(panes);
+
+        if let Some(active) = active {
+            if let Some(pos) = self
+                .iter_panes()
+                .iter()
+                .find(|p| p.pane.pane_id() == active.pane_id())
+            {
+                self.store_active_idx(pos.index);
+            }
+        }
+
+        Ok(new_index)
+    }
+
+    /// Places the supplied panes into the existing leaves of the tree,
+    /// in topological order, and resizes them to fit their new slots.
+    /// The structure and split sizes of the tree are unchanged.
+    fn reassign_panes(&self, panes: Vec<Rc<dyn Pane>>) {
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = root.take().unwrap().cursor();
+        let mut panes = panes.into_iter();
+        loop {
+            if cursor.is_leaf() {
+                if let Some(pane) = panes.next() {
+                    *cursor.leaf_mut().unwrap() = pane;
+                }
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    break;
+                }
+            }
+        }
+        apply_sizes_from_splits(
+            root.as_ref().unwrap(),
+            &self.usable_size(),
+            self.get_pane_gutter(),
+            self.get_pane_header_rows(),
+        );
+    }
+
+    /// Returns the topological index of the pane with the largest area.
+    /// If several panes share the largest area, the first is returned.
+    /// Returns None if the tab has no panes.
+    pub fn largest_pane(&self) -> Option<usize> {
+        let mut largest: Option<(usize, usize)> = None;
+        for pos in self.iter_panes_ignoring_zoom() {
+            let area = pos.width * pos.height;
+            match largest {
+                Some((_, largest_area)) if largest_area >= area => {}
+                _ => largest = Some((pos.index, area)),
+            }
+        }
+        largest.map(|(index, _)| index)
+    }
+
+    /// Exchanges the active pane with the largest pane (see
+    /// largest_pane), so that the active pane occupies the largest
+    /// slot.  The focus follows the active pane to its new slot.
+    pub fn swap_active_with_largest(&self) -> anyhow::Result<()> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot swap panes while zoomed");
+        }
+        let largest = match self.largest_pane() {
+            Some(largest) => largest,
+            None => anyhow::bail!("there are no panes to swap"),
+        };
+        let active = self.get_active_idx();
+        if active == largest {
+            return Ok(());
+        }
+
+        let mut panes: Vec<Rc<dyn Pane>> = self.iter_panes().into_iter().map(|p| p.pane).collect();
+        if active >= panes.len() {
+            anyhow::bail!("invalid active pane index {}", active);
+        }
+        panes.swap(active, largest);
+        self.reassign_panes(panes);
+        self.store_active_idx(largest);
+        Ok(())
+    }
+
+    /// Exchanges the positions of the panes at topological indices a
+    /// and b.  The structure and split sizes of the tree are unchanged;
+    /// each pane is resized to fit its new slot.  The active pane
+    /// remains active in its new position.
+    pub fn swap_panes(&self, a: usize, b: usize) -> anyhow::Result<()> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot swap panes while zoomed");
+        }
+        let mut panes: Vec<Rc<dyn Pane>> = self.iter_panes().into_iter().map(|p| p.pane).collect();
+        for index in &[a, b] {
+            if *index >= panes.len() {
+                anyhow::bail!(
+                    "invalid pane index {}; the tab has {} panes",
+                    index,
+                    panes.len()
+                );
+            }
+        }
+        if a == b {
+            anyhow::bail!("cannot swap pane {} with itself", a);
+        }
+
+        panes.swap(a, b);
+        self.reassign_panes(panes);
+
+        let active = self.get_active_idx();
+        if active == a {
+            self.store_active_idx(b);
+        } else if active == b {
+            self.store_active_idx(a);
+        }
+        Ok(())
+    }
+
+    /// Cycles the panes through the existing slots in topological order,
+    /// like tmux's rotate-window; when clockwise, the pane at index 0
+    /// moves to index 1, and so on, with the last pane moving to index 0.
+    /// The structure and split sizes of the tree are unchanged; each
+    /// pane is resized to fit its new slot.  The active pane remains
+    /// active in its new position.
+    pub fn rotate(&self, clockwise: bool) -> anyhow::Result<()> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot rotate panes while zoomed");
+        }
+        let mut panes: Vec<Rc<dyn Pane>> = self.iter_panes().into_iter().map(|p| p.pane).collect();
+        let count = panes.len();
+        if count < 2 {
+            return Ok(());
+        }
+        let active = self.get_active_idx();
+        if clockwise {
+            panes.rotate_right(1);
+        } else {
+            panes.rotate_left(1);
+        }
+        self.reassign_panes(panes);
+
+        if active < count {
+            self.store_active_idx(if clockwise {
+                (active + 1) % count
+            } else {
+                (active + count - 1) % count
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the indices (as used by PositionedSplit::index) of the
+    /// split dividers that occupy any cell on row y of the tab
+    pub fn dividers_on_row(&self, y: usize) -> Vec<usize> {
+        self.iter_splits()
+            .into_iter()
+            .filter(|split| match split.direction {
+                SplitDirection::Horizontal => y >= split.top && y < split.top + split.size,
+                SplitDirection::Vertical => y == split.top,
+            })
+            .map(|split| split.index)
+            .collect()
+    }
+
+    /// Returns the indices (as used by PositionedSplit::index) of the
+    /// split dividers that occupy any cell in column x of the tab
+    pub fn dividers_on_col(&self, x: usize) -> Vec<usize> {
+        self.iter_splits()
+            .into_iter()
+            .filter(|split| match split.direction {
+                SplitDirection::Horizontal => x == split.left,
+                SplitDirection::Vertical => x >= split.left && x < split.left + split.size,
+            })
+            .map(|split| split.index)
+            .collect()
+    }
+
+    /// Renders a scaled down composite of the tab that is at most
+    /// max_cols by max_rows cells in size.  Each cell of the thumbnail
+    /// samples the corresponding cell of the tab: either a character
+    /// from the visible portion of the pane beneath it, or blank for
+    /// the pane header rows.  Split dividers are snapped to the nearest
+    /// thumbnail column or row so that they are never lost to scaling.
+    pub fn thumbnail(&self, max_cols: usize, max_rows: usize) -> ThumbnailGrid {
+        let size = self.usable_size();
+        let (width, height) = (size.cols as usize, size.rows as usize);
+        let cols = max_cols.min(width);
+        let rows = max_rows.min(height);
+        let mut cells = vec![vec![' '; cols]; rows];
+        if cols == 0 || rows == 0 {
+            return ThumbnailGrid { cols, rows, cells };
+        }
+
+        let sample_x = |x: usize| x * width / cols;
+        let sample_y = |y: usize| y * height / rows;
+        // The thumbnail column/row edge nearest to a tab coordinate
+        let snap_x = |x: usize| ((x * cols + width / 2) / width).min(cols);
+        let snap_y = |y: usize| ((y * rows + height / 2) / height).min(rows);
+        let header = self.get_pane_header_rows() as usize;
+
+        for pos in self.iter_panes() {
+            let (first_row, lines) = pos.pane.get_lines(pos.pane.viewport_range());
+            let content_top = pos.top + header;
+            for (y, row) in cells.iter_mut().enumerate() {
+                let src_y = sample_y(y);
+                if src_y < content_top || src_y >= pos.top + pos.height {
+                    continue;
+                }
+                let line_idx = (src_y - content_top) as StableRowIndex + pos.pane.scroll_offset();
+                let line = match lines.get((line_idx - first_row) as usize) {
+                    Some(line) => line,
+                    None => continue,
                 };
+                for (x, cell) in row.iter_mut().enumerate() {
+                    let src_x = sample_x(x);
+                    if src_x < pos.left || src_x >= pos.left + pos.width {
+                        continue;
+                    }
+                    if let Some(c) = line
+                        .cells()
+                        .get(src_x - pos.left)
+                        .and_then(|c| c.str().chars().next())
+                    {
+                        *cell = c;
+                    }
+                }
+            }
+        }
 
-                if cursor.is_leaf() {
-                    let pane = Rc::clone(cursor.leaf_mut().unwrap());
-                    if f(pane_index, &pane) {
-                        if pane_index == active_idx {
-                            active_idx = pane_index.saturating_sub(1);
-                        }
-                        if Some(pane.pane_id()) == zoomed_pane {
-                            // If we removed the zoomed pane, un-zoom our state!
-                            self.zoomed.borrow_mut().take();
-                        }
-                        let parent;
-                        match cursor.unsplit_leaf() {
-                            Ok((c, dead, p)) => {
-                                dead_panes.push(dead.pane_id());
-                                parent = p.unwrap();
-                                cursor = c;
-                            }
-                            Err(c) => {
-                                // We might be the root, for example
-                                if c.is_top() && c.is_leaf() {
-                                    root.replace(Tree::Empty);
-                                    dead_panes.push(pane.pane_id());
-                                } else {
-                                    root.replace(c.tree());
-                                }
-                                break;
-                            }
-                        };
+        for split in self.iter_splits() {
+            match split.direction {
+                SplitDirection::Horizontal => {
+                    let x = snap_x(split.left).min(cols - 1);
+                    let y_start = snap_y(split.top).min(rows - 1);
+                    let y_end = snap_y(split.top + split.size).max(y_start + 1);
+                    for row in &mut cells[y_start..y_end] {
+                        row[x] = '│';
+                    }
+                }
+                SplitDirection::Vertical => {
+                    let y = snap_y(split.top).min(rows - 1);
+                    let x_start = snap_x(split.left).min(cols - 1);
+                    let x_end = snap_x(split.left + split.size).max(x_start + 1);
+                    for cell in &mut cells[y][x_start..x_end] {
+                        *cell = '─';
+                    }
+                }
+            }
+        }
+
+        ThumbnailGrid { cols, rows, cells }
+    }
+
+    pub fn tab_id(&self) -> TabId {
+        self.id
+    }
+
+    pub fn get_size(&self) -> PtySize {
+        *self.size.borrow()
+    }
+
+    /// Reserves rows at the bottom of the tab, for example to display
+    /// a status line.  The panes are laid out in the remaining area.
+    pub fn set_reserved_bottom_rows(&self, rows: u16) {
+        let current_size = self.usable_size();
+        *self.reserved_bottom_rows.borrow_mut() = rows;
+        let size = self.get_size();
+        self.resize_from(current_size, size);
+    }
+
+    pub fn get_reserved_bottom_rows(&self) -> u16 {
+        *self.reserved_bottom_rows.borrow()
+    }
+
+    /// Sets the maximum number of panes that this tab may hold.
+    /// Splits that would exceed it will fail.
+    pub fn set_max_panes(&self, max_panes: usize) {
+        *self.max_panes.borrow_mut() = max_panes;
+    }
+
+    pub fn get_max_panes(&self) -> usize {
+        *self.max_panes.borrow()
+    }
+
+    /// Returns the size of the area of the tab that holds panes; this is
+    /// the size of the tab less any reserved rows.
+    fn usable_size(&self) -> PtySize {
+        let size = *self.size.borrow();
+        let reserved = *self.reserved_bottom_rows.borrow();
+        let rows = size.rows.saturating_sub(reserved).max(1);
+        PtySize {
+            rows,
+            cols: size.cols,
+            pixel_width: size.pixel_width,
+            pixel_height: cell_dimensions(&size).pixel_height * rows,
+        }
+    }
+
+    /// Apply the new size of the tab to the panes contained within.
+    /// The delta between the current and the new size is computed,
+    /// and is distributed between the splits.  For small resizes
+    /// this algorithm biases towards adjusting the left/top nodes
+    /// first.  For large resizes this tends to proportionally adjust
+    /// the relative sizes of the elements in a split.
+    /// Panes that have been scrolled back into their scrollback are
+    /// returned to the same top row afterwards, rather than jumping
+    /// to the bottom; panes that are tracking the bottom continue
+    /// to do so.
+    pub fn resize(&self, size: PtySize) {
+        let scrolled: Vec<(Rc<dyn Pane>, StableRowIndex)> = self
+            .iter_panes_ignoring_zoom()
+            .into_iter()
+            .filter_map(|pos| {
+                let offset = pos.pane.scroll_offset();
+                if offset < pos.pane.get_dimensions().physical_top {
+                    Some((pos.pane, offset))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        self.resize_from(self.usable_size(), size);
+
+        for (pane, offset) in scrolled {
+            // scroll_to clamps the offset in case the scrollback has
+            // changed size
+            pane.scroll_to(offset);
+        }
+        self.note_active_rect();
+    }
+
+    /// Recomputes the number of rows and columns in the tab from its
+    /// pixel size and the supplied cell size, for example after the
+    /// font has changed, and resizes the panes to match.
+    /// Requests with a zero cell dimension are ignored.
+    pub fn set_cell_metrics(&self, cell_width: u16, cell_height: u16) {
+        if cell_width == 0 || cell_height == 0 {
+            return;
+        }
+        let size = *self.size.borrow();
+        self.resize(PtySize {
+            rows: (size.pixel_height / cell_height).max(1),
+            cols: (size.pixel_width / cell_width).max(1),
+            ..size
+        });
+
+        // resize rounds the pixel size down to whole cells; keep the
+        // actual pixel size so that later changes are computed from it
+        let mut new_size = self.size.borrow_mut();
+        new_size.pixel_width = size.pixel_width;
+        new_size.pixel_height = size.pixel_height;
+    }
+
+    /// Resizes the tab to size, where current_size is the size of the
+    /// area that the panes currently occupy.
+    fn resize_from(&self, current_size: PtySize, size: PtySize) {
+        if size.rows == 0 || size.cols == 0 {
+            // Ignore "impossible" resize requests
+            return;
+        }
+
+        // Un-zoom first, so that the layout can be reasoned about
+        // more easily.
+        let was_zoomed = self.zoomed.borrow().is_some();
+        self.set_zoomed(false);
+
+        {
+            let mut root = self.pane.borrow_mut();
+            let dims = cell_dimensions(&size);
+            let (min_x, min_y) = compute_min_size(root.as_mut().unwrap(), self.get_min_pane_size());
+            let reserved = *self.reserved_bottom_rows.borrow();
+
+            // Constrain the new size to the minimum possible dimensions
+            let cols = size.cols.max(min_x as u16);
+            let rows = size.rows.saturating_sub(reserved).max(min_y as u16);
+            let size = PtySize {
+                rows,
+                cols,
+                pixel_width: cols * dims.pixel_width,
+                pixel_height: rows * dims.pixel_height,
+            };
+
+            if size != current_size {
+                // Update the split nodes with adjusted sizes
+                adjust_x_size(
+                    root.as_mut().unwrap(),
+                    cols as isize - current_size.cols as isize,
+                    &dims,
+                    self.get_min_pane_size(),
+                );
+                adjust_y_size(
+                    root.as_mut().unwrap(),
+                    rows as isize - current_size.rows as isize,
+                    &dims,
+                    self.get_min_pane_size(),
+                );
+
+                *self.size.borrow_mut() = PtySize {
+                    rows: rows + reserved,
+                    pixel_height: (rows + reserved) * dims.pixel_height,
+                    ..size
+                };
+
+                // And then resize the individual panes to match
+                apply_sizes_from_splits(
+                    root.as_mut().unwrap(),
+                    &size,
+                    self.get_pane_gutter(),
+                    self.get_pane_header_rows(),
+                );
+            }
+        }
+
+        // Keep the proportions of a percentage layout; it is dropped
+        // once the panes no longer fit it
+        let spec = self.percentage_layout.borrow().clone();
+        if let Some(spec) = spec {
+            if self.size_stack_by_fractions(&spec).is_err() {
+                self.percentage_layout.borrow_mut().take();
+            }
+        }
+
+        // And finally restore the zoom, if appropriate
+        self.set_zoomed(was_zoomed);
+    }
+
+    /// Returns true if resizing the tab to size would change the
+    /// geometry of any of its panes.  This applies the same constraints
+    /// as resize, so that callers can skip redundant resize calls for
+    /// spurious resize events.
+    pub fn resize_would_change(&self, size: PtySize) -> bool {
+        if size.rows == 0 || size.cols == 0 {
+            return false;
+        }
+
+        let root = self.pane.borrow();
+        let root = match root.as_ref() {
+            Some(root) => root,
+            None => return false,
+        };
+        let mut tree = clone_tree(root);
+        let (min_x, min_y) = compute_min_size(&mut tree, self.get_min_pane_size());
+        let dims = cell_dimensions(&size);
+        let cols = size.cols.max(min_x as u16);
+        let rows = size
+            .rows
+            .saturating_sub(*self.reserved_bottom_rows.borrow())
+            .max(min_y as u16);
+        let size = PtySize {
+            rows,
+            cols,
+            pixel_width: cols * dims.pixel_width,
+            pixel_height: rows * dims.pixel_height,
+        };
+
+        size != self.usable_size()
+    }
+
+    /// Returns the indices of the panes that would become narrower,
+    /// and whose content would therefore need to be reflowed, if the
+    /// tab were resized to size.  This doesn't change the layout.
+    pub fn resize_causes_reflow(&self, size: PtySize) -> Vec<usize> {
+        if size.rows == 0 || size.cols == 0 {
+            return vec![];
+        }
+
+        let current_size = self.usable_size();
+        if self.zoomed.borrow().is_some() {
+            return if size.cols < current_size.cols {
+                vec![self.get_active_idx()]
+            } else {
+                vec![]
+            };
+        }
+
+        let root = self.pane.borrow();
+        let root = match root.as_ref() {
+            Some(root) => root,
+            None => return vec![],
+        };
+        let mut tree = clone_tree(root);
+        let (min_x, _) = compute_min_size(&mut tree, self.get_min_pane_size());
+        let cols = size.cols.max(min_x as u16);
+        adjust_x_size(
+            &mut tree,
+            cols as isize - current_size.cols as isize,
+            &self.cell_dimensions(),
+            self.get_min_pane_size(),
+        );
+
+        let mut before = vec![];
+        collect_pane_widths(root, &current_size, &mut before);
+        let mut after = vec![];
+        collect_pane_widths(
+            &tree,
+            &PtySize {
+                cols,
+                ..current_size
+            },
+            &mut after,
+        );
+
+        before
+            .into_iter()
+            .zip(after)
+            .enumerate()
+            .filter_map(|(index, (before, after))| if after < before { Some(index) } else { None })
+            .collect()
+    }
+
+    fn apply_pane_size(&self, pane_size: PtySize, cursor: &mut Cursor) {
+        let cell_width = pane_size.pixel_width / pane_size.cols.max(1);
+        let cell_height = pane_size.pixel_height / pane_size.rows.max(1);
+        let (min_cols, min_rows) = self.get_min_pane_size();
+        // Splits the space left after the divider, keeping the first
+        // child where possible but taking any deficit from it so that
+        // the second child keeps its minimum size
+        let divide = |total: u16, first: u16, min: usize| {
+            let avail = total.saturating_sub(1);
+            let second = avail.saturating_sub(first).max((min as u16).min(avail / 2));
+            (avail - second, second)
+        };
+        if let Ok(Some(node)) = cursor.node_mut() {
+            // Adjust the size of the node; we preserve the size of the first
+            // child and adjust the second, so if we are split down the middle
+            // and the window is made wider, the right column will grow in
+            // size, leaving the left at its current width.
+            if node.direction == SplitDirection::Horizontal {
+                node.first.rows = pane_size.rows;
+                node.second.rows = pane_size.rows;
+
+                let (first, second) = divide(pane_size.cols, node.first.cols, min_cols);
+                node.first.cols = first;
+                node.second.cols = second;
+            } else {
+                node.first.cols = pane_size.cols;
+                node.second.cols = pane_size.cols;
+
+                let (first, second) = divide(pane_size.rows, node.first.rows, min_rows);
+                node.first.rows = first;
+                node.second.rows = second;
+            }
+            node.first.pixel_width = node.first.cols * cell_width;
+            node.first.pixel_height = node.first.rows * cell_height;
+
+            node.second.pixel_width = node.second.cols * cell_width;
+            node.second.pixel_height = node.second.rows * cell_height;
+        }
+    }
+
+    /// Called when running in the mux server after an individual pane
+    /// has been resized.
+    /// Because the split manipulation happened on the GUI we "lost"
+    /// the information that would have allowed us to call resize_split_by()
+    /// and instead need to back-infer the split size information.
+    /// We rely on the client to have resized (or be in the process
+    /// of resizing) affected panes consistently with its own Tab
+    /// tree model.
+    /// This method does a simple tree walk to the leaves to back-propagate
+    /// the size of the panes up to their containing node split data.
+    /// Without this step, disconnecting and reconnecting would cause
+    /// the GUI to use stale size information for the window it spawns
+    /// to attach this tab.
+    pub fn rebuild_splits_sizes_from_contained_panes(&self) {
+        if self.zoomed.borrow().is_some() {
+            return;
+        }
+
+        // Panes that follow a divider are narrower than their slot
+        // by the gutter, and every pane is shorter than its slot by
+        // the header rows, so add those back
+        fn compute_size(
+            node: &mut Tree,
+            insets: (u16, u16),
+            after_x: bool,
+            after_y: bool,
+        ) -> Option<PtySize> {
+            let (gutter, header) = insets;
+            match node {
+                Tree::Empty => None,
+                Tree::Leaf(pane) => {
+                    let dims = pane.get_dimensions();
+                    let size = PtySize {
+                        cols: dims.cols as u16 + if after_x { gutter } else { 0 },
+                        rows: dims.viewport_rows as u16 + header + if after_y { gutter } else { 0 },
+                        pixel_height: 0,
+                        pixel_width: 0,
+                    };
+                    Some(size)
+                }
+                Tree::Node { left, right, data } => {
+                    if let Some(data) = data {
+                        if let Some(first) = compute_size(left, insets, after_x, after_y) {
+                            data.first = first;
+                        }
+                        let (right_x, right_y) = match data.direction {
+                            SplitDirection::Horizontal => (true, after_y),
+                            SplitDirection::Vertical => (after_x, true),
+                        };
+                        if let Some(second) = compute_size(right, insets, right_x, right_y) {
+                            data.second = second;
+                        }
+                        Some(data.size())
+                    } else {
+                        None
+                    }
+                }
+            }
+        }
+
+        let mut root = self.pane.borrow_mut();
+        if let Some(root) = root.as_mut() {
+            if let Some(mut size) = compute_size(
+                root,
+                (self.get_pane_gutter(), self.get_pane_header_rows()),
+                false,
+                false,
+            ) {
+                size.rows += *self.reserved_bottom_rows.borrow();
+                *self.size.borrow_mut() = size;
+            }
+        }
+    }
+
+    /// Given split_index, the topological index of a split returned by
+    /// iter_splits() as PositionedSplit::index, revised the split position
+    /// by the provided delta; positive values move the split to the right/bottom,
+    /// and negative values to the left/top.
+    /// The adjusted size is propogated downwards to contained children and
+    /// their panes are resized accordingly.
+    pub fn resize_split_by(&self, split_index: usize, delta: isize) {
+        if self.zoomed.borrow().is_some() {
+            return;
+        }
+
+        let mut root = self.pane.borrow_mut();
+
+        // Position cursor on the specified split
+        let mut cursor = match go_to_nth_split(root.take().unwrap().cursor(), split_index) {
+            Ok(c) => c,
+            Err(c) => {
+                // Didn't find it
+                root.replace(c.tree());
+                return;
+            }
+        };
+
+        // Now cursor is looking at the split
+        self.adjust_node_at_cursor(&mut cursor, delta);
+        self.cascade_size_from_cursor(root, cursor);
+    }
+
+    /// Like resize_split_by, but returns an error rather than silently
+    /// ignoring the request if split_index doesn't refer to a split or
+    /// if the tab is zoomed.  The divider is clamped so that both sides
+    /// of the split retain at least 1 cell.
+    pub fn adjust_split_size(&self, split_index: usize, delta: isize) -> anyhow::Result<()> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot adjust a split while zoomed");
+        }
+        let num_splits = self.iter_splits().len();
+        if split_index >= num_splits {
+            anyhow::bail!(
+                "invalid split_index {}; the tab has {} splits",
+                split_index,
+                num_splits
+            );
+        }
+        self.resize_split_by(split_index, delta);
+        Ok(())
+    }
+
+    /// Given split_index, the topological index of a split returned by
+    /// iter_splits() as PositionedSplit::index, exchanges the first and
+    /// second children of that split, along with their sizes, so that
+    /// that portion of the layout is mirrored.
+    /// Unlike swapping individual panes, this moves entire subtrees.
+    pub fn flip_split(&self, split_index: usize) -> anyhow::Result<()> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot flip a split while zoomed");
+        }
+
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = match go_to_nth_split(root.take().unwrap().cursor(), split_index) {
+            Ok(c) => c,
+            Err(c) => {
+                root.replace(c.tree());
+                anyhow::bail!("invalid split_index {}; cannot flip!", split_index);
+            }
+        };
+
+        if let Tree::Node {
+            left,
+            right,
+            data: Some(data),
+        } = cursor.subtree_mut()
+        {
+            std::mem::swap(left, right);
+            std::mem::swap(&mut data.first, &mut data.second);
+        }
+
+        self.cascade_size_from_cursor(root, cursor);
+        Ok(())
+    }
+
+    fn adjust_node_at_cursor(&self, cursor: &mut Cursor, delta: isize) {
+        // Moving a divider by hand abandons any percentage layout,
+        // rather than having the next resize put it back
+        self.percentage_layout.borrow_mut().take();
+        let cell_dimensions = self.cell_dimensions();
+        if let Ok(Some(node)) = cursor.node_mut() {
+            match node.direction {
+                SplitDirection::Horizontal => {
+                    let width = node.width();
+
+                    let mut cols = node.first.cols as isize;
+                    cols = cols
+                        .saturating_add(delta)
+                        .max(1)
+                        .min((width as isize).saturating_sub(2));
+                    node.first.cols = cols as u16;
+                    node.first.pixel_width =
+                        node.first.cols.saturating_mul(cell_dimensions.pixel_width);
+
+                    node.second.cols = width.saturating_sub(node.first.cols.saturating_add(1));
+                    node.second.pixel_width =
+                        node.second.cols.saturating_mul(cell_dimensions.pixel_width);
+                }
+                SplitDirection::Vertical => {
+                    let height = node.height();
+
+                    let mut rows = node.first.rows as isize;
+                    rows = rows
+                        .saturating_add(delta)
+                        .max(1)
+                        .min((height as isize).saturating_sub(2));
+                    node.first.rows = rows as u16;
+                    node.first.pixel_height =
+                        node.first.rows.saturating_mul(cell_dimensions.pixel_height);
+
+                    node.second.rows = height.saturating_sub(node.first.rows.saturating_add(1));
+                    node.second.pixel_height = node
+                        .second
+                        .rows
+                        .saturating_mul(cell_dimensions.pixel_height);
+                }
+            }
+        }
+    }
+
+    fn cascade_size_from_cursor(&self, mut root: RefMut<Option<Tree>>, mut cursor: Cursor) {
+        // Now we need to cascade this down to children
+        match cursor.preorder_next() {
+            Ok(c) => cursor = c,
+            Err(c) => {
+                root.replace(c.tree());
+                return;
+            }
+        }
+        let root_size = self.usable_size();
+
+        loop {
+            // Figure out the available size by looking at our immediate parent node.
+            // If we are the root, look at the provided new size
+            let pane_size = if let Some((branch, Some(parent))) = cursor.path_to_root().next() {
+                if branch == PathBranch::IsRight {
+                    parent.second
+                } else {
+                    parent.first
+                }
+            } else {
+                root_size
+            };
+
+            if cursor.is_leaf() {
+                // Apply our size to the tty
+                let (_, _, pane_size) = self.leaf_position(&cursor);
+                let pane_size = inset_for_header(pane_size, self.get_pane_header_rows());
+                cursor.leaf_mut().map(|pane| pane.resize(pane_size));
+            } else {
+                self.apply_pane_size(pane_size, &mut cursor);
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Adjusts the size of the active pane in the specified direction
+    /// by the specified amount.
+    pub fn adjust_pane_size(&self, direction: PaneDirection, amount: usize) {
+        if self.zoomed.borrow().is_some() {
+            return;
+        }
+        let active_index = *self.active.borrow();
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = root.take().unwrap().cursor();
+        let mut index = 0;
+
+        // Position cursor on the active leaf
+        loop {
+            if cursor.is_leaf() {
+                if index == active_index {
+                    // Found it
+                    break;
+                }
+                index += 1;
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    // Didn't find it
+                    root.replace(c.tree());
+                    return;
+                }
+            }
+        }
+
+        // We are on the active leaf.
+        // Now we go up until we find the parent node that is
+        // aligned with the desired direction.
+        let split_direction = match direction {
+            PaneDirection::Left | PaneDirection::Right => SplitDirection::Horizontal,
+            PaneDirection::Up | PaneDirection::Down => SplitDirection::Vertical,
+            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
+        };
+        let delta = match direction {
+            PaneDirection::Down | PaneDirection::Right => amount as isize,
+            PaneDirection::Up | PaneDirection::Left => -(amount as isize),
+            PaneDirection::Next | PaneDirection::Prev => unreachable!(),
+        };
+        loop {
+            match cursor.go_up() {
+                Ok(mut c) => {
+                    if let Ok(Some(node)) = c.node_mut() {
+                        if node.direction == split_direction {
+                            self.adjust_node_at_cursor(&mut c, delta);
+                            self.cascade_size_from_cursor(root, c);
+                            return;
+                        }
+                    }
+
+                    cursor = c;
+                }
+
+                Err(c) => {
+                    root.replace(c.tree());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Rearranges the panes into the next of the preset layouts,
+    /// remembering the position so that repeated calls cycle through
+    /// all of them.  The panes keep their topological order.
+    /// Returns the preset that was applied.
+    pub fn cycle_layout(&self) -> LayoutPreset {
+        let preset = match *self.layout_preset.borrow() {
+            Some(preset) => preset.next(),
+            None => LayoutPreset::EvenHorizontal,
+        };
+        self.apply_layout_preset(preset);
+        preset
+    }
+
+    /// Rearranges the panes into the specified preset layout.
+    /// The panes keep their topological order.
+    pub fn apply_layout_preset(&self, preset: LayoutPreset) {
+        self.set_zoomed(false);
+        let panes: Vec<Rc<dyn Pane>> = self.iter_panes().into_iter().map(|p| p.pane).collect();
+        if panes.is_empty() {
+            return;
+        }
+
+        let size = self.usable_size();
+        let tree = preset_tree(preset, panes, &size, &self.cell_dimensions());
+        apply_sizes_from_splits(
+            &tree,
+            &size,
+            self.get_pane_gutter(),
+            self.get_pane_header_rows(),
+        );
+        self.pane.borrow_mut().replace(tree);
+        self.layout_preset.borrow_mut().replace(preset);
+    }
+
+    /// Redistributes the space in the tab so that the items arranged
+    /// side by side by each run of same-direction splits are as close
+    /// to equal in size as the dividers allow.  Nested splits are
+    /// balanced within the space allotted to them by their parent.
+    /// A row of three panes is therefore split into thirds, rather
+    /// than halving the space at each split.
+    pub fn balance(&self) {
+        self.balance_axis(SplitDirection::Horizontal);
+        self.balance_axis(SplitDirection::Vertical);
+    }
+
+    /// Equalizes the sizes of the items that are arranged side by side
+    /// by the splits of the given direction, throughout the tab, while
+    /// the splits of the other direction keep their proportions.
+    /// For example, balancing Horizontal evens out the column widths
+    /// but leaves the heights of stacked panes alone.
+    pub fn balance_axis(&self, direction: SplitDirection) {
+        self.set_zoomed(false);
+        let cell_dims = self.cell_dimensions();
+        let mut root = self.pane.borrow_mut();
+        if let Some(tree) = root.as_mut() {
+            balance_chains_along(tree, direction, false, &cell_dims, self.get_min_pane_size());
+            apply_sizes_from_splits(
+                tree,
+                &self.usable_size(),
+                self.get_pane_gutter(),
+                self.get_pane_header_rows(),
+            );
+        }
+    }
+
+    /// Sizes the items of the stack at the top of the tab, that is, the
+    /// chain of splits that share the direction of the outermost split,
+    /// to the given fractions of the tab along that axis; for example,
+    /// [0.3, 0.7] for a narrow column beside a wide one.  Nested splits
+    /// of the other direction keep their proportions.
+    /// There must be one fraction per item, and the fractions must sum
+    /// to 1.0 (within 0.01).  The tab is unzoomed first.
+    /// The fractions are reapplied when the tab is resized, until the
+    /// number of items in the stack changes or a divider is moved by
+    /// hand, for example with resize_split_by.
+    pub fn apply_percentage_layout(&self, spec: &[f32]) -> anyhow::Result<()> {
+        self.set_zoomed(false);
+        self.size_stack_by_fractions(spec)?;
+        self.percentage_layout.borrow_mut().replace(spec.to_vec());
+        Ok(())
+    }
+
+    fn size_stack_by_fractions(&self, spec: &[f32]) -> anyhow::Result<()> {
+        if spec
+            .iter()
+            .any(|&fraction| !(fraction > 0.0 && fraction <= 1.0))
+        {
+            anyhow::bail!("layout fractions {:?} must be between 0.0 and 1.0", spec);
+        }
+        let sum: f32 = spec.iter().sum();
+        if (sum - 1.0).abs() > 0.01 {
+            anyhow::bail!("layout fractions {:?} sum to {} rather than 1.0", spec, sum);
+        }
+
+        let size = self.usable_size();
+        let cell_dims = self.cell_dimensions();
+        let mut root = self.pane.borrow_mut();
+        let tree = match root.as_mut() {
+            Some(tree) => tree,
+            None => anyhow::bail!("tab has no panes"),
+        };
+        let direction = match tree {
+            Tree::Node {
+                data: Some(data), ..
+            } => data.direction,
+            _ if spec.len() == 1 => return Ok(()),
+            _ => anyhow::bail!("expected 1 layout fraction but got {}", spec.len()),
+        };
+
+        let count = count_items_along(tree, direction);
+        if spec.len() != count {
+            anyhow::bail!("expected {} layout fractions but got {}", count, spec.len());
+        }
+        let current = match direction {
+            SplitDirection::Horizontal => size.cols,
+            SplitDirection::Vertical => size.rows,
+        };
+        if (current as usize) < count * 2 - 1 {
+            anyhow::bail!("no space for {} items", count);
+        }
+
+        let sizes = fractional_item_sizes(current, spec);
+        apply_item_sizes(
+            tree,
+            direction,
+            &sizes,
+            current,
+            &cell_dims,
+            self.get_min_pane_size(),
+        );
+        apply_sizes_from_splits(
+            tree,
+            &size,
+            self.get_pane_gutter(),
+            self.get_pane_header_rows(),
+        );
+        Ok(())
+    }
+
+    /// Resizes the pane at pane_index to get as close to its preferred
+    /// size as the layout allows, by moving the nearest enclosing split
+    /// in each direction; the surplus or deficit is taken up by its
+    /// siblings.  Does nothing if the pane has no preferred size.
+    pub fn fit_pane_to_content(&self, pane_index: usize) {
+        if self.zoomed.borrow().is_some() {
+            return;
+        }
+        let pane = match self.iter_panes().into_iter().nth(pane_index) {
+            Some(pos) => pos,
+            None => return,
+        };
+        let preferred = match pane.pane.preferred_size() {
+            Some(size) => size,
+            None => return,
+        };
+
+        self.grow_pane_along(
+            pane_index,
+            SplitDirection::Horizontal,
+            preferred.cols as isize - pane.width as isize,
+        );
+        self.grow_pane_along(
+            pane_index,
+            SplitDirection::Vertical,
+            preferred.rows as isize - pane.height as isize,
+        );
+    }
+
+    /// Grows (or shrinks, if amount is negative) the pane at pane_index
+    /// by moving the nearest enclosing split of the specified direction.
+    fn grow_pane_along(&self, pane_index: usize, direction: SplitDirection, amount: isize) {
+        if amount == 0 {
+            return;
+        }
+
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = match root.take().unwrap().cursor().go_to_nth_leaf(pane_index) {
+            Ok(c) => c,
+            Err(c) => {
+                root.replace(c.tree());
+                return;
+            }
+        };
+
+        loop {
+            let is_second = match cursor.path_to_root().next() {
+                Some((branch, _)) => branch == PathBranch::IsRight,
+                None => false,
+            };
+            match cursor.go_up() {
+                Ok(mut c) => {
+                    if let Ok(Some(node)) = c.node_mut() {
+                        if node.direction == direction {
+                            let delta = if is_second { -amount } else { amount };
+                            self.adjust_node_at_cursor(&mut c, delta);
+                            self.cascade_size_from_cursor(root, c);
+                            return;
+                        }
+                    }
+                    cursor = c;
+                }
+                Err(c) => {
+                    root.replace(c.tree());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Controls whether the pane with the specified id can be
+    /// activated by cycling through the panes with
+    /// PaneDirection::Next and PaneDirection::Prev.
+    /// Panes are focusable by default.
+    pub fn set_pane_focusable(&self, pane_id: PaneId, focusable: bool) {
+        let mut unfocusable = self.unfocusable_panes.borrow_mut();
+        if focusable {
+            unfocusable.remove(&pane_id);
+        } else {
+            unfocusable.insert(pane_id);
+        }
+    }
+
+    /// Returns false if the pane with the specified id has been marked
+    /// as not focusable via set_pane_focusable
+    pub fn is_pane_focusable(&self, pane_id: PaneId) -> bool {
+        !self.unfocusable_panes.borrow().contains(&pane_id)
+    }
+
+    /// Activate an adjacent pane in the specified direction.
+    /// In cases where there are multiple adjacent panes in the
+    /// intended direction, we take the pane that has the largest
+    /// edge intersection (see best_neighbor).
+    /// Returns true if a different pane was activated, or false if
+    /// there is no pane in that direction.
+    pub fn activate_pane_direction(&self, direction: PaneDirection) -> bool {
+        if self.zoomed.borrow().is_some() {
+            if !configuration().unzoom_on_switch_pane {
+                return false;
+            }
+            self.toggle_zoom();
+        }
+        let panes = self.iter_panes();
+
+        let active = match panes.iter().find(|pane| pane.is_active) {
+            Some(p) => p,
+            None => {
+                // No active pane somehow...
+                self.set_active_idx(0);
+                return false;
+            }
+        };
+
+        if matches!(direction, PaneDirection::Next | PaneDirection::Prev) {
+            // Step around the panes in topological order, skipping
+            // over any that have been marked as not focusable
+            let count = panes.len();
+            let unfocusable = self.unfocusable_panes.borrow();
+            let mut index = active.index;
+            for _ in 1..count {
+                index = if direction == PaneDirection::Next {
+                    (index + 1) % count
+                } else {
+                    (index + count - 1) % count
+                };
+                if !unfocusable.contains(&panes[index].pane.pane_id()) {
+                    self.set_active_idx(index);
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        match self.best_neighbor(active.index, direction) {
+            Some(target) => {
+                self.set_active_idx(target);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the visible pane whose area contains the cell at (x, y),
+    /// for example to hit test a mouse event.  Returns None if the
+    /// cell is part of a divider, or lies outside of the panes.
+    pub fn get_pane_at(&self, x: usize, y: usize) -> Option<PositionedPane> {
+        self.iter_panes().into_iter().find(|pos| {
+            x >= pos.left && x < pos.left + pos.width && y >= pos.top && y < pos.top + pos.height
+        })
+    }
+
+    /// Returns the topological indices of the visible panes whose
+    /// areas intersect the rectangle of cells with its top left corner
+    /// at (left, top) and the specified size, for example to select
+    /// the panes covered by a box dragged with the mouse.
+    /// An empty rectangle selects nothing.
+    pub fn panes_in_rect(
+        &self,
+        left: usize,
+        top: usize,
+        width: usize,
+        height: usize,
+    ) -> Vec<usize> {
+        self.iter_panes()
+            .into_iter()
+            .filter(|pos| {
+                left < pos.left + pos.width
+                    && pos.left < left + width
+                    && top < pos.top + pos.height
+                    && pos.top < top + height
+            })
+            .map(|pos| pos.index)
+            .collect()
+    }
+
+    /// Returns the topological index of each visible pane along with
+    /// the euclidean distance, in cells, from the point (x, y) to the
+    /// nearest cell of that pane, sorted nearest first.  A pane that
+    /// contains the point has a distance of 0.
+    pub fn panes_by_distance(&self, x: usize, y: usize) -> Vec<(usize, f32)> {
+        fn axis_distance(p: usize, start: usize, len: usize) -> f32 {
+            if p < start {
+                (start - p) as f32
+            } else if p >= start + len.max(1) {
+                (p + 1 - (start + len.max(1))) as f32
+            } else {
+                0.
+            }
+        }
+
+        let mut panes: Vec<(usize, f32)> = self
+            .iter_panes()
+            .iter()
+            .map(|pos| {
+                let dx = axis_distance(x, pos.left, pos.width);
+                let dy = axis_distance(y, pos.top, pos.height);
+                (pos.index, (dx * dx + dy * dy).sqrt())
+            })
+            .collect();
+        panes.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        panes
+    }
+
+    /// Returns the index of the pane that is adjacent to pane_index in
+    /// the specified direction.
+    /// In cases where there are multiple adjacent panes in the
+    /// intended direction, we take the pane that has the largest
+    /// edge intersection.  If several panes share the largest edge
+    /// intersection, the one whose center is closest to the center of
+    /// pane_index along the edge is preferred.
+    /// Returns None if there is no pane in that direction, or if
+    /// direction is Next or Prev.
+    pub fn best_neighbor(&self, pane_index: usize, direction: PaneDirection) -> Option<usize> {
+        let panes = self.iter_panes();
+        let active = panes.iter().find(|pane| pane.index == pane_index)?;
+
+        let mut best = None;
+        // Touching panes are separated by the divider and the gutter
+        let gap = 1 + self.get_pane_gutter() as usize;
+
+        /// Compute the edge intersection size between two touching panes
+        fn compute_score(
+            active_start: usize,
+            active_size: usize,
+            current_start: usize,
+            current_size: usize,
+        ) -> usize {
+            range_intersection(
+                &(active_start..active_start + active_size),
+                &(current_start..current_start + current_size),
+            )
+            .unwrap_or(0..0)
+            .count()
+        }
+
+        for pane in &panes {
+            let score = match direction {
+                PaneDirection::Right => {
+                    if pane.left == active.left + active.width + gap {
+                        compute_score(active.top, active.height, pane.top, pane.height)
+                    } else {
+                        0
+                    }
+                }
+                PaneDirection::Left => {
+                    if pane.left + pane.width + gap == active.left {
+                        compute_score(active.top, active.height, pane.top, pane.height)
+                    } else {
+                        0
+                    }
+                }
+                PaneDirection::Up => {
+                    if pane.top + pane.height + gap == active.top {
+                        compute_score(active.left, active.width, pane.left, pane.width)
+                    } else {
+                        0
+                    }
+                }
+                PaneDirection::Down => {
+                    if active.top + active.height + gap == pane.top {
+                        compute_score(active.left, active.width, pane.left, pane.width)
+                    } else {
+                        0
+                    }
+                }
+                PaneDirection::Next | PaneDirection::Prev => return None,
+            };
+
+            if score > 0 {
+                // Twice the distance between the centers of the panes,
+                // measured along the shared edge
+                let distance = match direction {
+                    PaneDirection::Left | PaneDirection::Right => ((2 * pane.top + pane.height)
+                        as isize
+                        - (2 * active.top + active.height) as isize)
+                        .abs(),
+                    _ => ((2 * pane.left + pane.width) as isize
+                        - (2 * active.left + active.width) as isize)
+                        .abs(),
+                };
+                let target = match best.take() {
+                    Some((best_score, best_distance, best_pane))
+                        if best_score > score
+                            || (best_score == score && best_distance < distance) =>
+                    {
+                        (best_score, best_distance, best_pane)
+                    }
+                    _ => (score, distance, pane),
+                };
+                best.replace(target);
+            }
+        }
+
+        best.map(|(_, _, target)| target.index)
+    }
+
+    pub fn prune_dead_panes(&self) -> bool {
+        if !self.get_close_on_last_pane_death() && self.is_dead() {
+            // Keep the dead panes so that their exit status
+            // remains visible
+            return false;
+        }
+        // Don't keep trickling paste data into panes that are going away
+        self.with_each_pane(|_, pane| {
+            if pane.is_dead() {
+                pane.cancel_paste();
+            }
+        });
+
+        // Dead overlays give way to the layouts that they displaced,
+        // and dead panes in those layouts are pruned as well
+        let mut dead_panes = vec![];
+        while self.has_overlay_pane() && self.pane.borrow().as_ref().is_none_or(all_panes_dead) {
+            if let Some(overlay) = self.pop_overlay_pane() {
+                dead_panes.push(overlay.pane_id());
+            }
+        }
+        for displaced in self.overlays.borrow_mut().iter_mut() {
+            let mut root = Some(std::mem::replace(&mut displaced.tree, Tree::Empty));
+            dead_panes.extend(self.remove_leaves_if(
+                &mut root,
+                displaced.size,
+                &mut displaced.active,
+                |_, pane| pane.is_dead(),
+            ));
+            displaced.tree = root.unwrap_or_else(Tree::new);
+        }
+        let pruned = !dead_panes.is_empty();
+        if pruned {
+            remove_from_mux(dead_panes);
+        }
+
+        self.remove_pane_if(|_, pane| pane.is_dead()) || pruned
+    }
+
+    /// Controls what happens when the last live pane in the tab dies.
+    /// When true (the default), the dead panes are pruned and the tab
+    /// is closed.  When false, the dead panes are retained so that
+    /// their output and exit status can be seen; the tab still reports
+    /// that it is_dead, but should_auto_close returns false.
+    pub fn set_close_on_last_pane_death(&self, close: bool) {
+        *self.close_on_last_pane_death.borrow_mut() = close;
+    }
+
+    pub fn get_close_on_last_pane_death(&self) -> bool {
+        *self.close_on_last_pane_death.borrow()
+    }
+
+    /// Returns true if the tab is dead and should be removed from its
+    /// window; see set_close_on_last_pane_death.
+    pub fn should_auto_close(&self) -> bool {
+        self.is_dead() && (self.get_close_on_last_pane_death() || self.count_panes() == 0)
+    }
+
+    /// Closes the pane that is adjacent to keep_index in the specified
+    /// direction and expands keep_index to occupy the space that it
+    /// leaves behind, returning the id of the closed pane.
+    /// This requires that the two panes are the halves of the same
+    /// split; an error is returned if the space in that direction is
+    /// shared between several panes, or if there is no pane there.
+    pub fn merge_panes(
+        &self,
+        keep_index: usize,
+        direction: PaneDirection,
+    ) -> anyhow::Result<PaneId> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot merge panes while zoomed");
+        }
+
+        let mut paths = vec![];
+        if let Some(root) = self.pane.borrow().as_ref() {
+            collect_split_paths(root, &mut 0, &mut vec![], &mut paths);
+        }
+        let path = match paths.get(keep_index) {
+            Some(path) => path,
+            None => anyhow::bail!("invalid pane index {}", keep_index),
+        };
+        let sibling = paths
+            .iter()
+            .enumerate()
+            .position(|(index, p)| index != keep_index && p == path);
+        let split_direction = path
+            .last()
+            .and_then(|&split| self.iter_splits().get(split).map(|s| s.direction));
+
+        let neighbor = match (sibling, split_direction, direction) {
+            (Some(sibling), Some(SplitDirection::Horizontal), PaneDirection::Right)
+            | (Some(sibling), Some(SplitDirection::Vertical), PaneDirection::Down)
+                if sibling > keep_index =>
+            {
+                sibling
+            }
+            (Some(sibling), Some(SplitDirection::Horizontal), PaneDirection::Left)
+            | (Some(sibling), Some(SplitDirection::Vertical), PaneDirection::Up)
+                if sibling < keep_index =>
+            {
+                sibling
+            }
+            _ => anyhow::bail!(
+                "pane {} has no single neighbor in direction {:?} to merge with",
+                keep_index,
+                direction
+            ),
+        };
+
+        let pane_id = self.iter_panes()[neighbor].pane.pane_id();
+        self.kill_pane(pane_id);
+        Ok(pane_id)
+    }
+
+    /// Closes the live pane at pane_index, as though it had died: its
+    /// sibling reclaims the space, the active pane is kept on the same
+    /// pane where possible, and the pane is removed from the mux.
+    /// Closing the only pane leaves the tab empty.
+    pub fn close_pane(&self, pane_index: usize) -> anyhow::Result<()> {
+        let panes = self.iter_panes_ignoring_zoom();
+        let pane = match panes.get(pane_index) {
+            Some(pos) => Rc::clone(&pos.pane),
+            None => anyhow::bail!(
+                "invalid pane_index {}; the tab has {} panes",
+                pane_index,
+                panes.len()
+            ),
+        };
+        let prior_active = self.get_active_idx();
+
+        pane.cancel_paste();
+        self.remove_pane_if(|idx, _| idx == pane_index);
+
+        if !*self.focus_locked.borrow() && prior_active > pane_index {
+            // The panes after the closed pane have moved down by one
+            self.store_active_idx(prior_active - 1);
+        }
+        Ok(())
+    }
+
+    pub fn kill_pane(&self, pane_id: PaneId) -> bool {
+        self.remove_pane_if(|_, pane| pane.pane_id() == pane_id)
+    }
+
+    pub fn kill_panes_in_domain(&self, domain: DomainId) -> bool {
+        self.remove_pane_if(|_, pane| pane.domain_id() == domain)
+    }
+
+    fn remove_pane_if<F>(&self, f: F) -> bool
+    where
+        F: Fn(usize, &Rc<dyn Pane>) -> bool,
+    {
+        let prior_active = *self.active.borrow();
+
+        let dead_panes = {
+            let mut active_idx = *self.active.borrow();
+            let dead_panes = self.remove_leaves_if(
+                &mut self.pane.borrow_mut(),
+                self.usable_size(),
+                &mut active_idx,
+                f,
+            );
+            *self.active.borrow_mut() = active_idx;
+            dead_panes
+        };
+
+        if *self.focus_locked.borrow() {
+            // Keep focus where it was, unless that index no longer exists
+            let count = self.count_panes();
+            *self.active.borrow_mut() = prior_active.min(count.saturating_sub(1));
+        }
+        self.fire_active_change(prior_active);
+
+        if !dead_panes.is_empty() {
+            self.apply_pane_insets();
+            self.peeked.borrow_mut().take();
+            self.active_history
+                .borrow_mut()
+                .retain(|id| !dead_panes.contains(id));
+            remove_from_mux(dead_panes);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes the panes for which f returns true from the tree in root,
+    /// which is root_size in size, giving their space to their siblings
+    /// and adjusting active_idx to account for the removed panes.
+    /// Returns the ids of the removed panes.
+    fn remove_leaves_if<F>(
+        &self,
+        root: &mut Option<Tree>,
+        root_size: PtySize,
+        active_idx: &mut usize,
+        f: F,
+    ) -> Vec<PaneId>
+    where
+        F: Fn(usize, &Rc<dyn Pane>) -> bool,
+    {
+        let mut dead_panes = vec![];
+        let zoomed_pane = self.zoomed.borrow().as_ref().map(|p| p.pane_id());
+        let mut cursor = match root.take() {
+            Some(tree) => tree.cursor(),
+            None => return dead_panes,
+        };
+        let mut pane_index = 0;
+        let cell_dims = self.cell_dimensions();
+
+        loop {
+            // Figure out the available size by looking at our immediate parent node.
+            // If we are the root, look at the tab size
+            let pane_size = if let Some((branch, Some(parent))) = cursor.path_to_root().next() {
+                if branch == PathBranch::IsRight {
+                    parent.second
+                } else {
+                    parent.first
+                }
+            } else {
+                root_size
+            };
+
+            if cursor.is_leaf() {
+                let pane = Rc::clone(cursor.leaf_mut().unwrap());
+                if f(pane_index, &pane) {
+                    if pane_index == *active_idx {
+                        *active_idx = pane_index.saturating_sub(1);
+                    }
+                    if Some(pane.pane_id()) == zoomed_pane {
+                        // If we removed the zoomed pane, un-zoom our state!
+                        self.zoomed.borrow_mut().take();
+                    }
+                    let parent;
+                    match cursor.unsplit_leaf() {
+                        Ok((c, dead, p)) => {
+                            dead_panes.push(dead.pane_id());
+                            parent = p.unwrap();
+                            cursor = c;
+                        }
+                        Err(c) => {
+                            // We might be the root, for example
+                            if c.is_top() && c.is_leaf() {
+                                root.replace(Tree::Empty);
+                                dead_panes.push(pane.pane_id());
+                            } else {
+                                root.replace(c.tree());
+                            }
+                            break;
+                        }
+                    };
+
+                    // Now we need to increase the size of the current node
+                    // and propagate the revised size to its children.
+                    let size = PtySize {
+                        rows: parent.height(),
+                        cols: parent.width(),
+                        pixel_width: cell_dims.pixel_width * parent.width(),
+                        pixel_height: cell_dims.pixel_height * parent.height(),
+                    };
+
+                    if let Some(unsplit) = cursor.leaf_mut() {
+                        unsplit.resize(size).ok();
+                    } else {
+                        self.apply_pane_size(size, &mut cursor);
+                    }
+                } else if !dead_panes.is_empty() {
+                    // Apply our revised size to the tty
+                    pane.resize(pane_size).ok();
+                }
+
+                pane_index += 1;
+            } else if !dead_panes.is_empty() {
+                self.apply_pane_size(pane_size, &mut cursor);
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    break;
+                }
+            }
+        }
+        dead_panes
+    }
+
+    /// Returns true if none of the panes in the tab, including those
+    /// hidden behind a zoomed pane, are running something that would
+    /// be lost by closing them without asking the user first
+    pub fn can_close_without_prompting(&self, reason: CloseReason) -> bool {
+        let panes = self.iter_panes_ignoring_zoom();
+        for pos in &panes {
+            if !pos.pane.can_close_without_prompting(reason) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn is_dead(&self) -> bool {
+        // Make sure we account for all panes, so that we don't
+        // kill the whole tab if the zoomed pane is dead!
+        let panes = self.iter_panes_ignoring_zoom();
+        let mut dead_count = 0;
+        for pos in &panes {
+            if pos.pane.is_dead() {
+                dead_count += 1;
+            }
+        }
+        // Likewise for the panes displaced by an overlay
+        dead_count == panes.len()
+            && self
+                .overlays
+                .borrow()
+                .iter()
+                .all(|displaced| all_panes_dead(&displaced.tree))
+    }
+
+    pub fn get_active_pane(&self) -> Option<Rc<dyn Pane>> {
+        if let Some(zoomed) = self.zoomed.borrow().as_ref() {
+            return Some(Rc::clone(zoomed));
+        }
+
+        self.iter_panes()
+            .iter()
+            .nth(*self.active.borrow())
+            .map(|p| Rc::clone(&p.pane))
+    }
+
+    /// Places the active pane into, or takes it out of, read-only mode;
+    /// see Pane::set_read_only.  Returns false if there is no active pane,
+    /// or if the active pane doesn't support read-only mode.
+    pub fn set_active_read_only(&self, read_only: bool) -> bool {
+        match self.get_active_pane() {
+            Some(pane) => {
+                pane.set_read_only(read_only);
+                pane.is_read_only() == read_only
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the active pane together with the split that holds it,
+    /// without removing it from the tab.  The split describes the
+    /// direction and the sizes of both halves of the slot, which is
+    /// the information required to recreate the slot elsewhere, for
+    /// example when moving the pane to another tab.
+    /// The bool is true if the pane occupies the right/bottom half of
+    /// the split, and false if it occupies the left/top half.
+    /// Returns None if the active pane isn't part of a split, which
+    /// is the case for a tab with a single pane.
+    /// The zoom state is not taken into account.
+    pub fn extract_active_pane(&self) -> Option<(Rc<dyn Pane>, SplitDirectionAndSize, bool)> {
+        let mut root = self.pane.borrow_mut();
+        let cursor = root.take()?.cursor();
+
+        match cursor.go_to_nth_leaf(self.get_active_idx()) {
+            Ok(mut cursor) => {
+                let parent = match cursor.path_to_root().next() {
+                    Some((branch, Some(split))) => Some((*split, branch == PathBranch::IsRight)),
+                    _ => None,
+                };
+                let pane = cursor.leaf_mut().map(|pane| Rc::clone(pane));
+                root.replace(cursor.tree());
+                let (split, is_second) = parent?;
+                Some((pane?, split, is_second))
+            }
+            Err(cursor) => {
+                root.replace(cursor.tree());
+                None
+            }
+        }
+    }
+
+    /// Captures the active pane and scroll position of each pane
+    pub fn capture_layout(&self) -> TabLayout {
+        TabLayout {
+            active_pane: self.get_active_pane().map(|pane| pane.pane_id()),
+            scroll_offsets: self
+                .iter_panes_ignoring_zoom()
+                .iter()
+                .map(|pos| (pos.pane.pane_id(), pos.pane.scroll_offset()))
+                .collect(),
+        }
+    }
+
+    /// Restores the active pane and scroll positions previously captured
+    /// by capture_layout.  Panes are matched up by their PaneId; entries
+    /// for panes that are no longer part of this tab are ignored.
+    pub fn restore_layout(&self, layout: &TabLayout) {
+        for pos in self.iter_panes_ignoring_zoom() {
+            let pane_id = pos.pane.pane_id();
+            if let Some((_, offset)) = layout.scroll_offsets.iter().find(|(id, _)| *id == pane_id) {
+                pos.pane.scroll_to(*offset);
+            }
+            if layout.active_pane == Some(pane_id) {
+                self.set_active_pane(&pos.pane);
+            }
+        }
+    }
+
+    /// Returns the split structure of the tab, with the panes
+    /// identified by their ids, in a form that can be persisted.
+    /// The zoom state is not taken into account.
+    pub fn serialize_layout(&self) -> SerializedTab {
+        match self.pane.borrow().as_ref() {
+            Some(root) => serialize_tree(root),
+            None => SerializedTab::Empty,
+        }
+    }
+
+    /// Replaces the panes and splits of the tab with those described by
+    /// layout, calling resolve to obtain the pane for each pane id.
+    /// Each pane is resized to fit its slot; if the layout was captured
+    /// at a different size, it is then resized to fit the tab.
+    /// The tab is unzoomed, and the active index is repaired to suit
+    /// the new set of panes; see repair_active.
+    pub fn rebuild_from_layout<F>(&self, layout: &SerializedTab, resolve: F)
+    where
+        F: Fn(PaneId) -> Rc<dyn Pane>,
+    {
+        self.set_zoomed(false);
+        let tree = tree_from_serialized(layout, &resolve);
+        let usable = self.usable_size();
+        let layout_size = match layout {
+            SerializedTab::Split { split, .. } => PtySize {
+                rows: split.height(),
+                cols: split.width(),
+                pixel_width: split.width() * self.cell_dimensions().pixel_width,
+                pixel_height: split.height() * self.cell_dimensions().pixel_height,
+            },
+            _ => usable,
+        };
+        apply_sizes_from_splits(
+            &tree,
+            &layout_size,
+            self.get_pane_gutter(),
+            self.get_pane_header_rows(),
+        );
+        self.pane.borrow_mut().replace(tree);
+        self.peeked.borrow_mut().take();
+
+        if layout_size != usable && !matches!(layout, SerializedTab::Empty) {
+            let size = *self.size.borrow();
+            self.resize_from(layout_size, size);
+        }
+        self.repair_active();
+    }
+
+    /// Like rebuild_from_layout, but first resizes the tab to new_size
+    /// and treats the split sizes in layout as proportions, scaling
+    /// them to fit; for example, to restore a session that was saved
+    /// on a screen of a different size.  Resizing the restored layout
+    /// with resize would instead give all of the change to the last
+    /// pane along each axis.
+    pub fn rebuild_from_layout_scaled<F>(
+        &self,
+        layout: &SerializedTab,
+        new_size: PtySize,
+        resolve: F,
+    ) where
+        F: Fn(PaneId) -> Rc<dyn Pane>,
+    {
+        if new_size.rows > 0 && new_size.cols > 0 {
+            *self.size.borrow_mut() = new_size;
+        }
+        let layout = scale_serialized(layout, &self.usable_size(), &self.cell_dimensions());
+        self.rebuild_from_layout(&layout, resolve);
+    }
+
+    /// Sets the width, in cells, of the border that the renderer draws
+    /// around the inside edge of the active pane
+    pub fn set_active_pane_border(&self, width: usize) {
+        *self.active_pane_border.borrow_mut() = width;
+    }
+
+    /// Returns the (left, top, width, height) of the active pane, in cells,
+    /// inset by the active pane border width.  This is the area that the
+    /// renderer should clip the pane content to.
+    pub fn active_pane_content_rect(&self) -> Option<(usize, usize, usize, usize)> {
+        let border = *self.active_pane_border.borrow();
+        self.iter_panes()
+            .into_iter()
+            .find(|pos| pos.is_active)
+            .map(|pos| {
+                (
+                    pos.left + border,
+                    pos.top + border,
+                    pos.width.saturating_sub(border * 2),
+                    pos.height.saturating_sub(border * 2),
+                )
+            })
+    }
+
+    /// Sets the number of rows at the top of each pane that the
+    /// renderer uses for chrome, such as a pane title bar.
+    /// The panes are resized so that their content fits beneath it.
+    pub fn set_pane_header_rows(&self, rows: u16) {
+        *self.pane_header_rows.borrow_mut() = rows;
+        match self.zoomed.borrow().as_ref() {
+            Some(zoomed) => {
+                zoomed
+                    .resize(inset_for_header(self.usable_size(), rows))
+                    .ok();
+            }
+            None => {
+                if let Some(root) = self.pane.borrow().as_ref() {
+                    apply_sizes_from_splits(
+                        root,
+                        &self.usable_size(),
+                        self.get_pane_gutter(),
+                        rows,
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn get_pane_header_rows(&self) -> u16 {
+        *self.pane_header_rows.borrow()
+    }
+
+    /// Sets the number of blank cells left between sibling panes in
+    /// addition to the divider, so that adjacent panes are separated
+    /// by 1 + gutter cells.  The gutter is taken from the pane that
+    /// follows each divider: iter_splits() reports the divider as the
+    /// first cell of the gap, while iter_panes(), compute_split_size()
+    /// and the directional navigation account for the whole gap.
+    pub fn set_pane_gutter(&self, gutter: u16) {
+        *self.pane_gutter.borrow_mut() = gutter;
+        if self.zoomed.borrow().is_none() {
+            if let Some(root) = self.pane.borrow().as_ref() {
+                apply_sizes_from_splits(
+                    root,
+                    &self.usable_size(),
+                    gutter,
+                    self.get_pane_header_rows(),
+                );
+            }
+        }
+    }
+
+    pub fn get_pane_gutter(&self) -> u16 {
+        *self.pane_gutter.borrow()
+    }
+
+    /// Sets the minimum width and height, in cells, that resizing the
+    /// tab will shrink a pane to; when the tab is made smaller than its
+    /// panes allow, it keeps the minimum size instead.  The default,
+    /// and the smallest permitted value, is 1 cell.
+    pub fn set_min_pane_size(&self, cols: usize, rows: usize) {
+        *self.min_pane_size.borrow_mut() = (cols.max(1), rows.max(1));
+    }
+
+    /// Returns the minimum (cols, rows) of a pane
+    pub fn get_min_pane_size(&self) -> (usize, usize) {
+        *self.min_pane_size.borrow()
+    }
+
+    /// Re-applies the split sizes to the panes after they have been
+    /// resized directly to their slots, so that the gutter and the
+    /// pane header rows are honored
+    fn apply_pane_insets(&self) {
+        let gutter = self.get_pane_gutter();
+        let header = self.get_pane_header_rows();
+        if (gutter == 0 && header == 0) || self.zoomed.borrow().is_some() {
+            return;
+        }
+        if let Some(root) = self.pane.borrow().as_ref() {
+            apply_sizes_from_splits(root, &self.usable_size(), gutter, header);
+        }
+    }
+
+    /// Returns the (left, top, width, height) in cells of the area in
+    /// which the content of each visible pane is drawn, keyed by
+    /// topological index.  This accounts for the reserved bottom rows,
+    /// the dividers, the pane header rows and the active pane border,
+    /// so renderers should use it to place the pane content.
+    pub fn content_grid(&self) -> Vec<(usize, (usize, usize, usize, usize))> {
+        let border = *self.active_pane_border.borrow();
+        let header = *self.pane_header_rows.borrow() as usize;
+        self.iter_panes()
+            .into_iter()
+            .map(|pos| {
+                let inset = if pos.is_active { border } else { 0 };
+                let top_inset = header + inset;
+                (
+                    pos.index,
+                    (
+                        pos.left + inset,
+                        pos.top + top_inset,
+                        pos.width.saturating_sub(inset * 2),
+                        pos.height.saturating_sub(top_inset + inset),
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the total number of bytes pasted into the panes in
+    /// this tab (see Pane::total_pasted_bytes)
+    pub fn total_pasted_bytes(&self) -> u64 {
+        let mut total = 0;
+        self.with_each_pane(|_, pane| total += pane.total_pasted_bytes());
+        total
+    }
+
+    /// Returns the pairs of pane indices whose viewports show the same
+    /// content, as determined by Pane::content_hash.  Each pair is
+    /// ordered by index, and the pairs are sorted.
+    /// The zoom state is not taken into account.
+    pub fn duplicate_content_panes(&self) -> Vec<(usize, usize)> {
+        let mut hashes = vec![];
+        self.with_each_pane(|index, pane| hashes.push((index, pane.content_hash())));
+
+        let mut pairs = vec![];
+        for (i, &(a, hash_a)) in hashes.iter().enumerate() {
+            for &(b, hash_b) in &hashes[i + 1..] {
+                if hash_a == hash_b {
+                    pairs.push((a, b));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Returns the sum of the scrollback memory estimates of the panes
+    /// in this tab, in bytes
+    pub fn scrollback_memory_estimate(&self) -> usize {
+        self.iter_panes_ignoring_zoom()
+            .iter()
+            .map(|p| p.pane.scrollback_memory_estimate())
+            .sum()
+    }
+
+    /// Returns the total number of rows, including the visible rows,
+    /// held in the scrollback of the panes in this tab; for example,
+    /// for diagnostics in large sessions
+    pub fn total_scrollback_rows(&self) -> usize {
+        self.iter_panes_ignoring_zoom()
+            .iter()
+            .map(|p| p.pane.get_dimensions().scrollback_rows)
+            .sum()
+    }
+
+    /// Like Pane::changed_cells for the pane at pane_index, but with the
+    /// cell ranges offset to be relative to the top left of the tab.
+    /// Returns None if pane_index is invalid.
+    pub fn changed_cells_in_tab(
+        &self,
+        pane_index: usize,
+        since_generation: u64,
+    ) -> Option<(u64, Vec<CellRange>)> {
+        let pos = self.iter_panes().into_iter().nth(pane_index)?;
+        let (generation, cells) = pos.pane.changed_cells(since_generation);
+        let cells = cells
+            .into_iter()
+            .map(|cell| CellRange {
+                y: cell.y + pos.top,
+                x: cell.x.start + pos.left..cell.x.end + pos.left,
+            })
+            .collect();
+        Some((generation, cells))
+    }
+
+    /// Returns the topological index of the pane that most recently
+    /// processed output (see Pane::last_activity).  If several panes
+    /// are tied, the first of them is returned.
+    /// The zoom state is not taken into account.
+    pub fn most_recently_active_pane(&self) -> Option<usize> {
+        let mut newest: Option<(usize, Instant)> = None;
+        self.with_each_pane(|index, pane| {
+            let activity = pane.last_activity();
+            match newest {
+                Some((_, newest_activity)) if newest_activity >= activity => {}
+                _ => newest = Some((index, activity)),
+            }
+        });
+        newest.map(|(index, _)| index)
+    }
+
+    /// Returns the indices of the panes whose width or height in the
+    /// layout is 0, including those hidden behind a zoomed pane.
+    /// Such panes are invisible and can't be used, so a non-empty
+    /// result indicates that the layout has been corrupted.
+    pub fn degenerate_panes(&self) -> Vec<usize> {
+        self.iter_panes_ignoring_zoom()
+            .iter()
+            .filter(|p| p.width == 0 || p.height == 0)
+            .map(|p| p.index)
+            .collect()
+    }
+
+    /// Returns true if any pane in the tab, including those hidden
+    /// behind a zoomed pane, is producing output faster than
+    /// BUSY_OUTPUT_RATE; for example, to show a busy indicator
+    pub fn any_pane_busy(&self) -> bool {
+        self.iter_panes_ignoring_zoom()
+            .iter()
+            .any(|p| p.pane.is_output_active(BUSY_OUTPUT_RATE))
+    }
+
+    /// Arranges for the id of the pane at pane_index to be returned by
+    /// take_triggered_panes, once, the next time that pattern is seen in
+    /// the output of that pane; for example, so that a notification can
+    /// be shown when a build running in a background tab completes.
+    /// The zoom state is not taken into account.
+    pub fn set_output_trigger(&self, pane_index: usize, pattern: Pattern) -> anyhow::Result<()> {
+        let pane = match self.iter_panes_ignoring_zoom().get(pane_index) {
+            Some(pos) => Rc::clone(&pos.pane),
+            None => anyhow::bail!("invalid pane_index {}", pane_index),
+        };
+        let triggered = Rc::clone(&self.triggered_panes);
+        pane.set_output_trigger(
+            pattern,
+            Box::new(move |pane_id| triggered.borrow_mut().push(pane_id)),
+        )
+    }
+
+    /// Returns the ids of the panes whose triggers, set by
+    /// set_output_trigger, have fired since the last call
+    pub fn take_triggered_panes(&self) -> Vec<PaneId> {
+        std::mem::take(&mut *self.triggered_panes.borrow_mut())
+    }
+
+    /// Returns a render priority for each pane, keyed by topological
+    /// index, so that a renderer under load can skip or defer the less
+    /// important panes.  Higher values are more important: the active
+    /// pane is given u8::MAX, the other visible panes are ranked by
+    /// their area (largest first) and panes that are hidden behind a
+    /// zoomed pane are given 0.
+    pub fn render_priorities(&self) -> Vec<(usize, u8)> {
+        let panes = self.iter_panes_ignoring_zoom();
+        let active = self.get_active_idx();
+        let zoomed = self.zoomed.borrow().is_some();
+
+        let mut priorities: Vec<(usize, u8)> = panes.iter().map(|p| (p.index, 0)).collect();
+        if !zoomed {
+            let mut others: Vec<&PositionedPane> =
+                panes.iter().filter(|p| p.index != active).collect();
+            others.sort_by_key(|p| std::cmp::Reverse(p.width * p.height));
+            for (rank, pos) in others.into_iter().enumerate() {
+                priorities[pos.index].1 = (u8::MAX as usize - 1).saturating_sub(rank).max(1) as u8;
+            }
+        }
+        if let Some(entry) = priorities.get_mut(active) {
+            entry.1 = u8::MAX;
+        }
+        priorities
+    }
+
+    /// Scrolls the viewport of the active pane by delta rows
+    pub fn scroll_active_pane(&self, delta: isize) {
+        if let Some(pane) = self.get_active_pane() {
+            pane.scroll_by(delta);
+        }
+    }
+
+    /// Returns the range of stable rows visible in the active pane
+    pub fn active_pane_viewport(&self) -> Option<Range<StableRowIndex>> {
+        self.get_active_pane().map(|pane| pane.viewport_range())
+    }
+
+    #[allow(unused)]
+    pub fn get_active_idx(&self) -> usize {
+        *self.active.borrow()
+    }
+
+    /// Returns the topological index of the pane that would receive
+    /// key presses.  Keys are delivered only to the active pane, as
+    /// there is no input broadcasting, so this is the active pane, or
+    /// None if the tab has no panes.
+    pub fn key_target(&self) -> Option<usize> {
+        let active = self.get_active_idx();
+        if active < self.count_panes() {
+            Some(active)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if pane_index is the topological index of the
+    /// active pane
+    pub fn is_pane_active(&self, pane_index: usize) -> bool {
+        pane_index == self.get_active_idx()
+    }
+
+    /// Returns true if the pane with the specified id is the active
+    /// pane in this tab.  Returns false if the tab doesn't contain it.
+    pub fn is_pane_id_active(&self, pane_id: PaneId) -> bool {
+        self.iter_panes_ignoring_zoom()
+            .iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+            .map(|p| self.is_pane_active(p.index))
+            .unwrap_or(false)
+    }
+
+    /// Returns the topological indices of the panes in reading order;
+    /// that is, sorted by their top edge and then by their left edge.
+    /// The zoom state is not taken into account.
+    fn panes_in_reading_order(&self) -> Vec<usize> {
+        let mut panes = self.iter_panes_ignoring_zoom();
+        panes.sort_by_key(|p| (p.top, p.left));
+        panes.into_iter().map(|p| p.index).collect()
+    }
+
+    /// Returns the topological index of the pane that follows the pane
+    /// at index from in reading order (top to bottom, then left to
+    /// right), wrapping around from the last pane to the first.
+    /// This is the spatial counterpart to stepping through the
+    /// topological indices.  If from is not a valid index, the first
+    /// pane in reading order is returned.
+    pub fn next_pane_spatial(&self, from: usize) -> usize {
+        self.step_pane_spatial(from, true)
+    }
+
+    /// Like next_pane_spatial, but steps backwards, wrapping around
+    /// from the first pane to the last
+    pub fn prev_pane_spatial(&self, from: usize) -> usize {
+        self.step_pane_spatial(from, false)
+    }
+
+    fn step_pane_spatial(&self, from: usize, forwards: bool) -> usize {
+        let order = self.panes_in_reading_order();
+        if order.is_empty() {
+            return from;
+        }
+        match order.iter().position(|&index| index == from) {
+            Some(pos) if forwards => order[(pos + 1) % order.len()],
+            Some(pos) => order[(pos + order.len() - 1) % order.len()],
+            None => order[0],
+        }
+    }
+
+    /// Returns the topological index of the pane with the specified id,
+    /// suitable for passing to set_active_idx; for example, to activate
+    /// a pane found by a mux-wide search.  The zoom state is not taken
+    /// into account.  Returns None if the tab doesn't contain the pane.
+    pub fn get_pane_index_by_id(&self, pane_id: PaneId) -> Option<usize> {
+        self.iter_panes_ignoring_zoom()
+            .iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+            .map(|p| p.index)
+    }
+
+    /// Returns the PositionedPane that iter_panes() reports for the pane
+    /// with the specified id.  Returns None if the tab doesn't contain
+    /// the pane, or if it is hidden because another pane is zoomed.
+    pub fn positioned_pane_by_id(&self, pane_id: PaneId) -> Option<PositionedPane> {
+        self.iter_panes()
+            .into_iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+    }
+
+    pub fn set_active_pane(&self, pane: &Rc<dyn Pane>) {
+        if let Some(item) = self
+            .iter_panes()
+            .iter()
+            .find(|p| p.pane.pane_id() == pane.pane_id())
+        {
+            let prior = self.get_active_pane();
+            self.store_active_idx(item.index);
+            self.advise_focus_change(prior);
+        }
+    }
+
+    fn advise_focus_change(&self, prior: Option<Rc<dyn Pane>>) {
+        let current = self.get_active_pane();
+        match (prior, current) {
+            (Some(prior), Some(current)) if prior.pane_id() != current.pane_id() => {
+                self.remember_active(prior.pane_id());
+                prior.focus_changed(false);
+                current.focus_changed(true);
+            }
+            (None, Some(current)) => {
+                current.focus_changed(true);
+            }
+            (Some(prior), None) => {
+                prior.focus_changed(false);
+            }
+            (Some(_), Some(_)) | (None, None) => {
+                // no change
+            }
+        }
+    }
+
+    pub fn set_active_idx(&self, pane_index: usize) {
+        let prior = self.get_active_pane();
+        self.store_active_idx(pane_index);
+        self.advise_focus_change(prior);
+    }
+
+    /// Sets the active index, notifying the subscribers registered via
+    /// subscribe_active_change if it has changed
+    fn store_active_idx(&self, pane_index: usize) {
+        let prior = self.get_active_idx();
+        *self.active.borrow_mut() = pane_index;
+        self.fire_active_change(prior);
+    }
+
+    /// Calls the active change subscribers if the active index is no
+    /// longer prior.  The subscribers are called without any of the
+    /// tab state borrowed, so they are free to query the tab.
+    fn fire_active_change(&self, prior: usize) {
+        self.note_active_rect();
+        let active = self.get_active_idx();
+        if active == prior {
+            return;
+        }
+        let subscribers: Vec<Rc<dyn Fn(usize)>> = self
+            .active_subscribers
+            .borrow()
+            .iter()
+            .map(|(_, cb)| Rc::clone(cb))
+            .collect();
+        for cb in subscribers {
+            cb(active);
+        }
+    }
+
+    /// Registers cb to be called with the new active index whenever the
+    /// active index changes, for example to update a status display.
+    /// It isn't called when the active index is set to its current value.
+    /// Returns an id that can be passed to unsubscribe_active_change.
+    pub fn subscribe_active_change(&self, cb: Box<dyn Fn(usize)>) -> usize {
+        let sub_id = ACTIVE_SUB_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed);
+        self.active_subscribers
+            .borrow_mut()
+            .push((sub_id, Rc::from(cb)));
+        sub_id
+    }
+
+    /// Removes a subscription made by subscribe_active_change.
+    /// Returns false if there was no such subscription.
+    pub fn unsubscribe_active_change(&self, sub_id: usize) -> bool {
+        let mut subscribers = self.active_subscribers.borrow_mut();
+        let len = subscribers.len();
+        subscribers.retain(|(id, _)| *id != sub_id);
+        subscribers.len() != len
+    }
+
+    /// Records pane_id as the most recently active pane
+    fn remember_active(&self, pane_id: PaneId) {
+        let mut history = self.active_history.borrow_mut();
+        history.retain(|&id| id != pane_id);
+        history.push(pane_id);
+        if history.len() > ACTIVE_HISTORY_LEN {
+            history.remove(0);
+        }
+    }
+
+    /// Activates the most recently active pane other than the current
+    /// one, so that repeated calls alternate between two panes.
+    /// The history is tracked by pane id, so it follows the panes as
+    /// their indices change; panes that have since been removed are
+    /// skipped.  Returns false if there is no such pane.
+    pub fn activate_last_pane(&self) -> bool {
+        let ids: Vec<PaneId> = self
+            .iter_panes_ignoring_zoom()
+            .iter()
+            .map(|pos| pos.pane.pane_id())
+            .collect();
+        let current = self.get_active_pane().map(|pane| pane.pane_id());
+        loop {
+            let pane_id = match self.active_history.borrow_mut().pop() {
+                Some(pane_id) => pane_id,
+                None => return false,
+            };
+            if Some(pane_id) == current {
+                continue;
+            }
+            if let Some(index) = ids.iter().position(|&id| id == pane_id) {
+                // This records the current pane in the history, so that
+                // the next call returns to it
+                self.set_active_idx(index);
+                return true;
+            }
+        }
+    }
+
+    /// Records the id and position of the active pane for repair_active
+    fn note_active_rect(&self) {
+        let active = self.get_active_idx();
+        let noted = self
+            .iter_panes_ignoring_zoom()
+            .into_iter()
+            .find(|pos| pos.index == active)
+            .map(|pos| {
+                (
+                    pos.pane.pane_id(),
+                    (pos.left, pos.top, pos.width, pos.height),
+                )
+            });
+        *self.active_rect.borrow_mut() = noted;
+    }
+
+    /// Ensures that the active index refers to the pane that was active
+    /// before the tree was rearranged, for example by manual tree surgery.
+    /// If that pane is no longer in the tab, its spatial neighbor is
+    /// activated instead; that is, the pane nearest to the center of
+    /// the area that it used to occupy.  Failing that, an out of range
+    /// index is clamped to the last pane.
+    pub fn repair_active(&self) {
+        let noted = *self.active_rect.borrow();
+        let panes = self.iter_panes_ignoring_zoom();
+        let active = self.get_active_idx();
+
+        let repaired = match noted {
+            Some((pane_id, (left, top, width, height))) => {
+                match panes.iter().find(|pos| pos.pane.pane_id() == pane_id) {
+                    Some(pos) => Some(pos.index),
+                    None => self
+                        .panes_by_distance(left + width / 2, top + height / 2)
+                        .first()
+                        .map(|(index, _)| *index),
+                }
+            }
+            None => None,
+        };
+        let repaired = repaired.unwrap_or_else(|| active.min(panes.len().saturating_sub(1)));
+
+        if repaired != active {
+            self.set_active_idx(repaired);
+        } else {
+            self.note_active_rect();
+        }
+    }
+
+    /// Activates pane_index in response to an implicit event, such as
+    /// the mouse moving over a pane with pane_focus_follows_mouse enabled.
+    /// This is a no-op while focus is locked.
+    pub fn auto_activate_idx(&self, pane_index: usize) {
+        if *self.focus_locked.borrow() {
+            return;
+        }
+        self.set_active_idx(pane_index);
+    }
+
+    /// When focus is locked, pruning panes and automatic activation
+    /// (see auto_activate_idx) won't move the active pane.
+    /// Explicitly calling set_active_idx still works.
+    pub fn set_focus_locked(&self, locked: bool) {
+        *self.focus_locked.borrow_mut() = locked;
+    }
+
+    pub fn is_focus_locked(&self) -> bool {
+        *self.focus_locked.borrow()
+    }
+
+    /// Assigns the root pane.
+    /// This is suitable when creating a new tab and then assigning
+    /// the initial pane
+    pub fn assign_pane(&self, pane: &Rc<dyn Pane>) {
+        match Tree::new().cursor().assign_top(Rc::clone(pane)) {
+            Ok(c) => *self.pane.borrow_mut() = Some(c.tree()),
+            Err(_) => panic!("tried to assign root pane to non-empty tree"),
+        }
+    }
+
+    fn cell_dimensions(&self) -> PtySize {
+        cell_dimensions(&*self.size.borrow())
+    }
+
+    /// Temporarily replaces the layout of the tab with pane, which is
+    /// resized to fill the tab and becomes the active pane; for example,
+    /// to show a modal command palette.  The displaced layout, along
+    /// with its active pane and zoom state, is restored by
+    /// pop_overlay_pane.  Overlays may be nested.
+    /// The displaced panes still count towards is_dead, and are pruned
+    /// by prune_dead_panes if they die; if the overlay dies, pruning
+    /// pops it.
+    pub fn push_overlay_pane(&self, pane: Rc<dyn Pane>) {
+        let zoomed = self.zoomed.borrow().is_some();
+        self.set_zoomed(false);
+        let size = self.usable_size();
+        pane.resize(inset_for_header(size, self.get_pane_header_rows()))
+            .ok();
+
+        let tree = self
+            .pane
+            .borrow_mut()
+            .replace(Tree::Leaf(pane))
+            .unwrap_or_else(Tree::new);
+        let active = self.get_active_idx();
+        self.store_active_idx(0);
+        self.overlays.borrow_mut().push(DisplacedLayout {
+            tree,
+            active,
+            zoomed,
+            size,
+        });
+    }
+
+    /// Removes the most recently pushed overlay pane and restores the
+    /// layout that it displaced, resizing it if the tab has been resized
+    /// in the meantime.  Returns the overlay pane, or None if there is
+    /// no overlay.
+    pub fn pop_overlay_pane(&self) -> Option<Rc<dyn Pane>> {
+        let displaced = self.overlays.borrow_mut().pop()?;
+        self.set_zoomed(false);
+        let overlay = match self.pane.borrow_mut().replace(displaced.tree) {
+            Some(Tree::Leaf(pane)) => Some(pane),
+            _ => None,
+        };
+        self.store_active_idx(displaced.active);
+
+        let size = self.usable_size();
+        if size != displaced.size {
+            self.resize_from(displaced.size, self.get_size());
+        }
+        if displaced.zoomed {
+            self.set_zoomed(true);
+        }
+        overlay
+    }
+
+    /// Returns true if an overlay pane has been pushed
+    pub fn has_overlay_pane(&self) -> bool {
+        !self.overlays.borrow().is_empty()
+    }
+
+    /// Computes the size of the pane that would result if the specified
+    /// pane was split in a particular direction.
+    /// The intent is to call this prior to spawning the new pane so that
+    /// you can create it with the correct size.  The sizes account for
+    /// the pane gutter, so they match the sizes that the panes are
+    /// given once the split has been made.
+    /// May return None if the specified pane_index is invalid.
+    pub fn compute_split_size(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+    ) -> Option<SplitDirectionAndSize> {
+        self.compute_split_size_with_options(pane_index, direction, SplitOptions::default())
+    }
+
+    /// Like compute_split_size, but takes into account the options that
+    /// will be passed to split_and_insert_with_options.  When
+    /// options.insert_before is set, the newly created leaf is the first
+    /// child of the split, so that is the one that is made slightly
+    /// smaller to leave room for the split UI.
+    pub fn compute_split_size_with_options(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        options: SplitOptions,
+    ) -> Option<SplitDirectionAndSize> {
+        let slots = self.compute_split_slots(pane_index, direction, options)?;
+        let (left, top, _, _) = self.pane_rect(pane_index)?;
+        let gutter = self.get_pane_gutter();
+        // The second child follows the new divider, and both children
+        // follow any divider that precedes the slot being split
+        let (second_x, second_y) = match direction {
+            SplitDirection::Horizontal => (true, top > 0),
+            SplitDirection::Vertical => (left > 0, true),
+        };
+        Some(SplitDirectionAndSize {
+            direction,
+            first: inset_for_gutter(slots.first, gutter, left > 0, top > 0),
+            second: inset_for_gutter(slots.second, gutter, second_x, second_y),
+        })
+    }
+
+    /// Computes the sizes of the slots that the split would divide the
+    /// slot of pane_index into.  These are the sizes recorded in the
+    /// tree; they include the gutter, which is only taken into account
+    /// when placing the panes.
+    fn compute_split_slots(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        options: SplitOptions,
+    ) -> Option<SplitDirectionAndSize> {
+        let cell_dims = self.cell_dimensions();
+
+        // Ensure that we're not zoomed, otherwise we'll end up in
+        // a bogus split state (https://github.com/wez/wezterm/issues/723)
+        self.set_zoomed(false);
+
+        // Split the whole slot, rather than the pane within it
+        self.slot_size(pane_index).map(|slot| {
+            let split_dimension = |dim: usize| -> (usize, usize) {
+                if let Some(ratio) = options.ratio {
+                    // Allow 1 cell for the split UI, and keep at least
+                    // 1 cell for each of the children
+                    let avail = dim.saturating_sub(1);
+                    let first = ((avail as f32 * ratio).round() as usize)
+                        .max(1)
+                        .min(avail.saturating_sub(1));
+                    return (first, avail.saturating_sub(first));
+                }
+                let halved = dim / 2;
+                if halved * 2 == dim {
+                    // Was an even size; we need to allow 1 cell to render
+                    // the split UI, so make the newly created leaf slightly
+                    // smaller
+                    if options.insert_before {
+                        (halved.saturating_sub(1), halved)
+                    } else {
+                        (halved, halved.saturating_sub(1))
+                    }
+                } else {
+                    (halved, halved)
+                }
+            };
+
+            let ((width1, width2), (height1, height2)) = match direction {
+                SplitDirection::Horizontal => (
+                    split_dimension(slot.cols as usize),
+                    (slot.rows as usize, slot.rows as usize),
+                ),
+                SplitDirection::Vertical => (
+                    (slot.cols as usize, slot.cols as usize),
+                    split_dimension(slot.rows as usize),
+                ),
+            };
+
+            SplitDirectionAndSize {
+                direction,
+                first: PtySize {
+                    rows: height1 as _,
+                    cols: width1 as _,
+                    pixel_height: cell_dims.pixel_height * height1 as u16,
+                    pixel_width: cell_dims.pixel_width * width1 as u16,
+                },
+                second: PtySize {
+                    rows: height2 as _,
+                    cols: width2 as _,
+                    pixel_height: cell_dims.pixel_height * height2 as u16,
+                    pixel_width: cell_dims.pixel_width * width2 as u16,
+                },
+            }
+        })
+    }
+
+    /// Split the pane that has pane_index in the given direction and assign
+    /// the right/bottom pane of the newly created split to the provided Pane
+    /// instance.  Returns the resultant index of the newly inserted pane.
+    /// Both the split and the inserted pane will be resized.
+    pub fn split_and_insert(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<usize> {
+        self.split_and_insert_with_options(pane_index, direction, pane, SplitOptions::default())
+    }
+
+    /// Like split_and_insert, but the behavior of the split can be
+    /// adjusted via options.
+    /// When options.insert_before is set, the new pane takes the
+    /// left/top position and so has index pane_index, while the
+    /// existing pane moves to pane_index + 1.
+    pub fn split_and_insert_with_options(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        pane: Rc<dyn Pane>,
+        options: SplitOptions,
+    ) -> anyhow::Result<usize> {
+        if self.zoomed.borrow().is_some() {
+            anyhow::bail!("cannot split while zoomed");
+        }
+
+        let max_panes = self.get_max_panes();
+        if self.count_panes() >= max_panes {
+            anyhow::bail!(
+                "cannot split: tab already has the maximum of {} panes",
+                max_panes
+            );
+        }
+
+        if let Some(ratio) = options.ratio {
+            if !(ratio > 0.0 && ratio < 1.0) {
+                anyhow::bail!("split ratio {} must be between 0.0 and 1.0", ratio);
+            }
+        }
+
+        let new_index = if options.insert_before {
+            pane_index
+        } else {
+            pane_index + 1
+        };
+
+        let prior_active = self.get_active_idx();
+        {
+            let split_info = self
+                .compute_split_slots(pane_index, direction, options)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("invalid pane_index {}; cannot split!", pane_index)
+                })?;
+            let tab_size = self.usable_size();
+            if split_info.first.rows == 0
+                || split_info.first.cols == 0
+                || split_info.second.rows == 0
+                || split_info.second.cols == 0
+                || split_info.top_of_second() as u16 + split_info.second.rows > tab_size.rows
+                || split_info.left_of_second() as u16 + split_info.second.cols > tab_size.cols
+            {
+                log::error!(
+                    "No splace for split!!! {:#?} height={} width={} top_of_second={} left_of_second={} tab_size={:?}",
+                    split_info,
+                    split_info.height(),
+                    split_info.width(),
+                    split_info.top_of_second(),
+                    split_info.left_of_second(),
+                    tab_size
+                );
+                anyhow::bail!("No space for split!");
+            }
+
+            let mut root = self.pane.borrow_mut();
+            let mut cursor = root.take().unwrap().cursor();
+
+            match cursor.go_to_nth_leaf(pane_index) {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    anyhow::bail!("invalid pane_index {}; cannot split!", pane_index);
+                }
+            };
+
+            let existing_pane = Rc::clone(cursor.leaf_mut().unwrap());
+
+            let split = if options.insert_before {
+                existing_pane.resize(split_info.second)?;
+                pane.resize(split_info.first)?;
+                cursor.split_leaf_and_insert_left(pane)
+            } else {
+                existing_pane.resize(split_info.first)?;
+                pane.resize(split_info.second)?;
+                cursor.split_leaf_and_insert_right(pane)
+            };
+
+            match split {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    anyhow::bail!("invalid pane_index {}; cannot split!", pane_index);
+                }
+            };
+
+            // cursor now points to the newly created split node;
+            // we need to populate its split information
+            match cursor.assign_node(Some(split_info)) {
+                Err(c) | Ok(c) => root.replace(c.tree()),
+            };
+
+            *self.active.borrow_mut() = new_index;
+        }
+        self.fire_active_change(prior_active);
+        self.apply_pane_insets();
+        self.peeked.borrow_mut().take();
+
+        if options.balance_siblings {
+            self.balance_siblings_of(new_index, direction);
+        }
+
+        log::debug!("split info after split: {:#?}", self.iter_splits());
+        log::debug!("pane info after split: {:#?}", self.iter_panes());
+
+        Ok(new_index)
+    }
+
+    /// Splits the pane at pane_index at the golden ratio, giving roughly
+    /// 61.8% of the space to the existing pane and the remainder to the
+    /// new pane.  Returns the index of the newly inserted pane.
+    pub fn split_golden(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<usize> {
+        self.split_and_insert_with_options(
+            pane_index,
+            direction,
+            pane,
+            SplitOptions {
+                ratio: Some(GOLDEN_RATIO),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Like split_and_insert_with_options, but also returns the
+    /// PositionedPane for the newly inserted pane, including its pixel
+    /// dimensions, so that the caller can render it straight away.
+    pub fn split_and_insert_positioned(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        pane: Rc<dyn Pane>,
+        options: SplitOptions,
+    ) -> anyhow::Result<(usize, PositionedPane)> {
+        let new_index = self.split_and_insert_with_options(pane_index, direction, pane, options)?;
+        let pos = self
+            .iter_panes()
+            .into_iter()
+            .nth(new_index)
+            .ok_or_else(|| anyhow::anyhow!("split pane {} went missing", new_index))?;
+        Ok((new_index, pos))
+    }
+
+    /// Splits the pane at pane_index, keeping an even row or column even.
+    /// If the pane is part of a chain of splits in the same direction, the
+    /// new pane becomes another sibling in that chain and all of the
+    /// siblings are given equal sizes; otherwise this is a normal half
+    /// split.  See SplitOptions::balance_siblings.
+    /// Returns the index of the newly inserted pane.
+    pub fn split_keeping_even(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<usize> {
+        self.split_and_insert_with_options(
+            pane_index,
+            direction,
+            pane,
+            SplitOptions {
+                balance_siblings: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Replaces the pane at pane_index with the provided pane, which is
+    /// resized to fit the same space.  This is intended to swap a
+    /// placeholder, such as a PendingPane, for the real pane once it
+    /// has been spawned.  Returns the pane that was replaced.
+    pub fn replace_pane(
+        &self,
+        pane_index: usize,
+        pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<Rc<dyn Pane>> {
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = match root.take().unwrap().cursor().go_to_nth_leaf(pane_index) {
+            Ok(c) => c,
+            Err(c) => {
+                root.replace(c.tree());
+                anyhow::bail!("invalid pane_index {}; cannot replace!", pane_index);
+            }
+        };
+
+        let (_, _, size) = self.leaf_position(&cursor);
+        let mut zoomed = self.zoomed.borrow_mut();
+        let is_zoomed = is_pane(cursor.leaf_mut().unwrap(), &zoomed.as_ref());
+        let size = if is_zoomed { self.usable_size() } else { size };
+        let size = inset_for_header(size, self.get_pane_header_rows());
+
+        if let Err(err) = pane.resize(size) {
+            root.replace(cursor.tree());
+            return Err(err);
+        }
+
+        let prior = std::mem::replace(cursor.leaf_mut().unwrap(), Rc::clone(&pane));
+        if is_zoomed {
+            pane.set_zoomed(true);
+            zoomed.replace(pane);
+        }
+        root.replace(cursor.tree());
+        Ok(prior)
+    }
+
+    /// Equalizes the sizes of the panes (or nested splits) that sit
+    /// alongside pane_index in a chain of splits of the given direction,
+    /// and resizes the affected panes.
+    fn balance_siblings_of(&self, pane_index: usize, direction: SplitDirection) {
+        let cell_dims = self.cell_dimensions();
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = match root.take().unwrap().cursor().go_to_nth_leaf(pane_index) {
+            Ok(c) => c,
+            Err(c) => {
+                root.replace(c.tree());
+                return;
+            }
+        };
+
+        // Walk up to the top of the chain of splits that share the direction
+        loop {
+            match cursor.path_to_root().next() {
+                Some((_, Some(parent))) if parent.direction == direction => {}
+                _ => break,
+            }
+            cursor = match cursor.go_up() {
+                Ok(c) => c,
+                Err(c) => {
+                    cursor = c;
+                    break;
+                }
+            };
+        }
+
+        if let Ok(Some(node)) = cursor.node_mut() {
+            let current = match direction {
+                SplitDirection::Horizontal => node.width(),
+                SplitDirection::Vertical => node.height(),
+            };
+            flatten_chain(cursor.subtree_mut(), direction);
+            let count = count_items_along(cursor.subtree(), direction);
+            let sizes = even_item_sizes(current, count);
+            apply_item_sizes(
+                cursor.subtree_mut(),
+                direction,
+                &sizes,
+                current,
+                &cell_dims,
+                self.get_min_pane_size(),
+            );
+        }
+
+        root.replace(cursor.tree());
+        apply_sizes_from_splits(
+            root.as_ref().unwrap(),
+            &self.usable_size(),
+            self.get_pane_gutter(),
+            self.get_pane_header_rows(),
+        );
+    }
+}
+
+/// This type is used directly by the codec, take care to bump
+/// the codec version if you change this
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub enum PaneNode {
+    Empty,
+    Split {
+        left: Box<PaneNode>,
+        right: Box<PaneNode>,
+        node: SplitDirectionAndSize,
+    },
+    Leaf(PaneEntry),
+}
+
+impl PaneNode {
+    pub fn into_tree(self) -> bintree::Tree<PaneEntry, SplitDirectionAndSize> {
+        match self {
+            PaneNode::Empty => bintree::Tree::Empty,
+            PaneNode::Split { left, right, node } => bintree::Tree::Node {
+                left: Box::new((*left).into_tree()),
+                right: Box::new((*right).into_tree()),
+                data: Some(node),
+            },
+            PaneNode::Leaf(e) => bintree::Tree::Leaf(e),
+        }
+    }
+
+    pub fn root_size(&self) -> Option<PtySize> {
+        match self {
+            PaneNode::Empty => None,
+            PaneNode::Split { node, .. } => Some(node.size()),
+            PaneNode::Leaf(entry) => Some(entry.size),
+        }
+    }
+
+    pub fn window_and_tab_ids(&self) -> Option<(WindowId, TabId)> {
+        match self {
+            PaneNode::Empty => None,
+            PaneNode::Split { left, right, .. } => match left.window_and_tab_ids() {
+                Some(res) => Some(res),
+                None => right.window_and_tab_ids(),
+            },
+            PaneNode::Leaf(entry) => Some((entry.window_id, entry.tab_id)),
+        }
+    }
+}
+
+/// This type is used directly by the codec, take care to bump
+/// the codec version if you change this
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct PaneEntry {
+    pub window_id: WindowId,
+    pub tab_id: TabId,
+    pub pane_id: PaneId,
+    pub title: String,
+    pub size: PtySize,
+    pub working_dir: Option<SerdeUrl>,
+    pub is_active_pane: bool,
+    pub is_zoomed_pane: bool,
+    pub workspace: String,
+}
+
+#[derive(Deserialize, Clone, Serialize, PartialEq, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct SerdeUrl {
+    pub url: Url,
+}
+
+impl std::convert::TryFrom<String> for SerdeUrl {
+    type Error = url::ParseError;
+    fn try_from(s: String) -> Result<SerdeUrl, url::ParseError> {
+        let url = Url::parse(&s)?;
+        Ok(SerdeUrl { url })
+    }
+}
+
+impl From<Url> for SerdeUrl {
+    fn from(url: Url) -> SerdeUrl {
+        SerdeUrl { url }
+    }
+}
+
+impl Into<Url> for SerdeUrl {
+    fn into(self) -> Url {
+        self.url
+    }
+}
+
+impl Into<String> for SerdeUrl {
+    fn into(self) -> String {
+        self.url.as_str().into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pendingpane::PendingPane;
+    use crate::renderable::*;
+    use crate::tmux_layout::tmux_layout_checksum;
+    use rangeset::RangeSet;
+    use std::ops::Range;
+    use termwiz::surface::SequenceNo;
+    use url::Url;
+    use wezterm_term::color::ColorPalette;
+    use wezterm_term::{KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex};
+
+    /// How many rows of scrollback a FakePane reports
+    const FAKE_SCROLLBACK: usize = 100;
+
+    struct FakePane {
+        id: PaneId,
+        size: RefCell<PtySize>,
+        dead: RefCell<bool>,
+        viewport_top: RefCell<Option<StableRowIndex>>,
+        preferred_size: RefCell<Option<PtySize>>,
+        changes: RefCell<Vec<(StableRowIndex, SequenceNo)>>,
+        last_activity: RefCell<Instant>,
+        output_rate: RefCell<OutputRate>,
+        pasted_bytes: RefCell<u64>,
+        foreground_process: RefCell<Option<String>>,
+        read_only: RefCell<bool>,
+        output_triggers: RefCell<Vec<OutputTrigger>>,
+    }
+
+    lazy_static::lazy_static! {
+        static ref FAKE_EPOCH: Instant = Instant::now();
+    }
+
+    impl FakePane {
+        fn new(id: PaneId, size: PtySize) -> Rc<dyn Pane> {
+            Rc::new(Self {
+                id,
+                size: RefCell::new(size),
+                dead: RefCell::new(false),
+                viewport_top: RefCell::new(None),
+                preferred_size: RefCell::new(None),
+                changes: RefCell::new(vec![]),
+                last_activity: RefCell::new(*FAKE_EPOCH),
+                output_rate: RefCell::new(OutputRate::default()),
+                pasted_bytes: RefCell::new(0),
+                foreground_process: RefCell::new(None),
+                read_only: RefCell::new(false),
+                output_triggers: RefCell::new(vec![]),
+            })
+        }
+    }
+
+    impl Pane for FakePane {
+        fn pane_id(&self) -> PaneId {
+            self.id
+        }
+
+        fn get_cursor_position(&self) -> StableCursorPosition {
+            unimplemented!();
+        }
+
+        fn get_current_seqno(&self) -> SequenceNo {
+            self.changes
+                .borrow()
+                .iter()
+                .map(|&(_, seqno)| seqno)
+                .max()
+                .unwrap_or(0)
+        }
+
+        fn get_changed_since(
+            &self,
+            lines: Range<StableRowIndex>,
+            seqno: SequenceNo,
+        ) -> RangeSet<StableRowIndex> {
+            let mut set = RangeSet::new();
+            for &(row, changed) in self.changes.borrow().iter() {
+                if changed > seqno && lines.contains(&row) {
+                    set.add(row);
+                }
+            }
+            set
+        }
+
+        fn is_dirty(&self, seqno: SequenceNo) -> bool {
+            self.get_current_seqno() > seqno
+        }
+
+        fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+            // Fill each line with the last digit of the pane id
+            let cols = self.size.borrow().cols as usize;
+            let text = (self.id % 10).to_string().repeat(cols);
+            (
+                lines.start,
+                lines
+                    .map(|_| Line::from_text(&text, &Default::default(), 0))
+                    .collect(),
+            )
+        }
+
+        fn get_dimensions(&self) -> RenderableDimensions {
+            let size = self.size.borrow();
+            RenderableDimensions {
+                cols: size.cols as usize,
+                viewport_rows: size.rows as usize,
+                scrollback_rows: size.rows as usize + FAKE_SCROLLBACK,
+                physical_top: FAKE_SCROLLBACK as StableRowIndex,
+                scrollback_top: 0,
+            }
+        }
+
+        fn scroll_offset(&self) -> StableRowIndex {
+            self.viewport_top
+                .borrow()
+                .unwrap_or(FAKE_SCROLLBACK as StableRowIndex)
+        }
+
+        fn set_viewport_top(&self, top: StableRowIndex) {
+            self.viewport_top.borrow_mut().replace(top);
+        }
+
+        fn get_title(&self) -> String {
+            format!("pane {}", self.id)
+        }
+        fn send_paste(&self, text: &str) -> anyhow::Result<()> {
+            if self.is_read_only() {
+                return Ok(());
+            }
+            *self.pasted_bytes.borrow_mut() += text.len() as u64;
+            Ok(())
+        }
+        fn total_pasted_bytes(&self) -> u64 {
+            *self.pasted_bytes.borrow()
+        }
+        fn set_read_only(&self, read_only: bool) {
+            *self.read_only.borrow_mut() = read_only;
+        }
+        fn is_read_only(&self) -> bool {
+            *self.read_only.borrow()
+        }
+        fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>> {
+            Ok(None)
+        }
+        fn writer(&self) -> RefMut<dyn std::io::Write> {
+            unimplemented!()
+        }
+        fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+            *self.size.borrow_mut() = size;
+            Ok(())
+        }
+        fn preferred_size(&self) -> Option<PtySize> {
+            *self.preferred_size.borrow()
+        }
+        fn scrollback_memory_estimate(&self) -> usize {
+            self.id * 1024
+        }
+        fn perform_actions(&self, actions: Vec<termwiz::escape::Action>) {
+            *self.last_activity.borrow_mut() = Instant::now();
+            let text = output_trigger_text(&actions);
+            fire_output_triggers(&self.output_triggers, self.id, &text);
+        }
+        fn set_output_trigger(
+            &self,
+            pattern: Pattern,
+            callback: OutputTriggerCallback,
+        ) -> anyhow::Result<()> {
+            let trigger = OutputTrigger::new(pattern, callback)?;
+            self.output_triggers.borrow_mut().push(trigger);
+            Ok(())
+        }
+        fn record_output(&self, bytes: usize) {
+            self.output_rate.borrow_mut().record(bytes);
+        }
+        fn last_activity(&self) -> Instant {
+            *self.last_activity.borrow()
+        }
+        fn output_rate(&self) -> f64 {
+            self.output_rate.borrow().rate()
+        }
+
+        fn key_down(&self, _key: KeyCode, _mods: KeyModifiers) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+        fn key_up(&self, _: KeyCode, _: KeyModifiers) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+        fn mouse_event(&self, _event: MouseEvent) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+        fn is_dead(&self) -> bool {
+            *self.dead.borrow()
+        }
+        fn kill(&self) {
+            *self.dead.borrow_mut() = true;
+        }
+        fn palette(&self) -> ColorPalette {
+            unimplemented!()
+        }
+        fn domain_id(&self) -> DomainId {
+            1
+        }
+        fn is_mouse_grabbed(&self) -> bool {
+            false
+        }
+        fn is_alt_screen_active(&self) -> bool {
+            false
+        }
+        fn get_current_working_dir(&self) -> Option<Url> {
+            None
+        }
+        fn get_foreground_process_name(&self) -> Option<String> {
+            self.foreground_process.borrow().clone()
+        }
+        fn can_close_without_prompting(&self, _reason: CloseReason) -> bool {
+            // The shell is represented by not having a foreground process
+            self.foreground_process.borrow().is_none()
+        }
+    }
+
+    #[test]
+    fn tab_splitting() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        let panes = tab.iter_panes();
+        assert_eq!(1, panes.len());
+        assert_eq!(0, panes[0].index);
+        assert_eq!(true, panes[0].is_active);
+        assert_eq!(0, panes[0].left);
+        assert_eq!(0, panes[0].top);
+        assert_eq!(80, panes[0].width);
+        assert_eq!(24, panes[0].height);
+
+        assert!(tab
+            .compute_split_size(1, SplitDirection::Horizontal)
+            .is_none());
+
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        assert_eq!(
+            horz_size,
+            SplitDirectionAndSize {
+                direction: SplitDirection::Horizontal,
+                first: PtySize {
+                    rows: 24,
+                    cols: 40,
+                    pixel_width: 400,
+                    pixel_height: 600
+                },
+                second: PtySize {
+                    rows: 24,
+                    cols: 39,
+                    pixel_width: 390,
+                    pixel_height: 600
+                },
+            }
+        );
+
+        let vert_size = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        assert_eq!(
+            vert_size,
+            SplitDirectionAndSize {
+                direction: SplitDirection::Vertical,
+                first: PtySize {
+                    rows: 12,
+                    cols: 80,
+                    pixel_width: 800,
+                    pixel_height: 300
+                },
+                second: PtySize {
+                    rows: 11,
+                    cols: 80,
+                    pixel_width: 800,
+                    pixel_height: 275
+                }
+            }
+        );
+
+        let new_index = tab
+            .split_and_insert(
+                0,
+                SplitDirection::Horizontal,
+                FakePane::new(2, horz_size.second),
+            )
+            .unwrap();
+        assert_eq!(new_index, 1);
+
+        let panes = tab.iter_panes();
+        assert_eq!(2, panes.len());
+
+        assert_eq!(0, panes[0].index);
+        assert_eq!(false, panes[0].is_active);
+        assert_eq!(0, panes[0].left);
+        assert_eq!(0, panes[0].top);
+        assert_eq!(40, panes[0].width);
+        assert_eq!(24, panes[0].height);
+        assert_eq!(400, panes[0].pixel_width);
+        assert_eq!(600, panes[0].pixel_height);
+        assert_eq!(1, panes[0].pane.pane_id());
+
+        assert_eq!(1, panes[1].index);
+        assert_eq!(true, panes[1].is_active);
+        assert_eq!(41, panes[1].left);
+        assert_eq!(0, panes[1].top);
+        assert_eq!(39, panes[1].width);
+        assert_eq!(24, panes[1].height);
+        assert_eq!(390, panes[1].pixel_width);
+        assert_eq!(600, panes[1].pixel_height);
+        assert_eq!(2, panes[1].pane.pane_id());
+
+        let vert_size = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        let new_index = tab
+            .split_and_insert(
+                0,
+                SplitDirection::Vertical,
+                FakePane::new(3, vert_size.second),
+            )
+            .unwrap();
+        assert_eq!(new_index, 1);
+
+        let panes = tab.iter_panes();
+        assert_eq!(3, panes.len());
+
+        assert_eq!(0, panes[0].index);
+        assert_eq!(false, panes[0].is_active);
+        assert_eq!(0, panes[0].left);
+        assert_eq!(0, panes[0].top);
+        assert_eq!(40, panes[0].width);
+        assert_eq!(12, panes[0].height);
+        assert_eq!(400, panes[0].pixel_width);
+        assert_eq!(300, panes[0].pixel_height);
+        assert_eq!(1, panes[0].pane.pane_id());
+
+        assert_eq!(1, panes[1].index);
+        assert_eq!(true, panes[1].is_active);
+        assert_eq!(0, panes[1].left);
+        assert_eq!(13, panes[1].top);
+        assert_eq!(40, panes[1].width);
+        assert_eq!(11, panes[1].height);
+        assert_eq!(400, panes[1].pixel_width);
+        assert_eq!(275, panes[1].pixel_height);
+        assert_eq!(3, panes[1].pane.pane_id());
+
+        assert_eq!(2, panes[2].index);
+        assert_eq!(false, panes[2].is_active);
+        assert_eq!(41, panes[2].left);
+        assert_eq!(0, panes[2].top);
+        assert_eq!(39, panes[2].width);
+        assert_eq!(24, panes[2].height);
+        assert_eq!(390, panes[2].pixel_width);
+        assert_eq!(600, panes[2].pixel_height);
+        assert_eq!(2, panes[2].pane.pane_id());
+
+        tab.resize_split_by(1, 1);
+        let panes = tab.iter_panes();
+        assert_eq!(40, panes[0].width);
+        assert_eq!(13, panes[0].height);
+        assert_eq!(400, panes[0].pixel_width);
+        assert_eq!(325, panes[0].pixel_height);
+
+        assert_eq!(40, panes[1].width);
+        assert_eq!(10, panes[1].height);
+        assert_eq!(400, panes[1].pixel_width);
+        assert_eq!(250, panes[1].pixel_height);
+
+        assert_eq!(39, panes[2].width);
+        assert_eq!(24, panes[2].height);
+        assert_eq!(390, panes[2].pixel_width);
+        assert_eq!(600, panes[2].pixel_height);
+    }
+
+    #[test]
+    fn iter_panes_into_matches_iter_panes() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, horz_size.second),
+        )
+        .unwrap();
+
+        // Start with some junk in the buffer to verify that it is cleared
+        let mut panes = tab.iter_panes();
+        panes.extend(tab.iter_panes());
+        tab.iter_panes_into(&mut panes);
+
+        assert_eq!(format!("{:?}", tab.iter_panes()), format!("{:?}", panes));
+    }
+
+    /// Pruning panes schedules their removal from the mux on the main
+    /// thread; there is no mux in these tests so we discard that work.
+    fn ignore_scheduled_tasks() {
+        promise::spawn::set_schedulers(Box::new(|_| {}), Box::new(|_| {}));
+    }
+
+    /// Builds a tab with three panes, each split horizontally from
+    /// the prior pane, and with pane ids 1, 2 and 3.
+    fn three_pane_tab(size: PtySize) -> Tab {
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        for (index, id) in [(0, 2), (1, 3)].iter() {
+            let split = tab
+                .compute_split_size(*index, SplitDirection::Horizontal)
+                .unwrap();
+            tab.split_and_insert(
+                *index,
+                SplitDirection::Horizontal,
+                FakePane::new(*id, split.second),
+            )
+            .unwrap();
+        }
+        tab
+    }
+
+    /// The size used by most of these tests
+    fn test_size() -> PtySize {
+        PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        }
+    }
+
+    /// Builds a 2x2 grid of panes 1 and 2 over 3 and 4.  The grid is
+    /// split column by column, so the topological order is top left,
+    /// bottom left, top right and bottom right; pane ids 1, 3, 2, 4.
+    fn grid_2x2_tab(size: PtySize) -> Tab {
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        for &(index, direction, id) in &[
+            (0, SplitDirection::Horizontal, 2),
+            (0, SplitDirection::Vertical, 3),
+            (2, SplitDirection::Vertical, 4),
+        ] {
+            let split = tab.compute_split_size(index, direction).unwrap();
+            tab.split_and_insert(index, direction, FakePane::new(id, split.second))
+                .unwrap();
+        }
+        tab
+    }
+
+    #[test]
+    fn focus_lock_survives_prune() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        tab.iter_panes()[1].pane.kill();
+        assert!(tab.prune_dead_panes());
+        assert_eq!(tab.get_active_idx(), 0);
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        tab.set_focus_locked(true);
+        tab.iter_panes()[1].pane.kill();
+        assert!(tab.prune_dead_panes());
+        assert_eq!(tab.get_active_idx(), 1);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+
+        tab.auto_activate_idx(0);
+        assert_eq!(tab.get_active_idx(), 1);
+        tab.set_active_idx(0);
+        assert_eq!(tab.get_active_idx(), 0);
+
+        // Removing the last pane clamps the locked index
+        tab.set_active_idx(1);
+        tab.iter_panes()[1].pane.kill();
+        assert!(tab.prune_dead_panes());
+        assert_eq!(tab.get_active_idx(), 0);
+    }
+
+    #[test]
+    fn pane_rect_matches_iter_panes() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let vert_size = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            1,
+            SplitDirection::Vertical,
+            FakePane::new(4, vert_size.second),
+        )
+        .unwrap();
+
+        let panes = tab.iter_panes();
+        assert_eq!(panes.len(), 4);
+        for pos in &panes {
+            assert_eq!(
+                tab.pane_rect(pos.index),
+                Some((pos.left, pos.top, pos.width, pos.height))
+            );
+        }
+        assert_eq!(tab.pane_rect(4), None);
+    }
+
+    #[test]
+    fn scroll_active_pane() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        let pane = tab.get_active_pane().unwrap();
+        assert_eq!(pane.scroll_offset(), FAKE_SCROLLBACK as StableRowIndex);
+
+        tab.scroll_active_pane(-10);
+        assert_eq!(pane.scroll_offset(), FAKE_SCROLLBACK as StableRowIndex - 10);
+
+        // Can't scroll past the top of the scrollback
+        tab.scroll_active_pane(-1000);
+        assert_eq!(pane.scroll_offset(), 0);
+
+        // Nor below the bottom
+        pane.scroll_to(1000);
+        assert_eq!(pane.scroll_offset(), FAKE_SCROLLBACK as StableRowIndex);
+
+        // The other panes are unaffected
+        assert_eq!(
+            tab.iter_panes()[0].pane.scroll_offset(),
+            FAKE_SCROLLBACK as StableRowIndex
+        );
+    }
+
+    #[test]
+    fn reserved_bottom_rows() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let vert_size = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Vertical,
+            FakePane::new(2, vert_size.second),
+        )
+        .unwrap();
+
+        let panes = tab.iter_panes();
+        assert_eq!(panes[1].top, 13);
+        assert_eq!(panes[1].height, 11);
+
+        tab.set_reserved_bottom_rows(1);
+        assert_eq!(tab.get_size(), size);
+
+        // Small resizes are biased towards adjusting the top node,
+        // so the bottom pane moves up by a row
+        let panes = tab.iter_panes();
+        assert_eq!(panes[0].top, 0);
+        assert_eq!(panes[0].height, 11);
+        assert_eq!(panes[0].pixel_height, 275);
+        assert_eq!(panes[1].top, 12);
+        assert_eq!(panes[1].height, 11);
+        // The reserved row is not covered by any pane
+        assert_eq!(panes[1].top + panes[1].height, 23);
+
+        // Resizing the tab preserves the reservation
+        tab.resize(PtySize {
+            rows: 30,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 750,
+        });
+        let panes = tab.iter_panes();
+        assert_eq!(panes[1].top + panes[1].height, 29);
+        assert_eq!(tab.get_size().rows, 30);
+    }
+
+    #[test]
+    fn iter_panes_with_depth() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert_eq!(tab.iter_panes_with_depth()[0].1, 0);
+
+        // Each split is applied to the newly inserted pane, so the
+        // tree leans towards the right/bottom
+        let tab = three_pane_tab(size);
+        let vert_size = tab.compute_split_size(2, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            2,
+            SplitDirection::Vertical,
+            FakePane::new(4, vert_size.second),
+        )
+        .unwrap();
+
+        let depths = tab
+            .iter_panes_with_depth()
+            .into_iter()
+            .map(|(pos, depth)| (pos.pane.pane_id(), depth))
+            .collect::<Vec<_>>();
+        assert_eq!(depths, vec![(1, 1), (2, 2), (3, 3), (4, 3)]);
+    }
+
+    #[test]
+    fn split_balancing_siblings() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let options = SplitOptions {
+            balance_siblings: true,
+            ..Default::default()
+        };
+
+        for (index, id) in [(0, 2), (1, 3), (0, 4)].iter() {
+            let split = tab
+                .compute_split_size(*index, SplitDirection::Horizontal)
+                .unwrap();
+            tab.split_and_insert_with_options(
+                *index,
+                SplitDirection::Horizontal,
+                FakePane::new(*id, split.second),
+                options,
+            )
+            .unwrap();
+        }
+
+        let panes = tab.iter_panes();
+        assert_eq!(panes.len(), 4);
+        let widths = panes.iter().map(|p| p.width).collect::<Vec<_>>();
+        // 80 columns less 3 dividers leaves 77 to share between 4 panes
+        assert_eq!(widths, vec![20, 19, 19, 19]);
+        let lefts = panes.iter().map(|p| p.left).collect::<Vec<_>>();
+        assert_eq!(lefts, vec![0, 21, 41, 61]);
+        for pos in &panes {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+            assert_eq!(pos.pixel_width, pos.width * 10);
+        }
+    }
+
+    #[test]
+    fn best_neighbor_prefers_longest_edge() {
+        let size = test_size();
+
+        // Pane 1 on the left, with panes 2 and 3 stacked on the right
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, horz_size.second),
+        )
+        .unwrap();
+        let vert_size = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            1,
+            SplitDirection::Vertical,
+            FakePane::new(3, vert_size.second),
+        )
+        .unwrap();
+
+        // Pane 2 has 12 rows, pane 3 has 11 rows
+        assert_eq!(tab.best_neighbor(0, PaneDirection::Right), Some(1));
+
+        // Make pane 3 the taller of the two
+        tab.resize_split_by(1, -4);
+        assert_eq!(tab.iter_panes()[2].height, 15);
+        assert_eq!(tab.best_neighbor(0, PaneDirection::Right), Some(2));
+
+        assert_eq!(tab.best_neighbor(0, PaneDirection::Left), None);
+        assert_eq!(tab.best_neighbor(2, PaneDirection::Left), Some(0));
+        assert_eq!(tab.best_neighbor(2, PaneDirection::Up), Some(1));
+        assert_eq!(tab.best_neighbor(0, PaneDirection::Next), None);
+        assert_eq!(tab.best_neighbor(3, PaneDirection::Left), None);
+    }
+
+    #[test]
+    fn capture_and_restore_layout() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        tab.scroll_active_pane(-5);
+        tab.iter_panes()[2].pane.scroll_by(-20);
+
+        let layout = tab.capture_layout();
+        let bottom = FAKE_SCROLLBACK as StableRowIndex;
+        assert_eq!(
+            layout,
+            TabLayout {
+                active_pane: Some(2),
+                scroll_offsets: vec![(1, bottom), (2, bottom - 5), (3, bottom - 20)],
+            }
+        );
+
+        tab.set_active_idx(0);
+        for pos in tab.iter_panes() {
+            pos.pane.scroll_to(bottom);
+        }
+
+        tab.restore_layout(&layout);
+        assert_eq!(tab.get_active_idx(), 1);
+        assert_eq!(tab.capture_layout(), layout);
+    }
+
+    #[test]
+    fn resize_causes_reflow() {
+        let size = test_size();
+
+        fn with_cols(size: PtySize, cols: u16) -> PtySize {
+            PtySize {
+                cols,
+                pixel_width: cols * 10,
+                ..size
+            }
+        }
+
+        let tab = three_pane_tab(size);
+        assert!(tab.resize_causes_reflow(with_cols(size, 100)).is_empty());
+        assert!(tab.resize_causes_reflow(size).is_empty());
+        // A small shrink is taken from the left most pane
+        assert_eq!(tab.resize_causes_reflow(with_cols(size, 79)), vec![0]);
+
+        let widths = |tab: &Tab| tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        for &cols in &[79, 70, 40] {
+            let tab = three_pane_tab(size);
+            let before = widths(&tab);
+            let predicted = tab.resize_causes_reflow(with_cols(size, cols));
+            // Predicting must not change the layout
+            assert_eq!(widths(&tab), before);
+
+            tab.resize(with_cols(size, cols));
+            let after = widths(&tab);
+            let actual = (0..before.len())
+                .filter(|&idx| after[idx] < before[idx])
+                .collect::<Vec<_>>();
+            assert_eq!(predicted, actual);
+        }
+    }
+
+    #[test]
+    fn flip_split() {
+        let size = test_size();
+
+        // Pane 1 | (pane 2 | pane 3)
+        let tab = three_pane_tab(size);
+        let summary = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.left, p.width))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(&tab), vec![(1, 0, 40), (2, 41, 19), (3, 61, 19)]);
+
+        assert!(tab.flip_split(2).is_err());
+
+        // (pane 2 | pane 3) | pane 1
+        tab.flip_split(0).unwrap();
+        assert_eq!(summary(&tab), vec![(2, 0, 19), (3, 20, 19), (1, 40, 40)]);
+        let splits = tab
+            .iter_splits()
+            .iter()
+            .map(|s| (s.index, s.left))
+            .collect::<Vec<_>>();
+        assert_eq!(splits, vec![(0, 39), (1, 19)]);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+        }
+
+        // Flipping again restores the original layout
+        tab.flip_split(0).unwrap();
+        assert_eq!(summary(&tab), vec![(1, 0, 40), (2, 41, 19), (3, 61, 19)]);
+    }
+
+    #[test]
+    fn active_pane_content_rect() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        assert_eq!(tab.active_pane_content_rect(), Some((41, 0, 19, 24)));
+
+        tab.set_active_pane_border(1);
+        assert_eq!(tab.active_pane_content_rect(), Some((42, 1, 17, 22)));
+    }
+
+    #[test]
+    fn layout_tree_rects() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let vert_size = tab.compute_split_size(2, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            2,
+            SplitDirection::Vertical,
+            FakePane::new(4, vert_size.second),
+        )
+        .unwrap();
+
+        fn contains(outer: &CellRect, inner: &CellRect) -> bool {
+            inner.0 >= outer.0
+                && inner.1 >= outer.1
+                && inner.0 + inner.2 <= outer.0 + outer.2
+                && inner.1 + inner.3 <= outer.1 + outer.3
+        }
+
+        fn check(node: &LayoutNode, parent: &CellRect, tab: &Tab) -> usize {
+            match node {
+                LayoutNode::Empty => 0,
+                LayoutNode::Leaf {
+                    index,
+                    pane_id,
+                    rect,
+                } => {
+                    assert!(contains(parent, rect));
+                    let pos = &tab.iter_panes()[*index];
+                    assert_eq!(pos.pane.pane_id(), *pane_id);
+                    assert_eq!(*rect, (pos.left, pos.top, pos.width, pos.height));
+                    1
+                }
+                LayoutNode::Split {
+                    index,
+                    direction,
+                    rect,
+                    divider,
+                    first,
+                    second,
+                } => {
+                    assert!(contains(parent, rect));
+                    assert!(contains(rect, divider));
+                    let split = &tab.iter_splits()[*index];
+                    assert_eq!(split.direction, *direction);
+                    assert_eq!((split.left, split.top), (divider.0, divider.1));
+                    check(first, rect, tab) + check(second, rect, tab)
+                }
+            }
+        }
+
+        let root = tab.layout_tree_rects();
+        assert_eq!(check(&root, &(0, 0, 80, 24), &tab), 4);
+
+        match &root {
+            LayoutNode::Split {
+                rect,
+                divider,
+                second,
+                ..
+            } => {
+                assert_eq!(*rect, (0, 0, 80, 24));
+                assert_eq!(*divider, (40, 0, 1, 24));
+                match &**second {
+                    LayoutNode::Split { rect, .. } => assert_eq!(*rect, (41, 0, 39, 24)),
+                    _ => panic!("expected a nested split"),
+                }
+            }
+            _ => panic!("expected a split at the root"),
+        }
+    }
+
+    #[test]
+    fn replace_pending_pane() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        let split_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        let pending: Rc<dyn Pane> = Rc::new(PendingPane::new(0, split_size.second));
+        let pending_id = pending.pane_id();
+        tab.split_and_insert(0, SplitDirection::Horizontal, pending)
+            .unwrap();
+
+        let before = tab.iter_panes();
+        assert_eq!(before[1].pane.pane_id(), pending_id);
+        assert_eq!(before[1].pane.get_title(), "Connecting...");
+
+        // The real pane shows up with a stale size; it must be fitted
+        let real = FakePane::new(2, size);
+        let prior = tab.replace_pane(1, real).unwrap();
+        assert_eq!(prior.pane_id(), pending_id);
+
+        let after = tab.iter_panes();
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[1].pane.pane_id(), 2);
+        assert_eq!(
+            (after[1].left, after[1].top, after[1].width, after[1].height),
+            (
+                before[1].left,
+                before[1].top,
+                before[1].width,
+                before[1].height
+            )
+        );
+        let dims = after[1].pane.get_dimensions();
+        assert_eq!((dims.cols, dims.viewport_rows), (39, 24));
+
+        assert!(tab.replace_pane(2, FakePane::new(3, size)).is_err());
+        assert_eq!(tab.count_panes(), 2);
+    }
+
+    #[test]
+    fn stack_axis() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert_eq!(tab.stack_axis(), None);
+
+        let tab = three_pane_tab(size);
+        assert_eq!(tab.stack_axis(), Some(SplitDirection::Horizontal));
+
+        let column = Tab::new(&size);
+        column.assign_pane(&FakePane::new(1, size));
+        for &(index, id) in &[(0, 2), (1, 3)] {
+            let split = column
+                .compute_split_size(index, SplitDirection::Vertical)
+                .unwrap();
+            column
+                .split_and_insert(
+                    index,
+                    SplitDirection::Vertical,
+                    FakePane::new(id, split.second),
+                )
+                .unwrap();
+        }
+        assert_eq!(column.stack_axis(), Some(SplitDirection::Vertical));
+
+        let split = tab.compute_split_size(2, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(2, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+        assert_eq!(tab.stack_axis(), None);
+    }
+
+    #[test]
+    fn max_panes() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert_eq!(tab.get_max_panes(), DEFAULT_MAX_PANES);
+        tab.set_max_panes(3);
+
+        let mut id = 2;
+        let err = loop {
+            let split = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+            match tab.split_and_insert(0, SplitDirection::Vertical, FakePane::new(id, split.second))
+            {
+                Ok(_) => id += 1,
+                Err(err) => break err,
+            }
+        };
+        assert!(err.to_string().contains("maximum"), "{}", err);
+        assert_eq!(id, 4);
+
+        let panes = tab.iter_panes();
+        assert_eq!(panes.len(), 3);
+        assert_eq!(
+            panes
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.top, p.height))
+                .collect::<Vec<_>>(),
+            vec![(1, 0, 6), (3, 7, 5), (2, 13, 11)]
+        );
+        assert_eq!(tab.iter_splits().len(), 2);
+    }
+
+    #[test]
+    fn active_pane_viewport() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        assert_eq!(tab.active_pane_viewport(), Some(100..124));
+
+        tab.iter_panes()[1].pane.set_viewport_top(40);
+        assert_eq!(tab.active_pane_viewport(), Some(40..64));
+        assert_eq!(tab.iter_panes()[0].pane.viewport_range(), 100..124);
+
+        let tab = Tab::new(&size);
+        assert_eq!(tab.active_pane_viewport(), None);
+    }
+
+    #[test]
+    fn fit_pane_to_content() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+
+        fn geometry(tab: &Tab) -> Vec<(PaneId, usize, usize, usize, usize)> {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.left, p.top, p.width, p.height))
+                .collect()
+        }
+        assert_eq!(
+            geometry(&tab),
+            vec![
+                (1, 0, 0, 40, 24),
+                (2, 41, 0, 19, 12),
+                (4, 41, 13, 19, 11),
+                (3, 61, 0, 19, 24)
+            ]
+        );
+
+        // No preference means no change
+        tab.fit_pane_to_content(1);
+        assert_eq!(geometry(&tab)[1], (2, 41, 0, 19, 12));
+
+        tab.iter_panes()[1]
+            .pane
+            .downcast_ref::<FakePane>()
+            .unwrap()
+            .preferred_size
+            .replace(Some(PtySize {
+                rows: 5,
+                cols: 10,
+                pixel_width: 100,
+                pixel_height: 125,
+            }));
+        tab.fit_pane_to_content(1);
+
+        assert_eq!(
+            geometry(&tab),
+            vec![
+                (1, 0, 0, 40, 24),
+                (2, 41, 0, 10, 5),
+                (4, 41, 6, 10, 18),
+                (3, 52, 0, 28, 24)
+            ]
+        );
+        let dims = tab.iter_panes()[3].pane.get_dimensions();
+        assert_eq!((dims.cols, dims.viewport_rows), (28, 24));
+    }
+
+    #[test]
+    fn cycle_layout() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+
+        fn geometry(tab: &Tab) -> Vec<(PaneId, usize, usize, usize, usize)> {
+            tab.iter_panes()
+                .iter()
+                .map(|p| {
+                    let dims = p.pane.get_dimensions();
+                    assert_eq!((dims.cols, dims.viewport_rows), (p.width, p.height));
+                    (p.pane.pane_id(), p.left, p.top, p.width, p.height)
+                })
+                .collect()
+        }
+
+        assert_eq!(tab.cycle_layout(), LayoutPreset::EvenHorizontal);
+        assert_eq!(
+            geometry(&tab),
+            vec![(1, 0, 0, 26, 24), (2, 27, 0, 26, 24), (3, 54, 0, 26, 24)]
+        );
+
+        assert_eq!(tab.cycle_layout(), LayoutPreset::EvenVertical);
+        assert_eq!(
+            geometry(&tab),
+            vec![(1, 0, 0, 80, 8), (2, 0, 9, 80, 7), (3, 0, 17, 80, 7)]
+        );
+
+        assert_eq!(tab.cycle_layout(), LayoutPreset::MainVertical);
+        assert_eq!(
+            geometry(&tab),
+            vec![(1, 0, 0, 40, 24), (2, 41, 0, 39, 12), (3, 41, 13, 39, 11)]
+        );
+
+        assert_eq!(tab.cycle_layout(), LayoutPreset::MainHorizontal);
+        assert_eq!(
+            geometry(&tab),
+            vec![(1, 0, 0, 80, 12), (2, 0, 13, 40, 11), (3, 41, 13, 39, 11)]
+        );
+
+        assert_eq!(tab.cycle_layout(), LayoutPreset::Tiled);
+        assert_eq!(
+            geometry(&tab),
+            vec![(1, 0, 0, 40, 12), (2, 41, 0, 39, 12), (3, 0, 13, 80, 11)]
+        );
+
+        assert_eq!(tab.cycle_layout(), LayoutPreset::EvenHorizontal);
+        assert_eq!(tab.get_active_idx(), 1);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+    }
+
+    #[test]
+    fn is_pane_active() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        assert!(!tab.is_pane_active(0));
+        assert!(tab.is_pane_active(1));
+        assert!(!tab.is_pane_active(2));
+
+        assert!(!tab.is_pane_id_active(1));
+        assert!(tab.is_pane_id_active(2));
+        assert!(!tab.is_pane_id_active(3));
+        assert!(!tab.is_pane_id_active(42));
+
+        tab.set_active_idx(2);
+        assert!(tab.is_pane_active(2));
+        assert!(tab.is_pane_id_active(3));
+        assert!(!tab.is_pane_id_active(2));
+    }
+
+    #[test]
+    fn move_pane_in_stack() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        for &(index, id) in &[(0, 2), (1, 3)] {
+            let split = tab
+                .compute_split_size(index, SplitDirection::Vertical)
+                .unwrap();
+            tab.split_and_insert(
+                index,
+                SplitDirection::Vertical,
+                FakePane::new(id, split.second),
+            )
+            .unwrap();
+        }
+        tab.set_active_idx(2);
+
+        fn geometry(tab: &Tab) -> Vec<(PaneId, usize, usize)> {
+            tab.iter_panes()
+                .iter()
+                .map(|p| {
+                    assert_eq!(p.pane.get_dimensions().viewport_rows, p.height);
+                    (p.pane.pane_id(), p.top, p.height)
+                })
+                .collect()
+        }
+        assert_eq!(geometry(&tab), vec![(1, 0, 12), (2, 13, 5), (3, 19, 5)]);
+
+        assert_eq!(tab.move_pane_in_stack(2, -1).unwrap(), 1);
+        assert_eq!(geometry(&tab), vec![(1, 0, 12), (3, 13, 5), (2, 19, 5)]);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+
+        assert_eq!(tab.move_pane_in_stack(1, -5).unwrap(), 0);
+        assert_eq!(geometry(&tab), vec![(3, 0, 12), (1, 13, 5), (2, 19, 5)]);
+        assert_eq!(tab.get_active_idx(), 0);
+
+        assert!(tab.move_pane_in_stack(3, 1).is_err());
+
+        let mixed = three_pane_tab(size);
+        let split = mixed
+            .compute_split_size(0, SplitDirection::Vertical)
+            .unwrap();
+        mixed
+            .split_and_insert(0, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+        assert!(mixed.move_pane_in_stack(0, 1).is_err());
+    }
+
+    #[test]
+    fn scrollback_memory_estimate() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        assert_eq!(tab.scrollback_memory_estimate(), 6 * 1024);
+
+        // Zooming hides panes, but they still hold their scrollback
+        tab.set_active_idx(0);
+        tab.toggle_zoom();
+        assert_eq!(tab.iter_panes().len(), 1);
+        assert_eq!(tab.scrollback_memory_estimate(), 6 * 1024);
+    }
+
+    #[test]
+    fn split_style() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        assert!(tab.iter_splits().iter().all(|s| s.style.is_none()));
+
+        let style = SplitStyle {
+            color: RgbColor::new_8bpc(0xff, 0x80, 0),
+        };
+        tab.set_split_style(1, Some(style));
+        let splits = tab.iter_splits();
+        assert_eq!(splits[0].style, None);
+        assert_eq!(splits[1].style, Some(style));
+        assert_eq!((splits[1].left, splits[1].top), (60, 0));
+
+        tab.set_split_style(1, None);
+        assert_eq!(tab.iter_splits()[1].style, None);
+    }
+
+    #[test]
+    fn with_each_pane() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let mut seen = vec![];
+        tab.with_each_pane(|index, pane| {
+            // It is safe to query the tab from within the callback
+            assert_eq!(tab.count_panes(), 3);
+            seen.push((index, pane.pane_id()));
+        });
+        assert_eq!(seen, vec![(0, 1), (1, 2), (2, 3)]);
+
+        let tab = Tab::new(&size);
+        let mut count = 0;
+        tab.with_each_pane(|_, _| count += 1);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn is_single_pane() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        assert!(!tab.is_single_pane());
+
+        tab.assign_pane(&FakePane::new(1, size));
+        assert!(tab.is_single_pane());
+
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+        assert!(!tab.is_single_pane());
+
+        let tab = three_pane_tab(size);
+        assert!(!tab.is_single_pane());
+    }
+
+    #[test]
+    fn common_split() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert_eq!(tab.common_split(0, 0), None);
+
+        // 1 | (2 | (3 / 4))
+        let tab = three_pane_tab(size);
+        let split = tab.compute_split_size(2, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(2, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+
+        let splits = tab.iter_splits();
+        assert_eq!(
+            splits.iter().map(|s| s.direction).collect::<Vec<_>>(),
+            vec![
+                SplitDirection::Horizontal,
+                SplitDirection::Horizontal,
+                SplitDirection::Vertical
+            ]
+        );
+
+        assert_eq!(tab.common_split(2, 3), Some(2));
+        assert_eq!(tab.common_split(3, 2), Some(2));
+        assert_eq!(tab.common_split(1, 3), Some(1));
+        assert_eq!(tab.common_split(1, 2), Some(1));
+        assert_eq!(tab.common_split(0, 3), Some(0));
+        assert_eq!(tab.common_split(0, 1), Some(0));
+        assert_eq!(tab.common_split(0, 4), None);
+        assert_eq!(tab.common_split(7, 1), None);
+    }
+
+    #[test]
+    fn changed_cells() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let pane = tab.iter_panes()[1].pane.clone();
+        let fake = pane.downcast_ref::<FakePane>().unwrap();
+
+        let (generation, cells) = pane.changed_cells(0);
+        assert_eq!(generation, 0);
+        assert!(cells.is_empty());
+
+        // Row 102 is the third visible row
+        fake.changes.borrow_mut().push((102, 1));
+        let (generation, cells) = pane.changed_cells(generation);
+        assert_eq!(generation, 1);
+        assert_eq!(cells, vec![CellRange { y: 2, x: 0..19 }]);
+
+        // Changes in the scrollback are not visible
+        fake.changes.borrow_mut().push((50, 2));
+        fake.changes.borrow_mut().push((110, 3));
+        let (generation, cells) = pane.changed_cells(generation);
+        assert_eq!(generation, 3);
+        assert_eq!(cells, vec![CellRange { y: 10, x: 0..19 }]);
+
+        let (_, cells) = tab.changed_cells_in_tab(1, 0).unwrap();
+        assert_eq!(
+            cells,
+            vec![
+                CellRange { y: 2, x: 41..60 },
+                CellRange { y: 10, x: 41..60 }
+            ]
+        );
+        assert!(tab.changed_cells_in_tab(3, 0).is_none());
+    }
+
+    #[test]
+    fn swap_active_with_largest() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        assert_eq!(tab.largest_pane(), Some(0));
+
+        tab.set_active_idx(2);
+        tab.swap_active_with_largest().unwrap();
+
+        let panes = tab.iter_panes();
+        assert_eq!(
+            panes
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.left, p.width))
+                .collect::<Vec<_>>(),
+            vec![(3, 0, 40), (2, 41, 19), (1, 61, 19)]
+        );
+        assert_eq!(tab.get_active_idx(), 0);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+        assert_eq!(panes[0].pane.get_dimensions().cols, 40);
+        assert_eq!(panes[2].pane.get_dimensions().cols, 19);
+
+        // The active pane is now the largest, so this is a no-op
+        tab.swap_active_with_largest().unwrap();
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+        assert_eq!(tab.iter_panes()[0].pane.pane_id(), 3);
+    }
+
+    #[test]
+    fn pane_titles() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let expected = vec![
+            (0, "pane 1".to_string()),
+            (1, "pane 2".to_string()),
+            (2, "pane 3".to_string()),
+        ];
+        assert_eq!(tab.pane_titles(), expected);
+
+        tab.set_active_idx(1);
+        tab.toggle_zoom();
+        assert_eq!(tab.pane_titles(), expected);
+    }
+
+    #[test]
+    fn close_on_last_pane_death() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert!(tab.get_close_on_last_pane_death());
+        tab.iter_panes()[0].pane.kill();
+        assert!(tab.is_dead());
+        assert!(tab.prune_dead_panes());
+        assert_eq!(tab.count_panes(), 0);
+        assert!(tab.is_dead());
+        assert!(tab.should_auto_close());
+
+        let tab = three_pane_tab(size);
+        tab.set_close_on_last_pane_death(false);
+        tab.iter_panes()[1].pane.kill();
+        assert!(!tab.is_dead());
+        // Dead panes are still pruned while others are alive
+        assert!(tab.prune_dead_panes());
+        assert_eq!(tab.count_panes(), 2);
+
+        for pos in tab.iter_panes() {
+            pos.pane.kill();
+        }
+        assert!(tab.is_dead());
+        assert!(!tab.prune_dead_panes());
+        assert!(!tab.should_auto_close());
+        let panes = tab.iter_panes();
+        assert_eq!(
+            panes.iter().map(|p| p.pane.pane_id()).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(panes[0].pane.get_dimensions().cols, 40);
+
+        // Once the panes are explicitly closed, the tab can go
+        assert!(tab.kill_pane(1));
+        assert!(tab.kill_pane(3));
+        assert!(tab.should_auto_close());
+    }
+
+    #[test]
+    fn most_recently_active_pane() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        assert_eq!(tab.most_recently_active_pane(), Some(0));
+
+        let panes = tab.iter_panes();
+        panes[2]
+            .pane
+            .perform_actions(vec![termwiz::escape::Action::Print('a')]);
+        assert_eq!(tab.most_recently_active_pane(), Some(2));
+
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        panes[1]
+            .pane
+            .perform_actions(vec![termwiz::escape::Action::Print('b')]);
+        assert_eq!(tab.most_recently_active_pane(), Some(1));
+
+        assert_eq!(Tab::new(&size).most_recently_active_pane(), None);
+    }
+
+    #[test]
+    fn tmux_layout() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+
+        let layout = tab.tmux_layout();
+        let body = "80x24,0,0{40x24,0,0,1,19x24,41,0[19x12,41,0,2,19x11,41,13,4],19x24,61,0,3}";
+        assert_eq!(
+            layout,
+            format!("{:04x},{}", tmux_layout_checksum(body), body)
+        );
+        assert_eq!(
+            TmuxLayout::parse(&layout).unwrap().to_layout_string(),
+            layout
+        );
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(0, size));
+        assert_eq!(tab.tmux_layout(), "b25d,80x24,0,0,0");
+    }
+
+    #[test]
+    fn dividers_on_row_and_col() {
+        let size = test_size();
+
+        // A 2x2 grid: (1 / 3) | (2 / 4)
+        let tab = grid_2x2_tab(size);
+
+        let splits = tab.iter_splits();
+        assert_eq!(
+            splits
+                .iter()
+                .map(|s| (s.index, s.left, s.top, s.size))
+                .collect::<Vec<_>>(),
+            vec![(0, 40, 0, 24), (1, 0, 12, 40), (2, 41, 12, 39)]
+        );
+
+        assert_eq!(tab.dividers_on_row(0), vec![0]);
+        assert_eq!(tab.dividers_on_row(11), vec![0]);
+        assert_eq!(tab.dividers_on_row(12), vec![0, 1, 2]);
+        assert_eq!(tab.dividers_on_row(23), vec![0]);
+        assert!(tab.dividers_on_row(24).is_empty());
+
+        assert_eq!(tab.dividers_on_col(0), vec![1]);
+        assert_eq!(tab.dividers_on_col(39), vec![1]);
+        assert_eq!(tab.dividers_on_col(40), vec![0]);
+        assert_eq!(tab.dividers_on_col(41), vec![2]);
+        assert_eq!(tab.dividers_on_col(79), vec![2]);
+        assert!(tab.dividers_on_col(80).is_empty());
+    }
+
+    #[test]
+    fn zoom_pane() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(0);
+
+        fn geometry(tab: &Tab) -> Vec<(PaneId, usize, usize, usize, usize)> {
+            tab.iter_panes()
+                .iter()
+                .map(|p| {
+                    let dims = p.pane.get_dimensions();
+                    assert_eq!((dims.cols, dims.viewport_rows), (p.width, p.height));
+                    (p.pane.pane_id(), p.left, p.top, p.width, p.height)
+                })
+                .collect()
+        }
+        let before = geometry(&tab);
+
+        assert!(tab.zoom_pane(3).is_err());
+        assert_eq!(geometry(&tab), before);
+
+        tab.zoom_pane(2).unwrap();
+        assert_eq!(tab.get_active_idx(), 2);
+        assert_eq!(geometry(&tab), vec![(3, 0, 0, 80, 24)]);
+        assert!(tab.iter_panes()[0].is_zoomed);
+
+        // Zooming another pane moves the zoom to it
+        tab.zoom_pane(1).unwrap();
+        assert_eq!(geometry(&tab), vec![(2, 0, 0, 80, 24)]);
+
+        tab.unzoom();
+        assert_eq!(geometry(&tab), before);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+    }
+
+    #[test]
+    fn resplit_along_same_axis_is_flat() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let options = SplitOptions {
+            balance_siblings: true,
+            ..Default::default()
+        };
+        for &id in &[2, 3] {
+            let split = tab
+                .compute_split_size(0, SplitDirection::Horizontal)
+                .unwrap();
+            tab.split_and_insert_with_options(
+                0,
+                SplitDirection::Horizontal,
+                FakePane::new(id, split.second),
+                options,
+            )
+            .unwrap();
+        }
+
+        match TmuxLayout::parse(&tab.tmux_layout()).unwrap() {
+            TmuxLayout::Split {
+                direction,
+                children,
+                ..
+            } => {
+                assert_eq!(direction, SplitDirection::Horizontal);
+                let widths = children
+                    .iter()
+                    .map(|child| match child {
+                        TmuxLayout::Pane { width, .. } => *width,
+                        TmuxLayout::Split { .. } => panic!("expected a pane, not a nested split"),
+                    })
+                    .collect::<Vec<_>>();
+                // 80 columns less 2 dividers leaves 78 to share between 3 panes
+                assert_eq!(widths, vec![26, 26, 26]);
+            }
+            TmuxLayout::Pane { .. } => panic!("expected a split"),
+        }
+        assert_eq!(tab.stack_axis(), Some(SplitDirection::Horizontal));
+
+        // Splitting the first pane twice would nest ((1 | 3) | 2);
+        // the chain is instead kept as (1 | (3 | 2))
+        match tab.pane.borrow().as_ref().unwrap() {
+            Tree::Node { left, right, .. } => {
+                assert!(matches!(&**left, Tree::Leaf(_)));
+                match &**right {
+                    Tree::Node {
+                        left,
+                        right,
+                        data: Some(data),
+                    } => {
+                        assert_eq!(data.direction, SplitDirection::Horizontal);
+                        assert!(matches!(&**left, Tree::Leaf(_)));
+                        assert!(matches!(&**right, Tree::Leaf(_)));
+                    }
+                    _ => panic!("expected a split"),
+                }
+            }
+            _ => panic!("expected a split"),
+        }
+        assert_eq!(
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.left, p.width))
+                .collect::<Vec<_>>(),
+            vec![(1, 0, 26), (3, 27, 26), (2, 54, 26)]
+        );
+    }
+
+    #[test]
+    fn render_priorities() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(2);
+        // Pane 3 is active; pane 1 is larger than pane 2
+        assert_eq!(tab.render_priorities(), vec![(0, 254), (1, 253), (2, 255)]);
+
+        tab.zoom_pane(1).unwrap();
+        assert_eq!(tab.render_priorities(), vec![(0, 0), (1, 255), (2, 0)]);
+
+        assert!(Tab::new(&size).render_priorities().is_empty());
+    }
+
+    #[test]
+    fn resize_would_change() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        assert!(!tab.resize_would_change(size));
+        assert!(!tab.resize_would_change(PtySize { rows: 0, ..size }));
+        assert!(tab.resize_would_change(PtySize {
+            cols: 100,
+            pixel_width: 1000,
+            ..size
+        }));
+
+        let before = tab.iter_panes();
+        tab.resize(size);
+        let after = tab.iter_panes();
+        assert_eq!(
+            before
+                .iter()
+                .map(|p| (p.width, p.height))
+                .collect::<Vec<_>>(),
+            after
+                .iter()
+                .map(|p| (p.width, p.height))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn extract_active_pane() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        let (pane, split, is_second) = tab.extract_active_pane().unwrap();
+        assert_eq!(pane.pane_id(), 2);
+        assert_eq!(split.direction, SplitDirection::Horizontal);
+        assert_eq!(split.first.cols, 19);
+        assert_eq!(split.second.cols, 19);
+        assert_eq!(split.first.rows, 24);
+        assert!(!is_second);
+        // The pane remains in the tab
+        assert_eq!(tab.count_panes(), 3);
+        assert!(tab.contains_pane(2));
+
+        // The last pane is the second half of the same split
+        tab.set_active_idx(2);
+        let (pane, split, is_second) = tab.extract_active_pane().unwrap();
+        assert_eq!(pane.pane_id(), 3);
+        assert_eq!(split.first.cols, 19);
+        assert!(is_second);
+
+        // The first pane is the first half of the outer split
+        tab.set_active_idx(0);
+        let (pane, split, is_second) = tab.extract_active_pane().unwrap();
+        assert_eq!(pane.pane_id(), 1);
+        assert_eq!(split.first.cols, 40);
+        assert_eq!(split.second.cols, 39);
+        assert!(!is_second);
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert!(tab.extract_active_pane().is_none());
+        assert_eq!(tab.count_panes(), 1);
+    }
+
+    #[test]
+    fn cycle_skips_unfocusable_panes() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(0);
+        tab.set_pane_focusable(2, false);
+        assert!(!tab.is_pane_focusable(2));
+
+        tab.activate_pane_direction(PaneDirection::Next);
+        assert_eq!(tab.get_active_idx(), 2);
+        tab.activate_pane_direction(PaneDirection::Next);
+        assert_eq!(tab.get_active_idx(), 0);
+        tab.activate_pane_direction(PaneDirection::Prev);
+        assert_eq!(tab.get_active_idx(), 2);
+        tab.activate_pane_direction(PaneDirection::Prev);
+        assert_eq!(tab.get_active_idx(), 0);
+
+        // With no other focusable pane, the active pane is retained
+        tab.set_pane_focusable(3, false);
+        tab.activate_pane_direction(PaneDirection::Next);
+        assert_eq!(tab.get_active_idx(), 0);
+
+        tab.set_pane_focusable(2, true);
+        tab.activate_pane_direction(PaneDirection::Next);
+        assert_eq!(tab.get_active_idx(), 1);
+    }
+
+    #[test]
+    fn total_pasted_bytes() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        assert_eq!(tab.total_pasted_bytes(), 0);
+
+        let panes = tab.iter_panes();
+        panes[0].pane.send_paste("hello").unwrap();
+        panes[0].pane.send_paste(", world").unwrap();
+        panes[2].pane.trickle_paste("abc".to_string()).unwrap();
+        assert_eq!(panes[0].pane.total_pasted_bytes(), 12);
+        assert_eq!(panes[1].pane.total_pasted_bytes(), 0);
+        assert_eq!(panes[2].pane.total_pasted_bytes(), 3);
+        assert_eq!(tab.total_pasted_bytes(), 15);
+    }
+
+    #[test]
+    fn repair_active() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        tab.repair_active();
+        assert_eq!(tab.get_active_idx(), 1);
+
+        tab.set_active_idx(7);
+        assert!(tab.get_active_pane().is_none());
+        tab.repair_active();
+        assert_eq!(tab.get_active_idx(), 2);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+
+        let tab = Tab::new(&size);
+        tab.set_active_idx(3);
+        tab.repair_active();
+        assert_eq!(tab.get_active_idx(), 0);
+
+        // A 2x2 grid of panes 1 and 2 over 3 and 4, in the topological
+        // order 1, 3, 2, 4
+        let tab = grid_2x2_tab(size);
+
+        // Remove the bottom left pane while it is active, leaving the
+        // active index pointing at the top right pane
+        tab.set_active_idx(1);
+        {
+            let mut root = tab.pane.borrow_mut();
+            let cursor = root.take().unwrap().cursor();
+            let cursor = cursor.go_to_nth_leaf(1).ok().unwrap();
+            let (cursor, _, _) = cursor.unsplit_leaf().ok().unwrap();
+            root.replace(cursor.tree());
+        }
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+
+        // The pane above it, which took over its space, is activated
+        tab.repair_active();
+        assert_eq!(tab.get_active_idx(), 0);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 1);
+
+        // A pane that has merely moved remains active
+        tab.set_active_idx(2);
+        let mut panes: Vec<Rc<dyn Pane>> = tab.iter_panes().into_iter().map(|p| p.pane).collect();
+        panes.swap(1, 2);
+        tab.reassign_panes(panes);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+        tab.repair_active();
+        assert_eq!(tab.get_active_idx(), 1);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 4);
+    }
+
+    #[test]
+    fn thumbnail() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+
+        let thumb = tab.thumbnail(20, 6);
+        assert_eq!(thumb.cols, 20);
+        assert_eq!(thumb.rows, 6);
+        assert_eq!(thumb.cells.len(), 6);
+        assert!(thumb.cells.iter().all(|row| row.len() == 20));
+        for line in thumb.lines() {
+            assert_eq!(line, "1111111111│222222222");
+        }
+
+        // The divider is kept even when no thumbnail column samples it
+        let thumb = tab.thumbnail(7, 3);
+        for line in thumb.lines() {
+            assert_eq!(line, "1111│22");
+        }
+
+        // The thumbnail is never larger than the tab
+        let thumb = tab.thumbnail(200, 100);
+        assert_eq!((thumb.cols, thumb.rows), (80, 24));
+
+        // The pane content starts below the header rows
+        tab.set_pane_header_rows(1);
+        let thumb = tab.thumbnail(80, 24);
+        let lines = thumb.lines();
+        assert_eq!(lines[0].trim_matches(' '), "│");
+        let full = format!("{}│{}", "1".repeat(40), "2".repeat(39));
+        for line in &lines[1..] {
+            assert_eq!(line, &full);
+        }
+    }
+
+    #[test]
+    fn merge_panes() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+
+        assert!(tab.merge_panes(0, PaneDirection::Left).is_err());
+        assert!(tab.merge_panes(0, PaneDirection::Down).is_err());
+        assert_eq!(tab.merge_panes(0, PaneDirection::Right).unwrap(), 2);
+
+        let panes = tab.iter_panes();
+        assert_eq!(panes.len(), 1);
+        assert_eq!(panes[0].pane.pane_id(), 1);
+        assert_eq!(panes[0].width, 80);
+        assert_eq!(panes[0].pane.get_dimensions().cols, 80);
+
+        // The space to the right of pane 1 is shared by panes 2 and 3
+        let tab = three_pane_tab(size);
+        assert!(tab.merge_panes(0, PaneDirection::Right).is_err());
+        assert_eq!(tab.merge_panes(2, PaneDirection::Left).unwrap(), 2);
+        let widths = tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        assert_eq!(widths, vec![40, 39]);
+    }
+
+    #[test]
+    fn split_ratios() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert!(tab.split_ratios().is_empty());
+
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+        let ratios = tab.split_ratios();
+        assert_eq!(ratios.len(), 1);
+        assert_eq!(ratios[0].0, 0);
+        assert!((ratios[0].1 - 40. / 79.).abs() < 0.001);
+        assert!((ratios[0].1 - 0.506).abs() < 0.001);
+
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(3, split.second))
+            .unwrap();
+        let ratios = tab.split_ratios();
+        assert_eq!(ratios.len(), 2);
+        assert_eq!(ratios[1].0, 1);
+        // 12 of the 23 rows that remain after the divider
+        assert!((ratios[1].1 - 12. / 23.).abs() < 0.001);
+    }
+
+    #[test]
+    fn split_and_insert_before() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let options = SplitOptions {
+            insert_before: true,
+            ..Default::default()
+        };
+
+        let horz_size = tab
+            .compute_split_size_with_options(0, SplitDirection::Horizontal, options)
+            .unwrap();
+        assert_eq!(horz_size.first.cols, 39);
+        assert_eq!(horz_size.second.cols, 40);
+
+        let new_index = tab
+            .split_and_insert_with_options(
+                0,
+                SplitDirection::Horizontal,
+                FakePane::new(2, horz_size.first),
+                options,
+            )
+            .unwrap();
+        assert_eq!(new_index, 0);
+
+        let panes = tab.iter_panes();
+        assert_eq!(2, panes.len());
+
+        assert_eq!(0, panes[0].index);
+        assert!(panes[0].is_active);
+        assert_eq!(0, panes[0].left);
+        assert_eq!(0, panes[0].top);
+        assert_eq!(39, panes[0].width);
+        assert_eq!(24, panes[0].height);
+        assert_eq!(390, panes[0].pixel_width);
+        assert_eq!(2, panes[0].pane.pane_id());
+
+        assert_eq!(1, panes[1].index);
+        assert!(!panes[1].is_active);
+        assert_eq!(40, panes[1].left);
+        assert_eq!(0, panes[1].top);
+        assert_eq!(40, panes[1].width);
+        assert_eq!(24, panes[1].height);
+        assert_eq!(400, panes[1].pixel_width);
+        assert_eq!(1, panes[1].pane.pane_id());
+
+        let vert_size = tab
+            .compute_split_size_with_options(1, SplitDirection::Vertical, options)
+            .unwrap();
+        let new_index = tab
+            .split_and_insert_with_options(
+                1,
+                SplitDirection::Vertical,
+                FakePane::new(3, vert_size.first),
+                options,
+            )
+            .unwrap();
+        assert_eq!(new_index, 1);
+        assert_eq!(tab.get_active_idx(), 1);
+
+        let panes = tab.iter_panes();
+        assert_eq!(3, panes.len());
+        assert_eq!(3, panes[1].pane.pane_id());
+        assert_eq!(40, panes[1].left);
+        assert_eq!(0, panes[1].top);
+        assert_eq!(11, panes[1].height);
+        assert_eq!(1, panes[2].pane.pane_id());
+        assert_eq!(40, panes[2].left);
+        assert_eq!(12, panes[2].top);
+        assert_eq!(12, panes[2].height);
+        for pos in &panes {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+            assert_eq!(pos.pane.get_dimensions().viewport_rows, pos.height);
+        }
+    }
+
+    #[test]
+    fn activate_pane_direction() {
+        let size = test_size();
+
+        // A 2x2 grid: (1 / 3) | (2 / 4)
+        let tab = grid_2x2_tab(size);
+
+        let activate = |from: PaneId, direction: PaneDirection| -> Option<PaneId> {
+            let index = tab
+                .iter_panes()
+                .iter()
+                .find(|p| p.pane.pane_id() == from)
+                .unwrap()
+                .index;
+            tab.set_active_idx(index);
+            if tab.activate_pane_direction(direction) {
+                Some(tab.get_active_pane().unwrap().pane_id())
+            } else {
+                assert_eq!(tab.get_active_idx(), index);
+                None
+            }
+        };
+
+        assert_eq!(activate(1, PaneDirection::Right), Some(2));
+        assert_eq!(activate(1, PaneDirection::Down), Some(3));
+        assert_eq!(activate(1, PaneDirection::Left), None);
+        assert_eq!(activate(1, PaneDirection::Up), None);
+
+        assert_eq!(activate(2, PaneDirection::Left), Some(1));
+        assert_eq!(activate(2, PaneDirection::Down), Some(4));
+        assert_eq!(activate(2, PaneDirection::Right), None);
+
+        assert_eq!(activate(3, PaneDirection::Up), Some(1));
+        assert_eq!(activate(3, PaneDirection::Right), Some(4));
+        assert_eq!(activate(3, PaneDirection::Down), None);
+
+        assert_eq!(activate(4, PaneDirection::Up), Some(2));
+        assert_eq!(activate(4, PaneDirection::Left), Some(3));
+        assert_eq!(activate(4, PaneDirection::Right), None);
+    }
+
+    #[test]
+    fn best_neighbor_prefers_closest_center_on_ties() {
+        let size = test_size();
+
+        // Pane 2 spans the bottom; panes 1, 4 and 3 sit above it
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        for &(index, direction, id) in &[
+            (0, SplitDirection::Vertical, 2),
+            (0, SplitDirection::Horizontal, 3),
+            (0, SplitDirection::Horizontal, 4),
+        ] {
+            let split = tab.compute_split_size(index, direction).unwrap();
+            tab.split_and_insert(index, direction, FakePane::new(id, split.second))
+                .unwrap();
+        }
+        tab.resize_split_by(1, 9);
+        tab.resize_split_by(2, -2);
+
+        let panes = tab.iter_panes();
+        assert_eq!(
+            panes
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.left, p.width))
+                .collect::<Vec<_>>(),
+            vec![(1, 0, 18), (4, 19, 30), (3, 50, 30), (2, 0, 80)]
+        );
+
+        // Panes 4 and 3 share the longest edge with pane 2, but
+        // pane 4 is closer to its center
+        assert_eq!(tab.best_neighbor(3, PaneDirection::Up), Some(1));
+    }
+
+    #[test]
+    fn split_golden() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        let new_index = tab
+            .split_golden(
+                0,
+                SplitDirection::Horizontal,
+                FakePane::new(2, split.second),
+            )
+            .unwrap();
+        assert_eq!(new_index, 1);
+
+        let widths = tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        // 79 cells remain after the divider; 61.8% of those is 48.8
+        assert_eq!(widths, vec![49, 30]);
+        let ratio = tab.split_ratios()[0].1;
+        assert!((ratio - GOLDEN_RATIO).abs() < 0.01);
+
+        assert!(tab
+            .split_and_insert_with_options(
+                0,
+                SplitDirection::Vertical,
+                FakePane::new(3, split.second),
+                SplitOptions {
+                    ratio: Some(1.5),
+                    ..Default::default()
+                },
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn adjust_split_size() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+
+        tab.adjust_split_size(0, 3).unwrap();
+        let panes = tab.iter_panes();
+        assert_eq!(panes[0].width, 43);
+        assert_eq!(panes[1].width, 36);
+        assert_eq!(panes[1].left, 44);
+        for pos in &panes {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+        }
+
+        // Both sides keep at least one cell
+        tab.adjust_split_size(0, 100).unwrap();
+        let widths = tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        assert_eq!(widths, vec![78, 1]);
+
+        assert!(tab.adjust_split_size(1, 1).is_err());
+    }
+
+    #[test]
+    fn duplicate_content_panes() {
+        ignore_scheduled_tasks();
+        let size = PtySize {
+            rows: 23,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 575,
+        };
+
+        // The fake panes fill their lines with the last digit of their
+        // id, so equally sized panes 1 and 11 show the same content
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        for &(index, direction, id) in &[
+            (0, SplitDirection::Vertical, 11),
+            (1, SplitDirection::Horizontal, 2),
+        ] {
+            let split = tab.compute_split_size(index, direction).unwrap();
+            tab.split_and_insert(index, direction, FakePane::new(id, split.second))
+                .unwrap();
+        }
+        assert!(tab.duplicate_content_panes().is_empty());
+
+        // Remove the split so that 1 and 11 are the same size
+        tab.merge_panes(1, PaneDirection::Right).unwrap();
+        let panes = tab.iter_panes();
+        assert_eq!(panes[0].width, panes[1].width);
+        assert_eq!(panes[0].height, panes[1].height);
+        assert_eq!(panes[0].pane.content_hash(), panes[1].pane.content_hash());
+        assert_eq!(tab.duplicate_content_panes(), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn zoom_active_pane() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        let geometry = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.pane.pane_id(), p.left, p.top, p.width, p.height))
+                .collect::<Vec<_>>()
+        };
+        let before = geometry(&tab);
+        assert!(!tab.is_zoomed());
+
+        tab.set_zoomed(true);
+        assert!(tab.is_zoomed());
+        let panes = tab.iter_panes();
+        assert_eq!(panes.len(), 1);
+        assert!(panes[0].is_zoomed);
+        assert!(panes[0].is_active);
+        assert_eq!(panes[0].pane.pane_id(), 2);
+        assert_eq!((panes[0].left, panes[0].top), (0, 0));
+        assert_eq!((panes[0].width, panes[0].height), (80, 24));
+        assert_eq!(panes[0].pane.get_dimensions().cols, 80);
+        assert!(tab.iter_splits().is_empty());
+
+        // Resizing while zoomed keeps the zoomed pane filling the tab
+        let larger = PtySize {
+            rows: 30,
+            cols: 100,
+            pixel_width: 1000,
+            pixel_height: 750,
+        };
+        tab.resize(larger);
+        assert!(tab.is_zoomed());
+        let panes = tab.iter_panes();
+        assert_eq!((panes[0].width, panes[0].height), (100, 30));
+        assert_eq!(panes[0].pane.get_dimensions().viewport_rows, 30);
+
+        tab.resize(size);
+        tab.set_zoomed(false);
+        assert!(!tab.is_zoomed());
+        assert_eq!(geometry(&tab), before);
+        assert_eq!(tab.iter_splits().len(), 2);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+        }
+    }
 
-                        // Now we need to increase the size of the current node
-                        // and propagate the revised size to its children.
-                        let size = PtySize {
-                            rows: parent.height(),
-                            cols: parent.width(),
-                            pixel_width: cell_dims.pixel_width * parent.width(),
-                            pixel_height: cell_dims.pixel_height * parent.height(),
-                        };
+    #[test]
+    fn key_target() {
+        let size = test_size();
 
-                        if let Some(unsplit) = cursor.leaf_mut() {
-                            unsplit.resize(size).ok();
-                        } else {
-                            self.apply_pane_size(size, &mut cursor);
-                        }
-                    } else if !dead_panes.is_empty() {
-                        // Apply our revised size to the tty
-                        pane.resize(pane_size).ok();
-                    }
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        assert_eq!(tab.key_target(), Some(1));
 
-                    pane_index += 1;
-                } else if !dead_panes.is_empty() {
-                    self.apply_pane_size(pane_size, &mut cursor);
-                }
-                match cursor.preorder_next() {
-                    Ok(c) => cursor = c,
-                    Err(c) => {
-                        root.replace(c.tree());
-                        break;
-                    }
-                }
-            }
-            *self.active.borrow_mut() = active_idx;
-        }
+        tab.zoom_pane(2).unwrap();
+        assert_eq!(tab.key_target(), Some(2));
 
-        if !dead_panes.is_empty() {
-            promise::spawn::spawn_into_main_thread(async move {
-                let mux = Mux::get().unwrap();
-                for pane_id in dead_panes.into_iter() {
-                    mux.remove_pane(pane_id);
-                }
-            })
-            .detach();
-            true
-        } else {
-            false
-        }
+        assert_eq!(Tab::new(&size).key_target(), None);
     }
 
-    pub fn can_close_without_prompting(&self, reason: CloseReason) -> bool {
-        let panes = self.iter_panes();
-        for pos in &panes {
-            if !pos.pane.can_close_without_prompting(reason) {
-                return false;
+    #[test]
+    fn serialize_and_rebuild_layout() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+        let layout = tab.serialize_layout();
+
+        let geometry = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| {
+                    (
+                        p.pane.pane_id(),
+                        p.left,
+                        p.top,
+                        p.width,
+                        p.height,
+                        p.pane.get_dimensions().cols,
+                        p.pane.get_dimensions().viewport_rows,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let rebuilt = Tab::new(&size);
+        let tiny = PtySize {
+            rows: 1,
+            cols: 1,
+            pixel_width: 10,
+            pixel_height: 25,
+        };
+        rebuilt.rebuild_from_layout(&layout, |pane_id| FakePane::new(pane_id, tiny));
+        assert_eq!(geometry(&rebuilt), geometry(&tab));
+        assert_eq!(rebuilt.serialize_layout(), layout);
+
+        let mut ids = vec![];
+        let mut stack = vec![&layout];
+        while let Some(node) = stack.pop() {
+            match node {
+                SerializedTab::Pane(id) => ids.push(*id),
+                SerializedTab::Split { first, second, .. } => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                SerializedTab::Empty => {}
             }
         }
-        true
+        assert_eq!(ids, vec![1, 2, 4, 3]);
     }
 
-    pub fn is_dead(&self) -> bool {
-        // Make sure we account for all panes, so that we don't
-        // kill the whole tab if the zoomed pane is dead!
-        let panes = self.iter_panes_ignoring_zoom();
-        let mut dead_count = 0;
+    #[test]
+    fn set_cell_metrics() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+
+        // A larger font: 20x30 pixel cells in an 800x600 window
+        tab.set_cell_metrics(20, 30);
+        let size = tab.get_size();
+        assert_eq!((size.cols, size.rows), (40, 20));
+        assert_eq!((size.pixel_width, size.pixel_height), (800, 600));
+
+        let panes = tab.iter_panes();
+        assert_eq!(panes[0].width + 1 + panes[1].width, 40);
         for pos in &panes {
-            if pos.pane.is_dead() {
-                dead_count += 1;
-            }
+            assert_eq!(pos.height, 20);
+            assert_eq!(pos.pixel_width, pos.width * 20);
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+            assert_eq!(pos.pane.get_dimensions().viewport_rows, 20);
         }
-        dead_count == panes.len()
-    }
 
-    pub fn get_active_pane(&self) -> Option<Rc<dyn Pane>> {
-        if let Some(zoomed) = self.zoomed.borrow().as_ref() {
-            return Some(Rc::clone(zoomed));
-        }
+        tab.set_cell_metrics(0, 30);
+        assert_eq!(tab.get_size(), size);
 
-        self.iter_panes()
-            .iter()
-            .nth(*self.active.borrow())
-            .map(|p| Rc::clone(&p.pane))
-    }
+        // The window keeps its pixel size when the cells don't fit
+        // into it exactly
+        tab.set_cell_metrics(30, 40);
+        let size = tab.get_size();
+        assert_eq!((size.cols, size.rows), (26, 15));
+        assert_eq!((size.pixel_width, size.pixel_height), (800, 600));
 
-    #[allow(unused)]
-    pub fn get_active_idx(&self) -> usize {
-        *self.active.borrow()
+        // So returning to the prior font restores the prior grid
+        tab.set_cell_metrics(20, 30);
+        let size = tab.get_size();
+        assert_eq!((size.cols, size.rows), (40, 20));
     }
 
-    pub fn set_active_pane(&self, pane: &Rc<dyn Pane>) {
-        if let Some(item) = self
-            .iter_panes()
-            .iter()
-            .find(|p| p.pane.pane_id() == pane.pane_id())
-        {
-            let prior = self.get_active_pane();
-            *self.active.borrow_mut() = item.index;
-            self.advise_focus_change(prior);
-        }
-    }
+    #[test]
+    fn panes_by_distance() {
+        let size = test_size();
 
-    fn advise_focus_change(&self, prior: Option<Rc<dyn Pane>>) {
-        let current = self.get_active_pane();
-        match (prior, current) {
-            (Some(prior), Some(current)) if prior.pane_id() != current.pane_id() => {
-                prior.focus_changed(false);
-                current.focus_changed(true);
-            }
-            (None, Some(current)) => {
-                current.focus_changed(true);
-            }
-            (Some(prior), None) => {
-                prior.focus_changed(false);
-            }
-            (Some(_), Some(_)) | (None, None) => {
-                // no change
-            }
-        }
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(3, split.second))
+            .unwrap();
+
+        // Pane 1 is the top right pane at 41,0 with 39x12 cells
+        let ranked = tab.panes_by_distance(50, 5);
+        assert_eq!(ranked[0], (1, 0.));
+        assert_eq!(ranked[1], (2, 8.));
+        assert_eq!(ranked[2], (0, 11.));
+
+        // The divider column is one cell away from the panes either side
+        let ranked = tab.panes_by_distance(40, 20);
+        assert_eq!(ranked[0], (0, 1.));
+        assert_eq!(ranked[1], (2, 1.));
+        assert_eq!(ranked.len(), 3);
     }
 
-    pub fn set_active_idx(&self, pane_index: usize) {
-        let prior = self.get_active_pane();
-        *self.active.borrow_mut() = pane_index;
-        self.advise_focus_change(prior);
+    #[test]
+    fn can_close_without_prompting() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        let vim = FakePane::new(2, split.second);
+        tab.split_and_insert(0, SplitDirection::Horizontal, Rc::clone(&vim))
+            .unwrap();
+        assert!(tab.can_close_without_prompting(CloseReason::Tab));
+
+        *vim.downcast_ref::<FakePane>()
+            .unwrap()
+            .foreground_process
+            .borrow_mut() = Some("/usr/bin/vim".to_string());
+        assert!(!tab.can_close_without_prompting(CloseReason::Tab));
+
+        // Still detected when hidden behind a zoomed pane
+        tab.set_active_idx(0);
+        tab.toggle_zoom();
+        assert!(!tab.can_close_without_prompting(CloseReason::Tab));
     }
 
-    /// Assigns the root pane.
-    /// This is suitable when creating a new tab and then assigning
-    /// the initial pane
-    pub fn assign_pane(&self, pane: &Rc<dyn Pane>) {
-        match Tree::new().cursor().assign_top(Rc::clone(pane)) {
-            Ok(c) => *self.pane.borrow_mut() = Some(c.tree()),
-            Err(_) => panic!("tried to assign root pane to non-empty tree"),
+    #[test]
+    fn balance_axis() {
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(3, split.second))
+            .unwrap();
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(4, split.second),
+        )
+        .unwrap();
+
+        // Make both axes uneven
+        for split in tab.iter_splits() {
+            tab.adjust_split_size(split.index, 3).unwrap();
+        }
+        let widths = |tab: &Tab| tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        let heights = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| p.height)
+                .collect::<Vec<_>>()
+        };
+        assert_ne!(widths(&tab), vec![26, 26, 26, 26]);
+        let uneven_heights = heights(&tab);
+        assert_eq!(uneven_heights, vec![24, 24, 15, 8]);
+
+        tab.balance_axis(SplitDirection::Horizontal);
+        assert_eq!(widths(&tab), vec![26, 26, 26, 26]);
+        assert_eq!(heights(&tab), uneven_heights);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+            assert_eq!(pos.pane.get_dimensions().viewport_rows, pos.height);
         }
+
+        tab.balance_axis(SplitDirection::Vertical);
+        assert_eq!(widths(&tab), vec![26, 26, 26, 26]);
+        assert_eq!(heights(&tab), vec![24, 24, 12, 11]);
     }
 
-    fn cell_dimensions(&self) -> PtySize {
-        cell_dimensions(&*self.size.borrow())
+    #[test]
+    fn get_pane_at() {
+        let size = test_size();
+
+        let tab = grid_2x2_tab(size);
+        // 0 is top left 40x12, 1 bottom left 40x11 at row 13,
+        // 2 top right 39x12 at column 41 and 3 bottom right 39x11
+        let index_at = |x, y| tab.get_pane_at(x, y).map(|pos| pos.index);
+
+        // Corners
+        assert_eq!(index_at(0, 0), Some(0));
+        assert_eq!(index_at(79, 0), Some(2));
+        assert_eq!(index_at(0, 23), Some(1));
+        assert_eq!(index_at(79, 23), Some(3));
+        assert_eq!(index_at(39, 11), Some(0));
+        assert_eq!(index_at(41, 13), Some(3));
+
+        // Centers
+        assert_eq!(index_at(20, 6), Some(0));
+        assert_eq!(index_at(60, 6), Some(2));
+        assert_eq!(index_at(20, 18), Some(1));
+        assert_eq!(index_at(60, 18), Some(3));
+
+        // Dividers
+        assert_eq!(index_at(40, 6), None);
+        assert_eq!(index_at(40, 18), None);
+        assert_eq!(index_at(20, 12), None);
+        assert_eq!(index_at(60, 12), None);
+        assert_eq!(index_at(40, 12), None);
+
+        // Outside of the tab
+        assert_eq!(index_at(80, 0), None);
+        assert_eq!(index_at(0, 24), None);
     }
 
-    /// Computes the size of the pane that would result if the specified
-    /// pane was split in a particular direction.
-    /// The intent is to call this prior to spawning the new pane so that
-    /// you can create it with the correct size.
-    /// May return None if the specified pane_index is invalid.
-    pub fn compute_split_size(
-        &self,
-        pane_index: usize,
-        direction: SplitDirection,
-    ) -> Option<SplitDirectionAndSize> {
-        let cell_dims = self.cell_dimensions();
+    #[test]
+    fn peeked_pane() {
+        let size = test_size();
 
-        // Ensure that we're not zoomed, otherwise we'll end up in
-        // a bogus split state (https://github.com/wez/wezterm/issues/723)
-        self.set_zoomed(false);
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(3, split.second))
+            .unwrap();
+        let before = tab.iter_panes();
 
-        self.iter_panes().iter().nth(pane_index).map(|pos| {
-            fn split_dimension(dim: usize) -> (usize, usize) {
-                let halved = dim / 2;
-                if halved * 2 == dim {
-                    // Was an even size; we need to allow 1 cell to render
-                    // the split UI, so make the newly created leaf slightly
-                    // smaller
-                    (halved, halved.saturating_sub(1))
-                } else {
-                    (halved, halved)
-                }
-            }
+        tab.set_peeked_pane(Some(1));
+        let panes = tab.iter_panes_for_render();
+        let peeked = &panes[1];
+        assert!(peeked.is_peeked);
+        // 39x12 at 41,0 grows to 59x18, pushed back within the tab
+        assert_eq!(
+            (peeked.left, peeked.top, peeked.width, peeked.height),
+            (21, 0, 59, 18)
+        );
+        assert_eq!(peeked.pixel_width, 590);
+        assert_eq!(peeked.pixel_height, 450);
+
+        for idx in &[0, 2] {
+            let (a, b) = (&before[*idx], &panes[*idx]);
+            assert!(!b.is_peeked);
+            assert_eq!(
+                (a.left, a.top, a.width, a.height),
+                (b.left, b.top, b.width, b.height)
+            );
+        }
+        // The panes themselves are not resized
+        assert_eq!(peeked.pane.get_dimensions().cols, 39);
+        assert_eq!(peeked.pane.get_dimensions().viewport_rows, 12);
+
+        // The layout doesn't see the peek
+        for (a, b) in before.iter().zip(tab.iter_panes().iter()) {
+            assert!(!b.is_peeked);
+            assert_eq!(
+                (a.left, a.top, a.width, a.height),
+                (b.left, b.top, b.width, b.height)
+            );
+        }
 
-            let ((width1, width2), (height1, height2)) = match direction {
-                SplitDirection::Horizontal => {
-                    (split_dimension(pos.width), (pos.height, pos.height))
-                }
-                SplitDirection::Vertical => ((pos.width, pos.width), split_dimension(pos.height)),
-            };
+        tab.set_peek_factor(10.);
+        let panes = tab.iter_panes_for_render();
+        assert_eq!((panes[1].width, panes[1].height), (80, 24));
 
-            SplitDirectionAndSize {
-                direction,
-                first: PtySize {
-                    rows: height1 as _,
-                    cols: width1 as _,
-                    pixel_height: cell_dims.pixel_height * height1 as u16,
-                    pixel_width: cell_dims.pixel_width * width1 as u16,
-                },
-                second: PtySize {
-                    rows: height2 as _,
-                    cols: width2 as _,
-                    pixel_height: cell_dims.pixel_height * height2 as u16,
-                    pixel_width: cell_dims.pixel_width * width2 as u16,
-                },
-            }
-        })
+        tab.set_peeked_pane(None);
+        let panes = tab.iter_panes_for_render();
+        assert!(!panes[1].is_peeked);
+        assert_eq!(panes[1].width, 39);
+
+        // Splitting the peeked pane uses its real size, and clears the
+        // peek since the indices have changed
+        tab.set_peeked_pane(Some(1));
+        let split = tab
+            .compute_split_size(1, SplitDirection::Horizontal)
+            .unwrap();
+        assert_eq!((split.first.cols, split.second.cols), (19, 19));
+        tab.split_and_insert(
+            1,
+            SplitDirection::Horizontal,
+            FakePane::new(4, split.second),
+        )
+        .unwrap();
+        assert_eq!(tab.get_peeked_pane(), None);
+        let panes = tab.iter_panes_for_render();
+        assert_eq!(
+            (panes[2].left, panes[2].width, panes[2].is_peeked),
+            (61, 19, false)
+        );
+
+        ignore_scheduled_tasks();
+        tab.set_peeked_pane(Some(2));
+        tab.kill_pane(4);
+        assert_eq!(tab.get_peeked_pane(), None);
     }
 
-    /// Split the pane that has pane_index in the given direction and assign
-    /// the right/bottom pane of the newly created split to the provided Pane
-    /// instance.  Returns the resultant index of the newly inserted pane.
-    /// Both the split and the inserted pane will be resized.
-    pub fn split_and_insert(
-        &self,
-        pane_index: usize,
-        direction: SplitDirection,
-        pane: Rc<dyn Pane>,
-    ) -> anyhow::Result<usize> {
-        if self.zoomed.borrow().is_some() {
-            anyhow::bail!("cannot split while zoomed");
-        }
+    #[test]
+    fn resize_preserves_scroll_position() {
+        let size = test_size();
 
-        {
-            let split_info = self
-                .compute_split_size(pane_index, direction)
-                .ok_or_else(|| {
-                    anyhow::anyhow!("invalid pane_index {}; cannot split!", pane_index)
-                })?;
-            let tab_size = *self.size.borrow();
-            if split_info.first.rows == 0
-                || split_info.first.cols == 0
-                || split_info.second.rows == 0
-                || split_info.second.cols == 0
-                || split_info.top_of_second() as u16 + split_info.second.rows > tab_size.rows
-                || split_info.left_of_second() as u16 + split_info.second.cols > tab_size.cols
-            {
-                log::error!(
-                    "No splace for split!!! {:#?} height={} width={} top_of_second={} left_of_second={} tab_size={:?}",
-                    split_info,
-                    split_info.height(),
-                    split_info.width(),
-                    split_info.top_of_second(),
-                    split_info.left_of_second(),
-                    tab_size
-                );
-                anyhow::bail!("No space for split!");
-            }
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
 
-            let mut root = self.pane.borrow_mut();
-            let mut cursor = root.take().unwrap().cursor();
+        let panes = tab.iter_panes();
+        panes[0].pane.scroll_by(-30);
+        let bottom = panes[1].pane.scroll_offset();
+        assert_eq!(panes[0].pane.scroll_offset(), bottom - 30);
+
+        tab.resize(PtySize {
+            rows: 30,
+            cols: 100,
+            pixel_width: 1000,
+            pixel_height: 750,
+        });
+        let panes = tab.iter_panes();
+        assert_eq!(panes[0].pane.get_dimensions().viewport_rows, 30);
+        assert_eq!(panes[0].pane.scroll_offset(), bottom - 30);
+        assert_eq!(panes[1].pane.scroll_offset(), bottom);
 
-            match cursor.go_to_nth_leaf(pane_index) {
-                Ok(c) => cursor = c,
-                Err(c) => {
-                    root.replace(c.tree());
-                    anyhow::bail!("invalid pane_index {}; cannot split!", pane_index);
-                }
-            };
+        // Unchanged by a resize that leaves the pane sizes alone
+        tab.resize(tab.get_size());
+        assert_eq!(tab.iter_panes()[0].pane.scroll_offset(), bottom - 30);
+    }
+
+    #[test]
+    fn close_pane() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        for pane_index in 0..2 {
+            let split = tab
+                .compute_split_size(pane_index, SplitDirection::Horizontal)
+                .unwrap();
+            tab.split_and_insert(
+                pane_index,
+                SplitDirection::Horizontal,
+                FakePane::new(2 + pane_index, split.second),
+            )
+            .unwrap();
+        }
+        let widths = |tab: &Tab| tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        assert_eq!(widths(&tab), vec![40, 19, 19]);
+        tab.set_active_idx(2);
 
-            let existing_pane = Rc::clone(cursor.leaf_mut().unwrap());
+        assert!(tab.close_pane(3).is_err());
+        tab.close_pane(1).unwrap();
 
-            existing_pane.resize(split_info.first)?;
-            pane.resize(split_info.second.clone())?;
+        let panes = tab.iter_panes();
+        assert_eq!(widths(&tab), vec![40, 39]);
+        assert_eq!(panes[1].left, 41);
+        assert_eq!(panes[1].pane.pane_id(), 3);
+        assert_eq!(panes[1].pane.get_dimensions().cols, 39);
+        // The active pane is still the same pane
+        assert_eq!(tab.get_active_idx(), 1);
+        assert!(!panes[1].pane.is_dead());
+
+        tab.close_pane(1).unwrap();
+        assert_eq!(widths(&tab), vec![80]);
+        assert_eq!(tab.get_active_idx(), 0);
+
+        tab.close_pane(0).unwrap();
+        assert!(tab.is_dead());
+        assert_eq!(tab.count_panes(), 0);
+    }
 
-            match cursor.split_leaf_and_insert_right(pane) {
-                Ok(c) => cursor = c,
-                Err(c) => {
-                    root.replace(c.tree());
-                    anyhow::bail!("invalid pane_index {}; cannot split!", pane_index);
-                }
-            };
+    #[test]
+    fn swap_panes() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let geometry = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.left, p.top, p.width, p.height))
+                .collect::<Vec<_>>()
+        };
+        let before = geometry(&tab);
+        tab.set_active_idx(0);
 
-            // cursor now points to the newly created split node;
-            // we need to populate its split information
-            match cursor.assign_node(Some(split_info)) {
-                Err(c) | Ok(c) => root.replace(c.tree()),
-            };
+        tab.swap_panes(0, 2).unwrap();
+        let panes = tab.iter_panes();
+        assert_eq!(
+            panes.iter().map(|p| p.pane.pane_id()).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+        assert_eq!(geometry(&tab), before);
+        assert_eq!(panes[0].pane.get_dimensions().cols, 40);
+        assert_eq!(panes[2].pane.get_dimensions().cols, 19);
+        // The active pane followed its pane
+        assert_eq!(tab.get_active_idx(), 2);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 1);
+
+        assert!(tab.swap_panes(1, 1).is_err());
+        assert!(tab.swap_panes(0, 3).is_err());
+    }
+
+    #[test]
+    fn split_names() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        // Panes 1 and 4 are stacked on the left, with 2 and 3 to their right
+        let tab = three_pane_tab(size);
+        let split = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(0, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+        let splits = tab.iter_splits();
+        assert_eq!(splits.len(), 3);
+        assert_eq!(splits[1].direction, SplitDirection::Vertical);
+
+        tab.set_split_name(0, "main").unwrap();
+        tab.set_split_name(2, "right").unwrap();
+        assert!(tab.set_split_name(3, "nope").is_err());
+        assert_eq!(tab.split_by_name("right"), Some(2));
+        assert_eq!(tab.split_by_name("nope"), None);
+
+        // Removing the stacked pane renumbers the splits
+        tab.iter_panes()[1].pane.kill();
+        assert!(tab.prune_dead_panes());
+        assert_eq!(tab.iter_splits().len(), 2);
+        assert_eq!(tab.split_by_name("main"), Some(0));
+        assert_eq!(tab.split_by_name("right"), Some(1));
+
+        // Splitting a pane inside a named split doesn't disturb it
+        let split = tab.compute_split_size(2, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(2, SplitDirection::Vertical, FakePane::new(5, split.second))
+            .unwrap();
+        assert_eq!(tab.split_by_name("right"), Some(1));
 
-            *self.active.borrow_mut() = pane_index + 1;
+        // Once a side of the split is gone, so is the split
+        tab.iter_panes()[1].pane.kill();
+        assert!(tab.prune_dead_panes());
+        assert_eq!(tab.split_by_name("right"), None);
+    }
+
+    #[test]
+    fn balance() {
+        let size = test_size();
+
+        // Three panes in a row, with the last split into three rows
+        let tab = three_pane_tab(size);
+        for _ in 0..2 {
+            let split = tab.compute_split_size(2, SplitDirection::Vertical).unwrap();
+            tab.split_and_insert(2, SplitDirection::Vertical, FakePane::new(4, split.second))
+                .unwrap();
         }
+        let extents = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.width, p.height))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            extents(&tab),
+            vec![(40, 24), (19, 24), (19, 6), (19, 5), (19, 11)]
+        );
 
-        log::debug!("split info after split: {:#?}", self.iter_splits());
-        log::debug!("pane info after split: {:#?}", self.iter_panes());
+        tab.balance();
+        let balanced = extents(&tab);
+        assert_eq!(
+            balanced,
+            vec![(26, 24), (26, 24), (26, 8), (26, 7), (26, 7)]
+        );
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+            assert_eq!(pos.pane.get_dimensions().viewport_rows, pos.height);
+        }
 
-        Ok(pane_index + 1)
+        // Balancing is idempotent
+        tab.balance();
+        assert_eq!(extents(&tab), balanced);
     }
-}
 
-/// This type is used directly by the codec, take care to bump
-/// the codec version if you change this
-#[derive(Deserialize, Serialize, PartialEq, Debug)]
-pub enum PaneNode {
-    Empty,
-    Split {
-        left: Box<PaneNode>,
-        right: Box<PaneNode>,
-        node: SplitDirectionAndSize,
-    },
-    Leaf(PaneEntry),
-}
+    #[test]
+    fn content_grid() {
+        let size = test_size();
 
-impl PaneNode {
-    pub fn into_tree(self) -> bintree::Tree<PaneEntry, SplitDirectionAndSize> {
-        match self {
-            PaneNode::Empty => bintree::Tree::Empty,
-            PaneNode::Split { left, right, node } => bintree::Tree::Node {
-                left: Box::new((*left).into_tree()),
-                right: Box::new((*right).into_tree()),
-                data: Some(node),
-            },
-            PaneNode::Leaf(e) => bintree::Tree::Leaf(e),
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        tab.set_reserved_bottom_rows(1);
+        tab.set_pane_header_rows(1);
+        assert_eq!(tab.content_grid(), vec![(0, (0, 1, 80, 22))]);
+
+        // The panes are sized to fit their content area
+        fn pane_sizes(tab: &Tab) -> Vec<(usize, usize)> {
+            tab.iter_panes()
+                .iter()
+                .map(|pos| {
+                    let dims = pos.pane.get_dimensions();
+                    (dims.cols, dims.viewport_rows)
+                })
+                .collect()
         }
+        assert_eq!(pane_sizes(&tab), vec![(80, 22)]);
+
+        let split = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(0, SplitDirection::Vertical, FakePane::new(2, split.second))
+            .unwrap();
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(3, split.second),
+        )
+        .unwrap();
+        tab.set_active_idx(2);
+
+        // The 23 usable rows hold 11 rows, a divider and 11 rows, and
+        // each pane gives up its first row to the header
+        assert_eq!(
+            tab.content_grid(),
+            vec![
+                (0, (0, 1, 40, 10)),
+                (1, (41, 1, 39, 10)),
+                (2, (0, 13, 80, 10)),
+            ]
+        );
+        assert_eq!(pane_sizes(&tab), vec![(40, 10), (39, 10), (80, 10)]);
+
+        tab.toggle_zoom();
+        assert_eq!(tab.content_grid(), vec![(0, (0, 1, 80, 22))]);
+        assert_eq!(pane_sizes(&tab), vec![(80, 22)]);
+        tab.toggle_zoom();
+        assert_eq!(pane_sizes(&tab), vec![(40, 10), (39, 10), (80, 10)]);
+
+        tab.set_pane_header_rows(0);
+        assert_eq!(pane_sizes(&tab), vec![(40, 11), (39, 11), (80, 11)]);
+        tab.set_pane_header_rows(1);
+
+        // The active pane is further inset by its border
+        tab.set_active_pane_border(1);
+        assert_eq!(tab.content_grid()[2], (2, (1, 14, 78, 8)));
+        assert_eq!(tab.content_grid()[0], (0, (0, 1, 40, 10)));
     }
 
-    pub fn root_size(&self) -> Option<PtySize> {
-        match self {
-            PaneNode::Empty => None,
-            PaneNode::Split { node, .. } => Some(node.size()),
-            PaneNode::Leaf(entry) => Some(entry.size),
+    #[test]
+    fn overlay_pane() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        let layout = tab.tmux_layout();
+        assert!(!tab.has_overlay_pane());
+        assert!(tab.pop_overlay_pane().is_none());
+
+        let overlay = FakePane::new(10, size);
+        tab.push_overlay_pane(Rc::clone(&overlay));
+        assert!(tab.has_overlay_pane());
+        let panes = tab.iter_panes();
+        assert_eq!(panes.len(), 1);
+        assert_eq!(panes[0].pane.pane_id(), 10);
+        assert_eq!((panes[0].width, panes[0].height), (80, 24));
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 10);
+
+        let popped = tab.pop_overlay_pane().unwrap();
+        assert_eq!(popped.pane_id(), 10);
+        assert!(!tab.has_overlay_pane());
+        assert_eq!(tab.tmux_layout(), layout);
+        assert_eq!(tab.get_active_idx(), 1);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+
+        // Zoom is restored, and a resize while the overlay is shown
+        // is applied to the displaced layout
+        tab.set_zoomed(true);
+        tab.push_overlay_pane(overlay);
+        tab.resize(PtySize {
+            rows: 30,
+            cols: 100,
+            pixel_width: 1000,
+            pixel_height: 750,
+        });
+        assert_eq!(tab.iter_panes()[0].width, 100);
+        tab.pop_overlay_pane().unwrap();
+        assert!(tab.is_zoomed());
+        tab.set_zoomed(false);
+        let widths = tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        assert_eq!(widths.iter().sum::<usize>() + 2, 100);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+            assert_eq!(pos.height, 30);
         }
     }
 
-    pub fn window_and_tab_ids(&self) -> Option<(WindowId, TabId)> {
-        match self {
-            PaneNode::Empty => None,
-            PaneNode::Split { left, right, .. } => match left.window_and_tab_ids() {
-                Some(res) => Some(res),
-                None => right.window_and_tab_ids(),
-            },
-            PaneNode::Leaf(entry) => Some((entry.window_id, entry.tab_id)),
+    #[test]
+    fn dead_overlay_pane() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(1);
+        let layout = tab.tmux_layout();
+
+        // The tab isn't dead just because its overlay died; pruning
+        // pops back to the displaced layout
+        let overlay = FakePane::new(10, size);
+        tab.push_overlay_pane(Rc::clone(&overlay));
+        overlay.kill();
+        assert!(!tab.is_dead());
+        assert!(!tab.should_auto_close());
+        assert!(tab.prune_dead_panes());
+        assert!(!tab.has_overlay_pane());
+        assert_eq!(tab.tmux_layout(), layout);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+
+        // Displaced panes that die are pruned while the overlay is shown
+        let displaced = tab.iter_panes();
+        tab.push_overlay_pane(FakePane::new(11, size));
+        displaced[0].pane.kill();
+        assert!(tab.prune_dead_panes());
+        assert!(tab.has_overlay_pane());
+        assert_eq!(tab.iter_panes()[0].pane.pane_id(), 11);
+        tab.pop_overlay_pane().unwrap();
+        let panes = tab.iter_panes();
+        let ids: Vec<PaneId> = panes.iter().map(|p| p.pane.pane_id()).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(panes[1].left + panes[1].width, 80);
+        for pos in &panes {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
         }
+
+        // Once every pane, displaced or not, has died the tab is dead
+        let overlay = FakePane::new(12, size);
+        tab.push_overlay_pane(Rc::clone(&overlay));
+        overlay.kill();
+        panes[0].pane.kill();
+        assert!(!tab.is_dead());
+        panes[1].pane.kill();
+        assert!(tab.is_dead());
     }
-}
 
-/// This type is used directly by the codec, take care to bump
-/// the codec version if you change this
-#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
-pub struct PaneEntry {
-    pub window_id: WindowId,
-    pub tab_id: TabId,
-    pub pane_id: PaneId,
-    pub title: String,
-    pub size: PtySize,
-    pub working_dir: Option<SerdeUrl>,
-    pub is_active_pane: bool,
-    pub is_zoomed_pane: bool,
-    pub workspace: String,
-}
+    #[test]
+    fn activate_last_pane() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let active_id = |tab: &Tab| tab.get_active_pane().unwrap().pane_id();
+        assert!(!tab.activate_last_pane());
+
+        tab.set_active_idx(1);
+        tab.set_active_idx(2);
+        assert!(tab.activate_last_pane());
+        assert_eq!(active_id(&tab), 2);
+        // Repeated calls toggle between the two most recent panes
+        assert!(tab.activate_last_pane());
+        assert_eq!(active_id(&tab), 3);
+        assert!(tab.activate_last_pane());
+        assert_eq!(active_id(&tab), 2);
+
+        tab.set_active_idx(0);
+        assert_eq!(active_id(&tab), 1);
+
+        // Removing pane 2 shifts pane 3 down to index 1; the history
+        // follows it, and forgets pane 2
+        tab.iter_panes()[1].pane.kill();
+        assert!(tab.prune_dead_panes());
+        assert_eq!(active_id(&tab), 1);
+        assert!(tab.activate_last_pane());
+        assert_eq!(tab.get_active_idx(), 1);
+        assert_eq!(active_id(&tab), 3);
+        assert!(tab.activate_last_pane());
+        assert_eq!(active_id(&tab), 1);
+        assert!(tab.activate_last_pane());
+        assert_eq!(active_id(&tab), 3);
+    }
 
-#[derive(Deserialize, Clone, Serialize, PartialEq, Debug)]
-#[serde(try_from = "String", into = "String")]
-pub struct SerdeUrl {
-    pub url: Url,
-}
+    #[test]
+    fn subscribe_active_change() {
+        ignore_scheduled_tasks();
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        tab.set_active_idx(0);
+        let seen = Rc::new(RefCell::new(vec![]));
+        let sub_id = {
+            let seen = Rc::clone(&seen);
+            tab.subscribe_active_change(Box::new(move |index| seen.borrow_mut().push(index)))
+        };
 
-impl std::convert::TryFrom<String> for SerdeUrl {
-    type Error = url::ParseError;
-    fn try_from(s: String) -> Result<SerdeUrl, url::ParseError> {
-        let url = Url::parse(&s)?;
-        Ok(SerdeUrl { url })
+        tab.set_active_idx(2);
+        // Setting the current value isn't a change
+        tab.set_active_idx(2);
+        assert!(tab.activate_pane_direction(PaneDirection::Left));
+        assert_eq!(*seen.borrow(), vec![2, 1]);
+
+        // Removing the active pane moves the focus to its neighbor
+        tab.iter_panes()[1].pane.kill();
+        tab.prune_dead_panes();
+        assert_eq!(*seen.borrow(), vec![2, 1, 0]);
+
+        assert!(tab.unsubscribe_active_change(sub_id));
+        assert!(!tab.unsubscribe_active_change(sub_id));
+        tab.set_active_idx(1);
+        assert_eq!(seen.borrow().len(), 3);
     }
-}
 
-impl From<Url> for SerdeUrl {
-    fn from(url: Url) -> SerdeUrl {
-        SerdeUrl { url }
+    #[test]
+    fn panes_in_rect() {
+        let size = test_size();
+
+        // A 2x2 grid: 0 top left, 1 bottom left, 2 top right, 3 bottom right
+        let tab = grid_2x2_tab(size);
+
+        // The top row, stopping short of the horizontal divider at row 12
+        assert_eq!(tab.panes_in_rect(0, 0, 80, 12), vec![0, 2]);
+        assert_eq!(tab.panes_in_rect(10, 2, 50, 5), vec![0, 2]);
+        // Including the divider row doesn't reach the bottom panes
+        assert_eq!(tab.panes_in_rect(0, 0, 80, 13), vec![0, 2]);
+        assert_eq!(tab.panes_in_rect(0, 0, 80, 14), vec![0, 1, 2, 3]);
+        // The right hand column
+        assert_eq!(tab.panes_in_rect(41, 0, 1, 24), vec![2, 3]);
+        // The vertical divider alone
+        assert!(tab.panes_in_rect(40, 0, 1, 24).is_empty());
+        assert!(tab.panes_in_rect(0, 0, 0, 24).is_empty());
     }
-}
 
-impl Into<Url> for SerdeUrl {
-    fn into(self) -> Url {
-        self.url
+    #[test]
+    fn rotate() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let geometry = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.left, p.top, p.width, p.height))
+                .collect::<Vec<_>>()
+        };
+        let ids = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| p.pane.pane_id())
+                .collect::<Vec<_>>()
+        };
+        let before = geometry(&tab);
+        tab.set_active_idx(2);
+
+        tab.rotate(true).unwrap();
+        assert_eq!(ids(&tab), vec![3, 1, 2]);
+        assert_eq!(geometry(&tab), before);
+        assert_eq!(tab.get_active_idx(), 0);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+        }
+
+        tab.rotate(false).unwrap();
+        assert_eq!(ids(&tab), vec![1, 2, 3]);
+        assert_eq!(geometry(&tab), before);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+
+        // Like swap_panes, rotating is refused while zoomed
+        tab.toggle_zoom();
+        assert!(tab.rotate(true).is_err());
+        assert!(tab.is_zoomed());
+        tab.toggle_zoom();
+        assert_eq!(ids(&tab), vec![1, 2, 3]);
     }
-}
 
-impl Into<String> for SerdeUrl {
-    fn into(self) -> String {
-        self.url.as_str().into()
+    #[test]
+    fn pane_gutter() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let splits_before: Vec<usize> = tab.iter_splits().iter().map(|s| s.left).collect();
+        let geometry = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.left, p.top, p.width, p.height))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            geometry(&tab),
+            vec![(0, 0, 40, 24), (41, 0, 19, 24), (61, 0, 19, 24)]
+        );
+
+        tab.set_pane_gutter(1);
+        assert_eq!(tab.get_pane_gutter(), 1);
+        assert_eq!(
+            geometry(&tab),
+            vec![(0, 0, 40, 24), (42, 0, 18, 24), (62, 0, 18, 24)]
+        );
+        // The dividers stay where they were
+        let splits_after: Vec<usize> = tab.iter_splits().iter().map(|s| s.left).collect();
+        assert_eq!(splits_before, splits_after);
+        for pos in tab.iter_panes() {
+            let dims = pos.pane.get_dimensions();
+            assert_eq!(dims.cols, pos.width);
+            assert_eq!(dims.viewport_rows, pos.height);
+        }
+
+        // Splitting honors the gutter along the new axis
+        let split = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        assert_eq!((split.first.rows, split.second.rows), (12, 10));
+        tab.split_and_insert(0, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+        assert_eq!(
+            geometry(&tab),
+            vec![
+                (0, 0, 40, 12),
+                (0, 14, 40, 10),
+                (42, 0, 18, 24),
+                (62, 0, 18, 24)
+            ]
+        );
+        assert_eq!(tab.iter_panes()[1].pane.get_dimensions().viewport_rows, 10);
+
+        // Resizing keeps the panes in step with their positions
+        tab.resize(PtySize {
+            rows: 30,
+            cols: 100,
+            pixel_width: 1000,
+            pixel_height: 750,
+        });
+        for pos in tab.iter_panes() {
+            let dims = pos.pane.get_dimensions();
+            assert_eq!(dims.cols, pos.width);
+            assert_eq!(dims.viewport_rows, pos.height);
+        }
+
+        tab.set_pane_gutter(0);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+        }
+        assert_eq!(tab.iter_panes()[2].left, 51);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::renderable::*;
-    use rangeset::RangeSet;
-    use std::ops::Range;
-    use termwiz::surface::SequenceNo;
-    use url::Url;
-    use wezterm_term::color::ColorPalette;
-    use wezterm_term::{KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex};
+    #[test]
+    fn split_with_pane_gutter() {
+        let size = test_size();
 
-    struct FakePane {
-        id: PaneId,
-        size: RefCell<PtySize>,
+        let tab = three_pane_tab(size);
+        tab.set_pane_gutter(1);
+        assert_eq!(tab.iter_panes()[2].width, 18);
+
+        // The 19 cell slot is divided, rather than the 18 cell pane,
+        // and both of the resulting panes lose a cell to the gutter
+        let split = tab
+            .compute_split_size(2, SplitDirection::Horizontal)
+            .unwrap();
+        assert_eq!((split.first.cols, split.second.cols), (8, 8));
+        tab.split_and_insert(
+            2,
+            SplitDirection::Horizontal,
+            FakePane::new(4, split.second),
+        )
+        .unwrap();
+        let geometry = |tab: &Tab| {
+            tab.iter_panes()
+                .iter()
+                .map(|p| (p.left, p.width))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(geometry(&tab), vec![(0, 40), (42, 18), (62, 8), (72, 8)]);
+
+        // Without the gutter, the panes fill the tab again
+        tab.set_pane_gutter(0);
+        assert_eq!(geometry(&tab), vec![(0, 40), (41, 19), (61, 9), (71, 9)]);
     }
 
-    impl FakePane {
-        fn new(id: PaneId, size: PtySize) -> Rc<dyn Pane> {
-            Rc::new(Self {
-                id,
-                size: RefCell::new(size),
-            })
-        }
+    #[test]
+    fn activate_pane_direction_with_pane_gutter() {
+        ignore_scheduled_tasks();
+        let tab = grid_2x2_tab(test_size());
+        tab.set_pane_gutter(1);
+
+        let activate = |from: usize, direction: PaneDirection| -> Option<PaneId> {
+            tab.set_active_idx(from);
+            if tab.activate_pane_direction(direction) {
+                Some(tab.get_active_pane().unwrap().pane_id())
+            } else {
+                None
+            }
+        };
+
+        // The panes are in the topological order 1, 3, 2, 4
+        assert_eq!(activate(0, PaneDirection::Right), Some(2));
+        assert_eq!(activate(0, PaneDirection::Down), Some(3));
+        assert_eq!(activate(0, PaneDirection::Left), None);
+        assert_eq!(activate(1, PaneDirection::Up), Some(1));
+        assert_eq!(activate(1, PaneDirection::Right), Some(4));
+        assert_eq!(activate(2, PaneDirection::Left), Some(1));
+        assert_eq!(activate(3, PaneDirection::Up), Some(2));
+        assert_eq!(activate(3, PaneDirection::Left), Some(3));
+
+        // Merging finds the neighbor across the gutter too
+        assert_eq!(tab.merge_panes(2, PaneDirection::Down).unwrap(), 4);
+        assert_eq!(tab.best_neighbor(0, PaneDirection::Right), Some(2));
     }
 
-    impl Pane for FakePane {
-        fn pane_id(&self) -> PaneId {
-            self.id
+    #[test]
+    fn pane_accent_color() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let colors: Vec<RgbColor> = (0..3)
+            .map(|idx| tab.pane_accent_color(idx).unwrap())
+            .collect();
+        assert_eq!(tab.pane_accent_color(3), None);
+
+        // Stable across calls and layout changes
+        assert_eq!(tab.pane_accent_color(0), Some(colors[0]));
+        tab.rotate(true).unwrap();
+        assert_eq!(tab.pane_accent_color(1), Some(colors[0]));
+        assert_eq!(colors[0], pane_accent_color_for_id(1));
+
+        for (idx, color) in colors.iter().enumerate() {
+            assert!(!colors[idx + 1..].contains(color));
         }
+        let ids: HashSet<_> = (0..64)
+            .map(|id| pane_accent_color_for_id(id).to_tuple_rgb8())
+            .collect();
+        assert_eq!(ids.len(), 64);
+    }
 
-        fn get_cursor_position(&self) -> StableCursorPosition {
-            unimplemented!();
-        }
+    #[test]
+    fn is_dirty() {
+        let size = test_size();
 
-        fn get_current_seqno(&self) -> SequenceNo {
-            unimplemented!();
-        }
+        let tab = three_pane_tab(size);
+        let panes: Vec<Rc<dyn Pane>> = tab.iter_panes().into_iter().map(|p| p.pane).collect();
+        let rendered: Vec<SequenceNo> = panes.iter().map(|p| p.get_current_seqno()).collect();
+        assert!(panes
+            .iter()
+            .zip(&rendered)
+            .all(|(pane, &seqno)| !pane.is_dirty(seqno)));
 
-        fn get_changed_since(
-            &self,
-            _lines: Range<StableRowIndex>,
-            _: SequenceNo,
-        ) -> RangeSet<StableRowIndex> {
-            unimplemented!();
-        }
+        // Only the pane that produced output needs repainting
+        let fake = panes[1].downcast_ref::<FakePane>().unwrap();
+        fake.changes.borrow_mut().push((102, 1));
+        let dirty: Vec<bool> = panes
+            .iter()
+            .zip(&rendered)
+            .map(|(pane, &seqno)| pane.is_dirty(seqno))
+            .collect();
+        assert_eq!(dirty, vec![false, true, false]);
+        assert!(!panes[1].is_dirty(panes[1].get_current_seqno()));
+
+        // Panes without change tracking are always repainted
+        let pending = PendingPane::new(0, size);
+        assert!(pending.is_dirty(pending.get_current_seqno()));
+    }
 
-        fn get_lines(&self, _lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
-            unimplemented!();
-        }
+    #[test]
+    fn read_only_pane() {
+        let size = test_size();
 
-        fn get_dimensions(&self) -> RenderableDimensions {
-            unimplemented!();
-        }
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+        tab.set_active_idx(0);
+        let active = tab.get_active_pane().unwrap();
+        let other = tab.iter_panes()[1].pane.clone();
+
+        assert!(!active.is_read_only());
+        assert!(tab.set_active_read_only(true));
+        assert!(active.is_read_only());
+        assert!(!other.is_read_only());
+
+        active.send_paste("hello").unwrap();
+        other.send_paste("hello").unwrap();
+        assert_eq!(active.total_pasted_bytes(), 0);
+        assert_eq!(other.total_pasted_bytes(), 5);
+
+        tab.set_active_read_only(false);
+        assert!(!active.is_read_only());
+        active.send_paste("hello").unwrap();
+        assert_eq!(active.total_pasted_bytes(), 5);
+
+        // Panes that don't support read-only mode are left as they are
+        let tab = Tab::new(&size);
+        tab.assign_pane(&(Rc::new(PendingPane::new(3, size)) as Rc<dyn Pane>));
+        assert!(!tab.set_active_read_only(true));
+        assert!(!tab.get_active_pane().unwrap().is_read_only());
+    }
 
-        fn get_title(&self) -> String {
-            unimplemented!()
-        }
-        fn send_paste(&self, _text: &str) -> anyhow::Result<()> {
-            unimplemented!()
-        }
-        fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>> {
-            Ok(None)
-        }
-        fn writer(&self) -> RefMut<dyn std::io::Write> {
-            unimplemented!()
-        }
-        fn resize(&self, size: PtySize) -> anyhow::Result<()> {
-            *self.size.borrow_mut() = size;
-            Ok(())
-        }
+    #[test]
+    fn count_panes_and_splits() {
+        let size = test_size();
+        let check = |tab: &Tab| {
+            assert_eq!(tab.count_panes(), tab.iter_panes_ignoring_zoom().len());
+            assert_eq!(tab.count_splits(), tab.iter_splits().len());
+        };
 
-        fn key_down(&self, _key: KeyCode, _mods: KeyModifiers) -> anyhow::Result<()> {
-            unimplemented!()
-        }
-        fn key_up(&self, _: KeyCode, _: KeyModifiers) -> anyhow::Result<()> {
-            unimplemented!()
-        }
-        fn mouse_event(&self, _event: MouseEvent) -> anyhow::Result<()> {
-            unimplemented!()
-        }
-        fn is_dead(&self) -> bool {
-            false
-        }
-        fn palette(&self) -> ColorPalette {
-            unimplemented!()
-        }
-        fn domain_id(&self) -> DomainId {
-            1
-        }
-        fn is_mouse_grabbed(&self) -> bool {
-            false
-        }
-        fn is_alt_screen_active(&self) -> bool {
-            false
+        let tab = Tab::new(&size);
+        check(&tab);
+        tab.assign_pane(&FakePane::new(1, size));
+        check(&tab);
+        assert_eq!((tab.count_panes(), tab.count_splits()), (1, 0));
+
+        let tab = three_pane_tab(size);
+        check(&tab);
+        assert_eq!(tab.count_panes(), tab.iter_panes().len());
+
+        // Nest some vertical splits within the horizontal ones
+        for (index, id) in [(1, 4), (0, 5), (4, 6)].iter() {
+            let split = tab
+                .compute_split_size(*index, SplitDirection::Vertical)
+                .unwrap();
+            tab.split_and_insert(
+                *index,
+                SplitDirection::Vertical,
+                FakePane::new(*id, split.second),
+            )
+            .unwrap();
+            check(&tab);
+            assert_eq!(tab.count_panes(), tab.iter_panes().len());
         }
-        fn get_current_working_dir(&self) -> Option<Url> {
-            None
+        assert_eq!((tab.count_panes(), tab.count_splits()), (6, 5));
+
+        tab.set_zoomed(true);
+        check(&tab);
+        assert_eq!((tab.count_panes(), tab.count_splits()), (6, 0));
+    }
+
+    #[test]
+    fn pane_lookup_by_id() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        for (index, id) in [1, 2, 3].iter().enumerate() {
+            assert_eq!(tab.get_pane_index_by_id(*id), Some(index));
+            let pos = tab.positioned_pane_by_id(*id).unwrap();
+            assert_eq!(pos.index, index);
+            assert_eq!(pos.pane.pane_id(), *id);
+            assert_eq!(
+                Some((pos.left, pos.top, pos.width, pos.height)),
+                tab.pane_rect(index)
+            );
         }
+        assert_eq!(tab.get_pane_index_by_id(4), None);
+        assert!(tab.positioned_pane_by_id(4).is_none());
+
+        tab.set_active_idx(tab.get_pane_index_by_id(3).unwrap());
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+
+        // While zoomed, the hidden panes keep their index but have no
+        // visible position
+        tab.set_active_idx(1);
+        tab.set_zoomed(true);
+        assert_eq!(tab.get_pane_index_by_id(3), Some(2));
+        assert!(tab.positioned_pane_by_id(3).is_none());
+        assert!(tab.positioned_pane_by_id(2).unwrap().is_zoomed);
     }
 
     #[test]
-    fn tab_splitting() {
+    fn split_keeping_even() {
+        // 83 columns leave 81 for three columns and 80 for four
         let size = PtySize {
             rows: 24,
-            cols: 80,
-            pixel_width: 800,
+            cols: 83,
+            pixel_width: 830,
             pixel_height: 600,
         };
+        let widths = |tab: &Tab| tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
 
         let tab = Tab::new(&size);
         tab.assign_pane(&FakePane::new(1, size));
+        tab.split_keeping_even(0, SplitDirection::Horizontal, FakePane::new(2, size))
+            .unwrap();
+        tab.split_keeping_even(1, SplitDirection::Horizontal, FakePane::new(3, size))
+            .unwrap();
+        assert_eq!(widths(&tab), vec![27, 27, 27]);
+
+        // Splitting the middle column adds a fourth equal column
+        let new_index = tab
+            .split_keeping_even(1, SplitDirection::Horizontal, FakePane::new(4, size))
+            .unwrap();
+        assert_eq!(new_index, 2);
+        assert_eq!(widths(&tab), vec![20, 20, 20, 20]);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+        }
 
+        // Splitting across the row is a normal half split
+        tab.split_keeping_even(0, SplitDirection::Vertical, FakePane::new(5, size))
+            .unwrap();
         let panes = tab.iter_panes();
-        assert_eq!(1, panes.len());
-        assert_eq!(0, panes[0].index);
-        assert_eq!(true, panes[0].is_active);
-        assert_eq!(0, panes[0].left);
-        assert_eq!(0, panes[0].top);
-        assert_eq!(80, panes[0].width);
-        assert_eq!(24, panes[0].height);
+        assert_eq!(widths(&tab), vec![20, 20, 20, 20, 20]);
+        assert_eq!((panes[0].height, panes[1].height), (12, 11));
+    }
 
-        assert!(tab
-            .compute_split_size(1, SplitDirection::Horizontal)
-            .is_none());
+    #[test]
+    fn total_scrollback_rows() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        let per_pane = 24 + FAKE_SCROLLBACK;
+        assert_eq!(tab.total_scrollback_rows(), 3 * per_pane);
+
+        // Panes hidden by zoom still hold their scrollback
+        tab.set_zoomed(true);
+        assert_eq!(tab.total_scrollback_rows(), 3 * per_pane);
+        tab.set_zoomed(false);
+
+        // The placeholder reports only its visible rows, and the pane
+        // that was split keeps a fixed amount of scrollback
+        let split = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
+        assert_eq!((split.first.rows, split.second.rows), (12, 11));
+        tab.split_and_insert(
+            0,
+            SplitDirection::Vertical,
+            Rc::new(PendingPane::new(0, split.second)),
+        )
+        .unwrap();
+        assert_eq!(
+            tab.total_scrollback_rows(),
+            (12 + FAKE_SCROLLBACK) + 11 + 2 * per_pane
+        );
+    }
 
-        let horz_size = tab
+    #[test]
+    fn split_with_ratio() {
+        let size = test_size();
+        let with_ratio = |ratio| SplitOptions {
+            ratio: Some(ratio),
+            ..Default::default()
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+
+        // 79 cells remain after the divider
+        let split = tab
+            .compute_split_size_with_options(0, SplitDirection::Horizontal, with_ratio(0.7))
+            .unwrap();
+        assert_eq!((split.first.cols, split.second.cols), (55, 24));
+        assert_eq!(split.first.pixel_width, 550);
+
+        let split = tab
+            .compute_split_size_with_options(0, SplitDirection::Horizontal, with_ratio(0.25))
+            .unwrap();
+        assert_eq!((split.first.cols, split.second.cols), (20, 59));
+
+        // Without a ratio the space is halved, as before
+        let split = tab
             .compute_split_size(0, SplitDirection::Horizontal)
             .unwrap();
-        assert_eq!(
-            horz_size,
-            SplitDirectionAndSize {
-                direction: SplitDirection::Horizontal,
-                first: PtySize {
-                    rows: 24,
-                    cols: 40,
-                    pixel_width: 400,
-                    pixel_height: 600
-                },
-                second: PtySize {
-                    rows: 24,
-                    cols: 39,
-                    pixel_width: 390,
-                    pixel_height: 600
-                },
-            }
-        );
+        assert_eq!((split.first.cols, split.second.cols), (40, 39));
+
+        tab.split_and_insert_with_options(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, size),
+            with_ratio(0.7),
+        )
+        .unwrap();
+        let widths = tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+        assert_eq!(widths, vec![55, 24]);
+        assert_eq!(tab.iter_panes()[1].pane.get_dimensions().cols, 24);
+
+        // Extreme ratios still leave a cell for each child
+        let tiny = PtySize {
+            rows: 24,
+            cols: 3,
+            pixel_width: 30,
+            pixel_height: 600,
+        };
+        let tab = Tab::new(&tiny);
+        tab.assign_pane(&FakePane::new(1, tiny));
+        for ratio in [0.01, 0.99].iter() {
+            let split = tab
+                .compute_split_size_with_options(0, SplitDirection::Horizontal, with_ratio(*ratio))
+                .unwrap();
+            assert_eq!((split.first.cols, split.second.cols), (1, 1));
+        }
+    }
 
-        let vert_size = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
-        assert_eq!(
-            vert_size,
-            SplitDirectionAndSize {
-                direction: SplitDirection::Vertical,
-                first: PtySize {
-                    rows: 12,
-                    cols: 80,
-                    pixel_width: 800,
-                    pixel_height: 300
-                },
-                second: PtySize {
-                    rows: 11,
-                    cols: 80,
-                    pixel_width: 800,
-                    pixel_height: 275
-                }
-            }
-        );
+    #[test]
+    fn split_and_insert_positioned() {
+        let size = test_size();
 
-        let new_index = tab
-            .split_and_insert(
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let (index, pos) = tab
+            .split_and_insert_positioned(
                 0,
                 SplitDirection::Horizontal,
-                FakePane::new(2, horz_size.second),
+                FakePane::new(2, size),
+                SplitOptions::default(),
             )
             .unwrap();
-        assert_eq!(new_index, 1);
-
-        let panes = tab.iter_panes();
-        assert_eq!(2, panes.len());
-
-        assert_eq!(0, panes[0].index);
-        assert_eq!(false, panes[0].is_active);
-        assert_eq!(0, panes[0].left);
-        assert_eq!(0, panes[0].top);
-        assert_eq!(40, panes[0].width);
-        assert_eq!(24, panes[0].height);
-        assert_eq!(400, panes[0].pixel_width);
-        assert_eq!(600, panes[0].pixel_height);
-        assert_eq!(1, panes[0].pane.pane_id());
-
-        assert_eq!(1, panes[1].index);
-        assert_eq!(true, panes[1].is_active);
-        assert_eq!(41, panes[1].left);
-        assert_eq!(0, panes[1].top);
-        assert_eq!(39, panes[1].width);
-        assert_eq!(24, panes[1].height);
-        assert_eq!(390, panes[1].pixel_width);
-        assert_eq!(600, panes[1].pixel_height);
-        assert_eq!(2, panes[1].pane.pane_id());
+        assert_eq!(index, 1);
+        assert_eq!(pos.pane.pane_id(), 2);
+        assert_eq!((pos.left, pos.top, pos.width, pos.height), (41, 0, 39, 24));
+        assert_eq!((pos.pixel_width, pos.pixel_height), (390, 600));
 
-        let vert_size = tab.compute_split_size(0, SplitDirection::Vertical).unwrap();
-        let new_index = tab
-            .split_and_insert(
+        let (index, pos) = tab
+            .split_and_insert_positioned(
                 0,
                 SplitDirection::Vertical,
-                FakePane::new(3, vert_size.second),
+                FakePane::new(3, size),
+                SplitOptions {
+                    insert_before: true,
+                    ..Default::default()
+                },
             )
             .unwrap();
-        assert_eq!(new_index, 1);
+        assert_eq!(index, 0);
+        let listed = &tab.iter_panes()[index];
+        assert_eq!(listed.pane.pane_id(), pos.pane.pane_id());
+        assert_eq!(
+            (listed.left, listed.top, listed.width, listed.height),
+            (pos.left, pos.top, pos.width, pos.height)
+        );
+        assert_eq!(
+            (listed.pixel_width, listed.pixel_height),
+            (pos.pixel_width, pos.pixel_height)
+        );
+        assert_eq!(pos.pixel_height, pos.height * 25);
+    }
+
+    #[test]
+    fn apply_percentage_layout() {
+        let size = test_size();
+        let widths = |tab: &Tab| tab.iter_panes().iter().map(|p| p.width).collect::<Vec<_>>();
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        assert!(tab.apply_percentage_layout(&[1.0]).is_ok());
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+
+        // 79 cells remain after the divider; 30% of those is 23.7
+        tab.apply_percentage_layout(&[0.3, 0.7]).unwrap();
+        assert_eq!(widths(&tab), vec![24, 55]);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_dimensions().cols, pos.width);
+        }
 
+        // The proportions carry through a resize
+        tab.resize(PtySize {
+            rows: 24,
+            cols: 160,
+            pixel_width: 1600,
+            pixel_height: 600,
+        });
+        let resized = widths(&tab);
+        assert_eq!(resized, vec![48, 111]);
+
+        assert!(tab.apply_percentage_layout(&[0.3, 0.6]).is_err());
+        assert!(tab.apply_percentage_layout(&[0.5, 0.25, 0.25]).is_err());
+        assert!(tab.apply_percentage_layout(&[1.2, -0.2]).is_err());
+        assert_eq!(widths(&tab), resized);
+
+        // Moving the divider by hand drops the percentage layout, so
+        // a resize no longer restores the 30% column
+        tab.resize_split_by(0, 12);
+        assert_eq!(widths(&tab), vec![60, 99]);
+        assert!(tab.percentage_layout.borrow().is_none());
+        tab.resize(test_size());
+        assert_eq!(widths(&tab), vec![20, 59]);
+
+        // The nested splits of the other direction are left alone
+        let tab = three_pane_tab(size);
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+        tab.apply_percentage_layout(&[0.5, 0.25, 0.25]).unwrap();
         let panes = tab.iter_panes();
-        assert_eq!(3, panes.len());
+        assert_eq!(widths(&tab), vec![39, 20, 20, 19]);
+        assert_eq!((panes[1].height, panes[2].height), (12, 11));
+    }
 
-        assert_eq!(0, panes[0].index);
-        assert_eq!(false, panes[0].is_active);
-        assert_eq!(0, panes[0].left);
-        assert_eq!(0, panes[0].top);
-        assert_eq!(40, panes[0].width);
-        assert_eq!(12, panes[0].height);
-        assert_eq!(400, panes[0].pixel_width);
-        assert_eq!(300, panes[0].pixel_height);
-        assert_eq!(1, panes[0].pane.pane_id());
+    #[test]
+    fn output_triggers() {
+        let tab = three_pane_tab(test_size());
+        tab.set_output_trigger(1, Pattern::CaseSensitiveString("build finished".into()))
+            .unwrap();
+        tab.set_output_trigger(2, Pattern::CaseSensitiveString("done".into()))
+            .unwrap();
+        assert!(tab
+            .set_output_trigger(3, Pattern::CaseSensitiveString("done".into()))
+            .is_err());
+
+        let mut parser = termwiz::escape::parser::Parser::new();
+        let mut output = |index: usize, bytes: &[u8]| {
+            tab.iter_panes()[index]
+                .pane
+                .perform_actions(parser.parse_as_vec(bytes));
+        };
 
-        assert_eq!(1, panes[1].index);
-        assert_eq!(true, panes[1].is_active);
-        assert_eq!(0, panes[1].left);
-        assert_eq!(13, panes[1].top);
-        assert_eq!(40, panes[1].width);
-        assert_eq!(11, panes[1].height);
-        assert_eq!(400, panes[1].pixel_width);
-        assert_eq!(275, panes[1].pixel_height);
-        assert_eq!(3, panes[1].pane.pane_id());
+        output(1, b"build fin");
+        assert!(tab.take_triggered_panes().is_empty());
+        output(1, b"ished\r\n");
+        output(2, b"\x1b[1mdone\x1b[0m\r\n");
+        assert_eq!(tab.take_triggered_panes(), vec![2, 3]);
 
-        assert_eq!(2, panes[2].index);
-        assert_eq!(false, panes[2].is_active);
-        assert_eq!(41, panes[2].left);
-        assert_eq!(0, panes[2].top);
-        assert_eq!(39, panes[2].width);
-        assert_eq!(24, panes[2].height);
-        assert_eq!(390, panes[2].pixel_width);
-        assert_eq!(600, panes[2].pixel_height);
-        assert_eq!(2, panes[2].pane.pane_id());
+        // The triggers only fire once
+        output(1, b"build finished\r\n");
+        output(2, b"done\r\n");
+        assert!(tab.take_triggered_panes().is_empty());
+    }
 
-        tab.resize_split_by(1, 1);
+    #[test]
+    fn output_rate() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        assert!(!tab.any_pane_busy());
+
+        let pane = tab.iter_panes()[1].pane.clone();
+        pane.record_output(8192);
+        assert!(pane.is_output_active(BUSY_OUTPUT_RATE));
+        assert!(!tab.iter_panes()[0].pane.is_output_active(BUSY_OUTPUT_RATE));
+        assert!(tab.any_pane_busy());
+
+        // The rate decays once the output stops
+        let start = Instant::now();
+        let mut rate = OutputRate::default();
+        rate.record_at(8192, start);
+        assert!(rate.rate_at(start) > BUSY_OUTPUT_RATE);
+        let half = rate.rate_at(start + std::time::Duration::from_secs(1));
+        assert!((half - rate.rate_at(start) / 2.).abs() < 1.);
+        assert!(rate.rate_at(start + std::time::Duration::from_secs(10)) < BUSY_OUTPUT_RATE);
+
+        // A steady stream settles at its actual rate
+        let mut rate = OutputRate::default();
+        for tick in 0..200 {
+            rate.record_at(100, start + std::time::Duration::from_millis(tick * 100));
+        }
+        let steady = rate.rate_at(start + std::time::Duration::from_millis(19_950));
+        assert!((steady - 1000.).abs() < 50., "steady rate {}", steady);
+    }
+
+    #[test]
+    fn resize_to_minimum_pane_size() {
+        let size = test_size();
+        let sized = |cols, rows| PtySize {
+            rows,
+            cols,
+            pixel_width: cols * 10,
+            pixel_height: rows * 25,
+        };
+        let check = |tab: &Tab| {
+            let usable = tab.usable_size();
+            let (min_cols, min_rows) = tab.get_min_pane_size();
+            for pos in tab.iter_panes() {
+                assert!(pos.width >= min_cols && pos.height >= min_rows);
+                assert!(pos.left + pos.width <= usable.cols as usize);
+                assert!(pos.top + pos.height <= usable.rows as usize);
+                let dims = pos.pane.get_dimensions();
+                assert_eq!((dims.cols, dims.viewport_rows), (pos.width, pos.height));
+            }
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, split.second),
+        )
+        .unwrap();
+
+        tab.resize(sized(3, 24));
         let panes = tab.iter_panes();
-        assert_eq!(40, panes[0].width);
-        assert_eq!(13, panes[0].height);
-        assert_eq!(400, panes[0].pixel_width);
-        assert_eq!(325, panes[0].pixel_height);
+        assert_eq!((panes[0].width, panes[1].width), (1, 1));
+        assert_eq!(panes[0].width + 1 + panes[1].width, 3);
+        check(&tab);
+
+        // The tab doesn't shrink below what its panes need
+        tab.resize(sized(2, 24));
+        assert_eq!(tab.get_size().cols, 3);
+        check(&tab);
+
+        // Shrinking step by step around nested splits used to loop
+        // forever once a nested pair reached a single cell each
+        let tab = three_pane_tab(size);
+        let split = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(4, split.second),
+        )
+        .unwrap();
+        for cols in (1..80).rev() {
+            tab.resize(sized(cols, 24));
+            check(&tab);
+        }
+        assert_eq!(tab.get_size().cols, 7);
+
+        // A larger minimum is honored too
+        let tab = three_pane_tab(size);
+        tab.set_min_pane_size(5, 3);
+        tab.resize(sized(10, 4));
+        assert_eq!(tab.get_size().cols, 17);
+        assert_eq!(tab.get_size().rows, 4);
+        check(&tab);
+    }
 
-        assert_eq!(40, panes[1].width);
-        assert_eq!(10, panes[1].height);
-        assert_eq!(400, panes[1].pixel_width);
-        assert_eq!(250, panes[1].pixel_height);
+    #[test]
+    fn deeply_nested_positions() {
+        let size = test_size();
 
-        assert_eq!(39, panes[2].width);
-        assert_eq!(24, panes[2].height);
-        assert_eq!(390, panes[2].pixel_width);
-        assert_eq!(600, panes[2].pixel_height);
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        // Each split divides the second child of the previous one,
+        // alternating the direction, so that every ancestor of the
+        // deepest pane contributes to its position
+        let steps = [
+            (0, SplitDirection::Horizontal),
+            (1, SplitDirection::Vertical),
+            (2, SplitDirection::Horizontal),
+            (3, SplitDirection::Vertical),
+        ];
+        for (id, (index, direction)) in steps.iter().enumerate() {
+            let split = tab.compute_split_size(*index, *direction).unwrap();
+            let new_index = tab
+                .split_and_insert(*index, *direction, FakePane::new(id + 2, split.second))
+                .unwrap();
+            assert_eq!(new_index, index + 1);
+        }
+
+        let geometry: Vec<_> = tab
+            .iter_panes()
+            .iter()
+            .map(|p| (p.pane.pane_id(), p.left, p.top, p.width, p.height))
+            .collect();
+        assert_eq!(
+            geometry,
+            vec![
+                (1, 0, 0, 40, 24),
+                (2, 41, 0, 39, 12),
+                (3, 41, 13, 19, 11),
+                (4, 61, 13, 19, 5),
+                (5, 61, 19, 19, 5),
+            ]
+        );
+        for (index, (_, left, top, width, height)) in geometry.iter().enumerate() {
+            assert_eq!(tab.pane_rect(index), Some((*left, *top, *width, *height)));
+        }
+    }
+
+    #[test]
+    fn spatial_pane_order() {
+        let size = test_size();
+
+        let tab = grid_2x2_tab(size);
+        let ids: Vec<PaneId> = tab.iter_panes().iter().map(|p| p.pane.pane_id()).collect();
+        assert_eq!(ids, vec![1, 3, 2, 4]);
+
+        // Reading order visits top-left, top-right, bottom-left and
+        // bottom-right, then wraps around
+        let mut index = 0;
+        let mut visited = vec![];
+        for _ in 0..5 {
+            visited.push(index);
+            index = tab.next_pane_spatial(index);
+        }
+        assert_eq!(visited, vec![0, 2, 1, 3, 0]);
+
+        let mut index = 0;
+        let mut visited = vec![];
+        for _ in 0..5 {
+            visited.push(index);
+            index = tab.prev_pane_spatial(index);
+        }
+        assert_eq!(visited, vec![0, 3, 1, 2, 0]);
+
+        assert_eq!(tab.next_pane_spatial(42), 0);
+    }
+
+    #[test]
+    fn current_working_dir_async() {
+        let size = test_size();
+
+        let tab = three_pane_tab(size);
+        for pos in tab.iter_panes() {
+            let cwd = smol::block_on(pos.pane.get_current_working_dir_async()).unwrap();
+            assert_eq!(cwd, None);
+            assert_eq!(cwd, pos.pane.get_current_working_dir());
+        }
+    }
+
+    #[test]
+    fn rebuild_from_layout_scaled() {
+        let size = test_size();
+        let large = PtySize {
+            rows: 48,
+            cols: 160,
+            pixel_width: 1600,
+            pixel_height: 1200,
+        };
+
+        let tab = three_pane_tab(size);
+        let split = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(1, SplitDirection::Vertical, FakePane::new(4, split.second))
+            .unwrap();
+        let layout = tab.serialize_layout();
+
+        let restored = Tab::new(&size);
+        restored.rebuild_from_layout_scaled(&layout, large, |pane_id| FakePane::new(pane_id, size));
+        assert_eq!(restored.get_size(), large);
+
+        let original = tab.iter_panes();
+        let scaled = restored.iter_panes();
+        assert_eq!(scaled.len(), original.len());
+        for (before, after) in original.iter().zip(scaled.iter()) {
+            assert_eq!(before.pane.pane_id(), after.pane.pane_id());
+            // Each pane keeps its share of the tab, allowing for the
+            // rounding and the dividers
+            let share = |pos: &PositionedPane, total: &PtySize| {
+                (
+                    pos.width as f32 / total.cols as f32,
+                    pos.height as f32 / total.rows as f32,
+                    pos.left as f32 / total.cols as f32,
+                    pos.top as f32 / total.rows as f32,
+                )
+            };
+            let (a, b) = (share(before, &size), share(after, &large));
+            assert!((a.0 - b.0).abs() < 0.02, "{:?} vs {:?}", a, b);
+            assert!((a.1 - b.1).abs() < 0.03, "{:?} vs {:?}", a, b);
+            assert!((a.2 - b.2).abs() < 0.02, "{:?} vs {:?}", a, b);
+            assert!((a.3 - b.3).abs() < 0.03, "{:?} vs {:?}", a, b);
+
+            let dims = after.pane.get_dimensions();
+            assert_eq!((dims.cols, dims.viewport_rows), (after.width, after.height));
+        }
+        let widths: Vec<usize> = scaled.iter().map(|p| p.width).collect();
+        assert_eq!(widths, vec![81, 39, 39, 38]);
+        assert_eq!(
+            scaled.last().unwrap().left + scaled.last().unwrap().width,
+            160
+        );
+    }
+
+    #[test]
+    fn degenerate_panes() {
+        let size = test_size();
+        let tab = three_pane_tab(size);
+        assert!(tab.degenerate_panes().is_empty());
+
+        let sized = |cols: u16, rows: u16| PtySize {
+            rows,
+            cols,
+            pixel_width: cols * 10,
+            pixel_height: rows * 25,
+        };
+        let layout = SerializedTab::Split {
+            split: SplitDirectionAndSize {
+                direction: SplitDirection::Horizontal,
+                first: sized(0, 24),
+                second: sized(79, 24),
+            },
+            first: Box::new(SerializedTab::Pane(1)),
+            second: Box::new(SerializedTab::Split {
+                split: SplitDirectionAndSize {
+                    direction: SplitDirection::Vertical,
+                    first: sized(79, 23),
+                    second: sized(79, 0),
+                },
+                first: Box::new(SerializedTab::Pane(2)),
+                second: Box::new(SerializedTab::Pane(3)),
+            }),
+        };
+        let tab = Tab::new(&size);
+        tab.rebuild_from_layout(&layout, |pane_id| FakePane::new(pane_id, size));
+        assert_eq!(tab.degenerate_panes(), vec![0, 2]);
+
+        // Panes hidden by zoom are still checked
+        tab.set_active_idx(1);
+        tab.set_zoomed(true);
+        assert_eq!(tab.degenerate_panes(), vec![0, 2]);
+    }
+
+    #[test]
+    fn semantic_zones() {
+        let size = test_size();
+
+        // Panes that don't track OSC 133 markers report no zones
+        let tab = three_pane_tab(size);
+        for pos in tab.iter_panes() {
+            assert_eq!(pos.pane.get_semantic_zones().unwrap(), vec![]);
+        }
     }
 }
@@ -4,7 +4,7 @@
 //! session.
 
 use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
-use crate::pane::{alloc_pane_id, CloseReason, Pane, PaneId};
+use crate::pane::{alloc_pane_id, CloseReason, Pane, PaneCapabilities, PaneId, ViewportState};
 use crate::renderable::*;
 use crate::tab::Tab;
 use crate::window::WindowId;
@@ -85,6 +85,7 @@ pub struct TermWizTerminalPane {
     dead: RefCell<bool>,
     writer: RefCell<Vec<u8>>,
     render_rx: FileDescriptor,
+    viewport: ViewportState,
 }
 
 impl TermWizTerminalPane {
@@ -112,6 +113,7 @@ impl TermWizTerminalPane {
             render_rx,
             input_tx,
             dead: RefCell::new(false),
+            viewport: ViewportState::default(),
         }
     }
 }
@@ -145,6 +147,21 @@ impl Pane for TermWizTerminalPane {
         terminal_get_dimensions(&mut self.terminal.borrow_mut())
     }
 
+    fn scroll_offset(&self) -> StableRowIndex {
+        self.viewport.top(&self.get_dimensions())
+    }
+
+    fn set_viewport_top(&self, top: StableRowIndex) {
+        self.viewport.set_top(top, &self.get_dimensions());
+    }
+
+    fn capabilities(&self) -> PaneCapabilities {
+        PaneCapabilities::SCROLLBACK
+            | PaneCapabilities::RESIZE
+            | PaneCapabilities::CWD
+            | PaneCapabilities::VIEWPORT
+    }
+
     fn get_title(&self) -> String {
         self.terminal.borrow_mut().get_title().to_string()
     }
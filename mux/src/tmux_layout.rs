@@ -0,0 +1,236 @@
+//! Emits and parses the window layout descriptions used by tmux,
+//! for example `b25d,80x24,0,0,0`.
+//! A layout is a checksum followed by a tree of cells; each cell has
+//! a size and offset, and is either a pane (identified by its id) or
+//! a list of child cells arranged left to right (`{...}`) or top to
+//! bottom (`[...]`).
+
+use crate::tab::SplitDirection;
+use anyhow::{anyhow, bail, ensure};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TmuxLayout {
+    Pane {
+        width: usize,
+        height: usize,
+        x: usize,
+        y: usize,
+        pane_id: usize,
+    },
+    Split {
+        width: usize,
+        height: usize,
+        x: usize,
+        y: usize,
+        /// Horizontal is tmux's left-right `{}` arrangement and
+        /// Vertical is its top-bottom `[]` arrangement
+        direction: SplitDirection,
+        children: Vec<TmuxLayout>,
+    },
+}
+
+/// Computes the checksum that tmux prefixes to a layout
+pub fn tmux_layout_checksum(layout: &str) -> u16 {
+    let mut csum: u16 = 0;
+    for &b in layout.as_bytes() {
+        csum = (csum >> 1) | ((csum & 1) << 15);
+        csum = csum.wrapping_add(b as u16);
+    }
+    csum
+}
+
+impl TmuxLayout {
+    /// Parses a layout, including its checksum prefix
+    pub fn parse(layout: &str) -> anyhow::Result<Self> {
+        let comma = layout
+            .find(',')
+            .ok_or_else(|| anyhow!("layout {:?} has no checksum", layout))?;
+        let (checksum, body) = (&layout[..comma], &layout[comma + 1..]);
+        let checksum = u16::from_str_radix(checksum, 16)
+            .map_err(|err| anyhow!("invalid layout checksum {:?}: {}", checksum, err))?;
+        let expected = tmux_layout_checksum(body);
+        ensure!(
+            checksum == expected,
+            "layout checksum {:04x} doesn't match {:04x}",
+            checksum,
+            expected
+        );
+
+        let mut parser = Parser {
+            input: body.as_bytes(),
+            pos: 0,
+        };
+        let node = parser.parse_cell()?;
+        ensure!(
+            parser.pos == parser.input.len(),
+            "unexpected trailing data in layout at offset {}",
+            parser.pos
+        );
+        Ok(node)
+    }
+
+    /// Returns the layout in the form used by tmux, including the
+    /// checksum prefix
+    pub fn to_layout_string(&self) -> String {
+        let mut body = String::new();
+        self.write_cell(&mut body);
+        format!("{:04x},{}", tmux_layout_checksum(&body), body)
+    }
+
+    fn write_cell(&self, out: &mut String) {
+        match self {
+            Self::Pane {
+                width,
+                height,
+                x,
+                y,
+                pane_id,
+            } => {
+                out.push_str(&format!("{}x{},{},{},{}", width, height, x, y, pane_id));
+            }
+            Self::Split {
+                width,
+                height,
+                x,
+                y,
+                direction,
+                children,
+            } => {
+                out.push_str(&format!("{}x{},{},{}", width, height, x, y));
+                let (open, close) = match direction {
+                    SplitDirection::Horizontal => ('{', '}'),
+                    SplitDirection::Vertical => ('[', ']'),
+                };
+                out.push(open);
+                for (idx, child) in children.iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    child.write_cell(out);
+                }
+                out.push(close);
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> anyhow::Result<()> {
+        match self.peek() {
+            Some(b) if b == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => bail!("expected {:?} at offset {} of layout", c as char, self.pos),
+        }
+    }
+
+    fn number(&mut self) -> anyhow::Result<usize> {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        ensure!(self.pos > start, "expected a number at offset {}", start);
+        Ok(std::str::from_utf8(&self.input[start..self.pos])?.parse()?)
+    }
+
+    fn parse_cell(&mut self) -> anyhow::Result<TmuxLayout> {
+        let width = self.number()?;
+        self.expect(b'x')?;
+        let height = self.number()?;
+        self.expect(b',')?;
+        let x = self.number()?;
+        self.expect(b',')?;
+        let y = self.number()?;
+
+        let (direction, close) = match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                let pane_id = self.number()?;
+                return Ok(TmuxLayout::Pane {
+                    width,
+                    height,
+                    x,
+                    y,
+                    pane_id,
+                });
+            }
+            Some(b'{') => (SplitDirection::Horizontal, b'}'),
+            Some(b'[') => (SplitDirection::Vertical, b']'),
+            _ => bail!("unexpected end of cell at offset {} of layout", self.pos),
+        };
+        self.pos += 1;
+
+        let mut children = vec![self.parse_cell()?];
+        while self.peek() == Some(b',') {
+            self.pos += 1;
+            children.push(self.parse_cell()?);
+        }
+        self.expect(close)?;
+
+        Ok(TmuxLayout::Split {
+            width,
+            height,
+            x,
+            y,
+            direction,
+            children,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        // These are taken from real tmux control mode output
+        assert_eq!(tmux_layout_checksum("80x24,0,0,0"), 0xb25d);
+        assert_eq!(tmux_layout_checksum("120x29,0,0,0"), 0xcafd);
+    }
+
+    #[test]
+    fn round_trip() {
+        let layout = TmuxLayout::parse("b25d,80x24,0,0,0").unwrap();
+        assert_eq!(
+            layout,
+            TmuxLayout::Pane {
+                width: 80,
+                height: 24,
+                x: 0,
+                y: 0,
+                pane_id: 0
+            }
+        );
+        assert_eq!(layout.to_layout_string(), "b25d,80x24,0,0,0");
+
+        let body = "80x24,0,0{40x24,0,0,1,39x24,41,0[39x12,41,0,2,39x11,41,13,3]}";
+        let layout = format!("{:04x},{}", tmux_layout_checksum(body), body);
+        let parsed = TmuxLayout::parse(&layout).unwrap();
+        match &parsed {
+            TmuxLayout::Split {
+                direction,
+                children,
+                ..
+            } => {
+                assert_eq!(*direction, SplitDirection::Horizontal);
+                assert_eq!(children.len(), 2);
+            }
+            _ => panic!("expected a split"),
+        }
+        assert_eq!(parsed.to_layout_string(), layout);
+
+        assert!(TmuxLayout::parse("0000,80x24,0,0,0").is_err());
+        assert!(TmuxLayout::parse("80x24,0,0,0").is_err());
+    }
+}
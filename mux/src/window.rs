@@ -210,7 +210,7 @@ impl Window {
                 if tab.prune_dead_panes() {
                     invalidated = true;
                 }
-                if tab.is_dead() {
+                if tab.should_auto_close() {
                     Some(tab.tab_id())
                 } else {
                     None
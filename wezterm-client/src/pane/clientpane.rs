@@ -6,7 +6,10 @@ use async_trait::async_trait;
 use codec::*;
 use config::configuration;
 use mux::domain::DomainId;
-use mux::pane::{alloc_pane_id, CloseReason, Pane, PaneId, Pattern, SearchResult};
+use mux::pane::{
+    alloc_pane_id, CloseReason, Pane, PaneCapabilities, PaneId, Pattern, SearchResult,
+    ViewportState,
+};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::TabId;
 use mux::{Mux, MuxNotification};
@@ -37,6 +40,8 @@ pub struct ClientPane {
     mouse_grabbed: RefCell<bool>,
     ignore_next_kill: RefCell<bool>,
     user_vars: RefCell<HashMap<String, String>>,
+    viewport: ViewportState,
+    read_only: RefCell<bool>,
 }
 
 impl ClientPane {
@@ -94,6 +99,8 @@ impl ClientPane {
             mouse_grabbed: RefCell::new(false),
             ignore_next_kill: RefCell::new(false),
             user_vars: RefCell::new(HashMap::new()),
+            viewport: ViewportState::default(),
+            read_only: RefCell::new(false),
         }
     }
 
@@ -189,6 +196,18 @@ impl Pane for ClientPane {
     fn get_dimensions(&self) -> RenderableDimensions {
         self.renderable.borrow().get_dimensions()
     }
+    fn scroll_offset(&self) -> StableRowIndex {
+        self.viewport.top(&self.get_dimensions())
+    }
+    fn set_viewport_top(&self, top: StableRowIndex) {
+        self.viewport.set_top(top, &self.get_dimensions());
+    }
+    fn set_read_only(&self, read_only: bool) {
+        *self.read_only.borrow_mut() = read_only;
+    }
+    fn is_read_only(&self) -> bool {
+        *self.read_only.borrow()
+    }
     fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
         self.renderable.borrow().get_lines(lines)
     }
@@ -209,6 +228,15 @@ impl Pane for ClientPane {
         self.clipboard.borrow_mut().replace(Arc::clone(clipboard));
     }
 
+    fn capabilities(&self) -> PaneCapabilities {
+        PaneCapabilities::SEARCH
+            | PaneCapabilities::SCROLLBACK
+            | PaneCapabilities::RESIZE
+            | PaneCapabilities::KILL
+            | PaneCapabilities::CWD
+            | PaneCapabilities::VIEWPORT
+    }
+
     fn get_title(&self) -> String {
         let renderable = self.renderable.borrow();
         let inner = renderable.inner.borrow();
@@ -216,6 +244,9 @@ impl Pane for ClientPane {
     }
 
     fn send_paste(&self, text: &str) -> anyhow::Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
         let client = Arc::clone(&self.client);
         let remote_pane_id = self.remote_pane_id;
         self.renderable
@@ -322,6 +353,9 @@ impl Pane for ClientPane {
     }
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
         let input_serial;
         {
             let renderable = self.renderable.borrow();
@@ -379,6 +413,9 @@ impl Pane for ClientPane {
     }
 
     fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
         self.mouse.borrow_mut().append(event);
         if MouseState::next(Rc::clone(&self.mouse)) {
             self.renderable
@@ -51,7 +51,7 @@ impl CopyOverlay {
             window,
             delegate: Rc::clone(pane),
             start: None,
-            viewport: term_window.get_viewport(pane.pane_id()),
+            viewport: term_window.get_viewport(pane),
         };
         Rc::new(CopyOverlay {
             delegate: Rc::clone(pane),
@@ -186,7 +186,7 @@ impl QuickSelectOverlay {
         pane: &Rc<dyn Pane>,
         args: &QuickSelectArguments,
     ) -> Rc<dyn Pane> {
-        let viewport = term_window.get_viewport(pane.pane_id());
+        let viewport = term_window.get_viewport(pane);
         let dims = pane.get_dimensions();
 
         let config = term_window.config.clone();
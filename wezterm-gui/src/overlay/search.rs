@@ -57,7 +57,7 @@ impl SearchOverlay {
         pane: &Rc<dyn Pane>,
         pattern: Pattern,
     ) -> Rc<dyn Pane> {
-        let viewport = term_window.get_viewport(pane.pane_id());
+        let viewport = term_window.get_viewport(pane);
         let dims = pane.get_dimensions();
 
         let window = term_window.window.clone().unwrap();
@@ -202,8 +202,10 @@ impl Pane for SearchOverlay {
                 let mut r = self.renderer.borrow_mut();
                 let pattern = match &r.pattern {
                     Pattern::CaseSensitiveString(s) => Pattern::CaseInSensitiveString(s.clone()),
-                    Pattern::CaseInSensitiveString(s) => Pattern::Regex(s.clone()),
-                    Pattern::Regex(s) => Pattern::CaseSensitiveString(s.clone()),
+                    Pattern::CaseInSensitiveString(s) => Pattern::WholeWord(s.clone()),
+                    Pattern::WholeWord(s) => Pattern::Regex(s.clone()),
+                    Pattern::Regex(s) => Pattern::MultiLineRegex(s.clone()),
+                    Pattern::MultiLineRegex(s) => Pattern::CaseSensitiveString(s.clone()),
                 };
                 r.pattern = pattern;
                 r.update_search();
@@ -321,7 +323,9 @@ impl Pane for SearchOverlay {
                 let mode = &match renderer.pattern {
                     Pattern::CaseSensitiveString(_) => "case-sensitive",
                     Pattern::CaseInSensitiveString(_) => "ignore-case",
+                    Pattern::WholeWord(_) => "whole-word",
                     Pattern::Regex(_) => "regex",
+                    Pattern::MultiLineRegex(_) => "multiline-regex",
                 };
                 line.overlay_text_with_attribute(
                     0,
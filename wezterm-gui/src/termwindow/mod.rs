@@ -28,7 +28,7 @@ use config::{
     WindowCloseConfirmation,
 };
 use mlua::{FromLua, UserData, UserDataFields};
-use mux::pane::{CloseReason, Pane, PaneId};
+use mux::pane::{CloseReason, Pane, PaneCapabilities, PaneId};
 use mux::renderable::RenderableDimensions;
 use mux::tab::{PositionedPane, PositionedSplit, SplitDirection, Tab, TabId};
 use mux::window::WindowId as MuxWindowId;
@@ -1301,9 +1301,7 @@ impl TermWindow {
 
     fn check_for_dirty_lines_and_invalidate_selection(&mut self, pane: &Rc<dyn Pane>) {
         let dims = pane.get_dimensions();
-        let viewport = self
-            .get_viewport(pane.pane_id())
-            .unwrap_or(dims.physical_top);
+        let viewport = self.get_viewport(pane).unwrap_or(dims.physical_top);
         let visible_range = viewport..viewport + dims.viewport_rows as StableRowIndex;
         let seqno = self.selection(pane.pane_id()).seqno;
         let dirty = pane.get_changed_since(visible_range, seqno);
@@ -1843,9 +1841,7 @@ impl TermWindow {
             None => return Ok(()),
         };
         let dims = pane.get_dimensions();
-        let position = self
-            .get_viewport(pane.pane_id())
-            .unwrap_or(dims.physical_top);
+        let position = self.get_viewport(&pane).unwrap_or(dims.physical_top);
         let zone = {
             let zones = self.get_semantic_prompt_zones(&pane);
             let idx = match zones.binary_search(&position) {
@@ -1870,9 +1866,7 @@ impl TermWindow {
             None => return Ok(()),
         };
         let dims = pane.get_dimensions();
-        let position = self
-            .get_viewport(pane.pane_id())
-            .unwrap_or(dims.physical_top) as f64
+        let position = self.get_viewport(&pane).unwrap_or(dims.physical_top) as f64
             + (amount * dims.viewport_rows as f64);
         self.set_viewport(pane.pane_id(), Some(position as isize), dims);
         if let Some(win) = self.window.as_ref() {
@@ -1888,7 +1882,7 @@ impl TermWindow {
         };
         let dims = pane.get_dimensions();
         let position = self
-            .get_viewport(pane.pane_id())
+            .get_viewport(&pane)
             .unwrap_or(dims.physical_top)
             .saturating_add(amount);
         self.set_viewport(pane.pane_id(), Some(position), dims);
@@ -2393,8 +2387,22 @@ impl TermWindow {
         RefMut::map(self.pane_state(pane_id), |state| &mut state.selection)
     }
 
-    pub fn get_viewport(&self, pane_id: PaneId) -> Option<StableRowIndex> {
-        self.pane_state(pane_id).viewport
+    /// Returns the top of the viewport of the pane if it has been
+    /// scrolled back, or None if it is at the bottom of the scrollback.
+    /// Panes that track their own viewport, which the mux can also
+    /// scroll, are the source of truth for it; otherwise it is held
+    /// in the PaneState.
+    pub fn get_viewport(&self, pane: &Rc<dyn Pane>) -> Option<StableRowIndex> {
+        if pane.capabilities().contains(PaneCapabilities::VIEWPORT) {
+            let top = pane.scroll_offset();
+            if top < pane.get_dimensions().physical_top {
+                Some(top)
+            } else {
+                None
+            }
+        } else {
+            self.pane_state(pane.pane_id()).viewport
+        }
     }
 
     pub fn set_viewport(
@@ -2415,10 +2423,17 @@ impl TermWindow {
             None => None,
         };
 
-        let mut state = self.pane_state(pane_id);
-        if pos != state.viewport {
-            state.viewport = pos;
+        let prior = match Mux::get().unwrap().get_pane(pane_id) {
+            Some(pane) if pane.capabilities().contains(PaneCapabilities::VIEWPORT) => {
+                let prior = self.get_viewport(&pane);
+                pane.scroll_to(pos.unwrap_or(dims.physical_top));
+                prior
+            }
+            _ => std::mem::replace(&mut self.pane_state(pane_id).viewport, pos),
+        };
 
+        let state = self.pane_state(pane_id);
+        if pos != prior {
             // This is a bit gross.  If we add other overlays that need this information,
             // this should get extracted out into a trait
             if let Some(overlay) = state.overlay.as_ref() {
@@ -2446,7 +2461,11 @@ impl TermWindow {
     }
 
     fn scroll_to_bottom(&mut self, pane: &Rc<dyn Pane>) {
-        self.pane_state(pane.pane_id()).viewport = None;
+        if pane.capabilities().contains(PaneCapabilities::VIEWPORT) {
+            pane.scroll_to(pane.get_dimensions().physical_top);
+        } else {
+            self.pane_state(pane.pane_id()).viewport = None;
+        }
     }
 
     fn get_active_pane_no_overlay(&self) -> Option<Rc<dyn Pane>> {
@@ -2559,6 +2578,7 @@ impl TermWindow {
                 index: 0,
                 is_active: true,
                 is_zoomed: false,
+                is_peeked: false,
                 left: 0,
                 top: 0,
                 width: size.cols as _,
@@ -241,7 +241,7 @@ impl super::TermWindow {
         };
 
         let dims = pane.get_dimensions();
-        let current_viewport = self.get_viewport(pane.pane_id());
+        let current_viewport = self.get_viewport(&pane);
 
         let from_top = start_event.coords.y.saturating_sub(item.y as isize);
         let effective_thumb_top = event.coords.y.saturating_sub(from_top).max(0) as usize;
@@ -380,7 +380,7 @@ impl super::TermWindow {
     ) {
         if let WMEK::Press(MousePress::Left) = event.kind {
             let dims = pane.get_dimensions();
-            let current_viewport = self.get_viewport(pane.pane_id());
+            let current_viewport = self.get_viewport(&pane);
             // Page up
             self.set_viewport(
                 pane.pane_id(),
@@ -405,7 +405,7 @@ impl super::TermWindow {
     ) {
         if let WMEK::Press(MousePress::Left) = event.kind {
             let dims = pane.get_dimensions();
-            let current_viewport = self.get_viewport(pane.pane_id());
+            let current_viewport = self.get_viewport(&pane);
             // Page down
             self.set_viewport(
                 pane.pane_id(),
@@ -486,7 +486,7 @@ impl super::TermWindow {
                             if self.config.pane_focus_follows_mouse {
                                 let mux = Mux::get().unwrap();
                                 mux.get_active_tab_for_window(self.mux_window_id)
-                                    .map(|tab| tab.set_active_idx(pos.index));
+                                    .map(|tab| tab.auto_activate_idx(pos.index));
 
                                 pane = Rc::clone(&pos.pane);
                                 context.invalidate();
@@ -544,10 +544,8 @@ impl super::TermWindow {
         );
 
         let dims = pane.get_dimensions();
-        let stable_row = self
-            .get_viewport(pane.pane_id())
-            .unwrap_or(dims.physical_top)
-            + y as StableRowIndex;
+        let stable_row =
+            self.get_viewport(&pane).unwrap_or(dims.physical_top) + y as StableRowIndex;
 
         self.last_mouse_terminal_coords = (x, stable_row); // FIXME: per-pane
 
@@ -652,7 +650,7 @@ impl super::TermWindow {
                 // adjust viewport
                 let dims = pane.get_dimensions();
                 let position = self
-                    .get_viewport(pane.pane_id())
+                    .get_viewport(&pane)
                     .unwrap_or(dims.physical_top)
                     .saturating_sub((*amount).into());
                 self.set_viewport(pane.pane_id(), Some(position), dims);
@@ -930,7 +930,7 @@ impl super::TermWindow {
         let zone = {
             let dims = pos.pane.get_dimensions();
             let position = self
-                .get_viewport(pos.pane.pane_id())
+                .get_viewport(&pos.pane)
                 .unwrap_or(dims.physical_top);
 
             let zones = self.get_semantic_zones(&pos.pane);
@@ -962,7 +962,7 @@ impl super::TermWindow {
             self.prev_cursor.update(&cursor);
         }
 
-        let current_viewport = self.get_viewport(pos.pane.pane_id());
+        let current_viewport = self.get_viewport(&pos.pane);
         let (stable_top, lines);
         let dims = pos.pane.get_dimensions();
 
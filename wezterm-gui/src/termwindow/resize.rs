@@ -236,7 +236,9 @@ impl super::TermWindow {
         let mux = Mux::get().unwrap();
         if let Some(window) = mux.get_window(self.mux_window_id) {
             for tab in window.iter() {
-                tab.resize(size);
+                if tab.resize_would_change(size) {
+                    tab.resize(size);
+                }
             }
         };
         self.update_title();
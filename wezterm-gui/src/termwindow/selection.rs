@@ -122,9 +122,7 @@ impl super::TermWindow {
         // a smaller gap.
         const VERTICAL_GAP: isize = 1;
         let dims = pane.get_dimensions();
-        let top = self
-            .get_viewport(pane.pane_id())
-            .unwrap_or(dims.physical_top);
+        let top = self.get_viewport(pane).unwrap_or(dims.physical_top);
         let vertical_gap = if dims.physical_top <= VERTICAL_GAP {
             1
         } else {